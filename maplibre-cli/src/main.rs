@@ -0,0 +1,37 @@
+//! Command line tools for maplibre-rs.
+
+use clap::{Parser, Subcommand};
+
+mod render;
+mod render_tests;
+
+#[derive(Parser)]
+#[clap(name = "maplibre-cli", version, about)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Renders a static map to a PNG image without opening a window
+    Render(render::RenderArgs),
+    /// Renders the fixtures of the render tests and compares them with the expected images
+    RenderTests(render_tests::RenderTestsArgs),
+}
+
+fn main() {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("warn"));
+
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Render(args) => render::run(args),
+        Command::RenderTests(args) => render_tests::run(args),
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,314 @@
+//! Renders static maps offscreen.
+
+use clap::Args;
+use maplibre::coords::{LngLat, Zoom};
+use maplibre::headless::{HeadlessMapWindowConfig, RgbaImage};
+use maplibre::io::directory_source_client::DirectorySourceClient;
+use maplibre::io::mbtiles_source_client::MbtilesSourceClient;
+use maplibre::io::settings::{IoSettings, PrefetchSettings};
+use maplibre::io::source_client::SourceClient;
+use maplibre::map_state::CameraOptions;
+use maplibre::platform::http_client::ReqwestHttpClient;
+use maplibre::platform::run_multithreaded;
+use maplibre::platform::schedule_method::TokioScheduleMethod;
+use maplibre::render::settings::RenderSettings;
+use maplibre::style::source::Source;
+use maplibre::style::Style;
+use maplibre::window::WindowSize;
+use maplibre::MapBuilder;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Args)]
+pub struct RenderArgs {
+    /// Path to the style JSON
+    #[clap(long)]
+    style: PathBuf,
+    /// Local tile source. Either an MBTiles file or a directory with tiles stored as
+    /// `{z}/{x}/{y}.pbf`. Defaults to the first vector source of the style with an `mbtiles://`
+    /// or `file://` URL.
+    #[clap(long)]
+    source: Option<PathBuf>,
+    /// Center of the map as `lon,lat`
+    #[clap(long, value_parser = parse_center, default_value = "0,0")]
+    center: LngLat,
+    #[clap(long, default_value_t = 0.0)]
+    zoom: f64,
+    /// Bearing in degrees
+    #[clap(long, default_value_t = 0.0)]
+    bearing: f64,
+    /// Pitch in degrees
+    #[clap(long, default_value_t = 0.0)]
+    pitch: f64,
+    /// Size of the image in logical pixels as `WxH`
+    #[clap(long, value_parser = parse_size, default_value = "512x512")]
+    size: (u32, u32),
+    /// Pixel ratio. The image has `ratio` times as many pixels in each dimension but shows the
+    /// same area.
+    #[clap(long, default_value_t = 1.0)]
+    ratio: f64,
+    /// Maximum number of frames which are rendered while waiting for tiles
+    #[clap(long, default_value_t = 1000)]
+    max_frames: u64,
+    /// Render with a software adapter instead of the GPU
+    #[clap(long)]
+    software: bool,
+    /// Path of the PNG image
+    #[clap(short, long)]
+    output: PathBuf,
+}
+
+fn parse_center(value: &str) -> Result<LngLat, String> {
+    let (lng, lat) = value
+        .split_once(',')
+        .ok_or_else(|| format!("expected lon,lat but got {}", value))?;
+    let lng = lng.trim().parse::<f64>().map_err(|e| e.to_string())?;
+    let lat = lat.trim().parse::<f64>().map_err(|e| e.to_string())?;
+    Ok(LngLat::new(lng, lat))
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("expected WxH but got {}", value))?;
+    let width = width.parse::<u32>().map_err(|e| e.to_string())?;
+    let height = height.parse::<u32>().map_err(|e| e.to_string())?;
+    if width == 0 || height == 0 {
+        return Err("size must not be zero".to_string());
+    }
+    Ok((width, height))
+}
+
+fn load_style(path: &Path) -> Result<Style, String> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read style {:?}: {}", path, e))?;
+    let json = serde_json::from_str(&json)
+        .map_err(|e| format!("failed to parse style {:?}: {}", path, e))?;
+    style_from_json(json).map_err(|e| format!("failed to parse style {:?}: {}", path, e))
+}
+
+/// Parses a style and prepares its layers for rendering.
+pub(crate) fn style_from_json(json: serde_json::Value) -> Result<Style, String> {
+    let mut style: Style = serde_json::from_value(json).map_err(|e| e.to_string())?;
+
+    // Only layers which reference a source layer can be rendered
+    style.layers.retain(|layer| {
+        if layer.source_layer.is_none() {
+            log::warn!("skipping layer {} without source layer", layer.id);
+        }
+        layer.source_layer.is_some()
+    });
+
+    // The draw order is given by the order of the layers
+    for (index, layer) in style.layers.iter_mut().enumerate() {
+        layer.index = index as u32;
+    }
+
+    Ok(style)
+}
+
+/// Returns the local path of a tile URL like `mbtiles://tiles.mbtiles` or
+/// `file://tiles/{z}/{x}/{y}.pbf`. Relative paths are resolved against `base_dir`.
+pub(crate) fn local_source_path(url: &str, base_dir: &Path) -> Option<PathBuf> {
+    let path = url
+        .strip_prefix("mbtiles://")
+        .or_else(|| url.strip_prefix("file://"))?;
+    // Remove the tile template
+    let path = match path.find("/{z}") {
+        Some(index) => &path[..index],
+        None => path,
+    };
+    Some(base_dir.join(path))
+}
+
+/// Opens the local tile source of the style. Relative paths are resolved against `base_dir`.
+pub(crate) async fn style_source_client(
+    style: &Style,
+    base_dir: &Path,
+) -> Result<SourceClient<ReqwestHttpClient>, String> {
+    let (source, url, path) = style
+        .sources
+        .values()
+        .filter_map(|source| match source {
+            Source::Vector(source) => Some((source, source.tiles.as_ref()?)),
+            Source::Raster(_) => None,
+        })
+        .flat_map(|(source, tiles)| tiles.iter().map(move |url| (source, url)))
+        .find_map(|(source, url)| Some((source, url, local_source_path(url, base_dir)?)))
+        .ok_or("the style has no local vector source")?;
+
+    // Tile templates are read as given, the path of MBTiles files and directories is opened
+    if url.starts_with("file://") && url.contains('{') {
+        let client = DirectorySourceClient::from_url(url)
+            .map_err(|e| format!("failed to read tiles from {}: {:?}", url, e))?
+            .with_base_dir(base_dir)
+            .with_scheme(source.scheme.clone().unwrap_or_default());
+        return Ok(SourceClient::Directory(client));
+    }
+    source_client(&path).await
+}
+
+async fn source_client(path: &Path) -> Result<SourceClient<ReqwestHttpClient>, String> {
+    if path.extension() == Some(OsStr::new("mbtiles")) {
+        MbtilesSourceClient::open(path)
+            .map(SourceClient::Mbtiles)
+            .map_err(|e| format!("failed to open {:?}: {:?}", path, e))
+    } else if path.is_dir() {
+        DirectorySourceClient::open(path)
+            .await
+            .map(SourceClient::Directory)
+            .map_err(|e| format!("failed to open {:?}: {:?}", path, e))
+    } else {
+        Err(format!(
+            "{:?} is neither an MBTiles file nor a directory",
+            path
+        ))
+    }
+}
+
+/// Describes what is rendered by [`render_snapshot`].
+pub(crate) struct Snapshot {
+    pub style: Style,
+    pub source_client: SourceClient<ReqwestHttpClient>,
+    pub center: LngLat,
+    pub zoom: f64,
+    pub bearing: f64,
+    pub pitch: f64,
+    /// Size in logical pixels
+    pub size: (u32, u32),
+    pub ratio: f64,
+}
+
+/// Renders the snapshot offscreen. Waits for at most `max_frames` frames until all tiles are
+/// loaded.
+pub(crate) async fn render_snapshot(
+    snapshot: Snapshot,
+    software: bool,
+    max_frames: u64,
+) -> Result<RgbaImage, String> {
+    if snapshot.ratio <= 0.0 {
+        return Err("ratio must be positive".to_string());
+    }
+    let (width, height) = snapshot.size;
+    let size = WindowSize::new(
+        (width as f64 * snapshot.ratio).round() as u32,
+        (height as f64 * snapshot.ratio).round() as u32,
+    )
+    .ok_or("size must not be zero")?;
+
+    // Showing the same area with more pixels is equivalent to zooming in
+    let camera = CameraOptions::new()
+        .with_center(snapshot.center)
+        .with_zoom(Zoom::new(snapshot.zoom + snapshot.ratio.log2()))
+        .with_bearing(snapshot.bearing)
+        .with_pitch(snapshot.pitch);
+
+    let mut map = MapBuilder::new()
+        .with_map_window_config(
+            HeadlessMapWindowConfig::new(size).with_force_fallback_adapter(software),
+        )
+        .with_http_client(ReqwestHttpClient::new(None))
+        .with_source_client(snapshot.source_client)
+        .with_schedule_method(TokioScheduleMethod::new())
+        .with_style(snapshot.style)
+        .with_initial_camera(camera)
+        // Snapshots show the loaded tiles without fading them in
+        .with_render_settings(RenderSettings {
+            fade_duration: Duration::ZERO,
+            ..RenderSettings::default()
+        })
+        // Only the tiles in view are loaded
+        .with_io_settings(IoSettings {
+            prefetch: PrefetchSettings {
+                enabled: false,
+                ..PrefetchSettings::default()
+            },
+            ..IoSettings::default()
+        })
+        .build()
+        .initialize_headless()
+        .await
+        .map_err(|e| format!("failed to initialize rendering: {:?}", e))?;
+
+    let image = map
+        .render_snapshot(max_frames)
+        .await
+        .map_err(|e| format!("failed to render: {:?}", e))?;
+
+    if !map.map_state().is_idle() {
+        log::warn!("not all tiles were loaded after {} frames", max_frames);
+    }
+
+    Ok(image)
+}
+
+pub fn run(args: RenderArgs) -> Result<(), String> {
+    let style = load_style(&args.style)?;
+
+    run_multithreaded(async {
+        let source_client = match &args.source {
+            Some(source) => source_client(source).await?,
+            None => style_source_client(
+                &style,
+                args.style.parent().unwrap_or_else(|| Path::new(".")),
+            )
+            .await
+            .map_err(|e| format!("{}, specify a source with --source", e))?,
+        };
+
+        let snapshot = Snapshot {
+            style,
+            source_client,
+            center: args.center,
+            zoom: args.zoom,
+            bearing: args.bearing,
+            pitch: args.pitch,
+            size: args.size,
+            ratio: args.ratio,
+        };
+
+        let image = render_snapshot(snapshot, args.software, args.max_frames).await?;
+        image
+            .save_png(&args.output)
+            .map_err(|e| format!("failed to write {:?}: {}", args.output, e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{local_source_path, parse_center, parse_size};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_parse_arguments() {
+        let center = parse_center("11.58,48.14").unwrap();
+        assert_eq!((center.lng, center.lat), (11.58, 48.14));
+        assert!(parse_center("11.58").is_err());
+
+        assert_eq!(parse_size("800x600"), Ok((800, 600)));
+        assert!(parse_size("800x0").is_err());
+        assert!(parse_size("800").is_err());
+    }
+
+    #[test]
+    fn test_local_source_path() {
+        let base_dir = Path::new("/data");
+        assert_eq!(
+            local_source_path("mbtiles://tiles.mbtiles", base_dir),
+            Some(PathBuf::from("/data/tiles.mbtiles"))
+        );
+        assert_eq!(
+            local_source_path("file://tiles/{z}/{x}/{y}.pbf", base_dir),
+            Some(PathBuf::from("/data/tiles"))
+        );
+        assert_eq!(
+            local_source_path("file:///tiles/{z}/{x}/{y}.pbf", base_dir),
+            Some(PathBuf::from("/tiles"))
+        );
+        assert_eq!(
+            local_source_path("https://example.com/{z}/{x}/{y}.pbf", base_dir),
+            None
+        );
+    }
+}
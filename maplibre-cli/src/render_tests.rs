@@ -0,0 +1,472 @@
+//! Runs render tests which follow the layout of the render tests of MapLibre GL.
+//!
+//! Each fixture is a directory which contains a `style.json` and an `expected.png`. The size of
+//! the image and the tolerance are configured in the `metadata.test` object of the style. The
+//! camera is configured by the `center`, `zoom`, `bearing` and `pitch` of the style. Tiles are
+//! read from `mbtiles://` or `file://` sources which are relative to the fixture directory.
+//!
+//! The rendered image is written to `actual.png` and the differences to `diff.png`. Fixtures which
+//! use style properties that are not supported by maplibre-rs are skipped.
+
+use crate::render::{render_snapshot, style_from_json, style_source_client, Snapshot};
+use clap::Args;
+use maplibre::coords::LngLat;
+use maplibre::headless::RgbaImage;
+use maplibre::platform::run_multithreaded;
+use serde_json::Value;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_SIZE: u32 = 512;
+/// The fraction of pixels which may differ, the same default as in MapLibre GL.
+const DEFAULT_ALLOWED: f64 = 0.00015;
+
+const SUPPORTED_ROOT_PROPERTIES: &[&str] = &[
+    "version", "name", "metadata", "sources", "layers", "center", "zoom", "bearing", "pitch",
+];
+/// Properties which do not change the rendering of supported layers.
+const IGNORED_ROOT_PROPERTIES: &[&str] = &["sprite", "glyphs", "transition"];
+const SUPPORTED_TEST_PROPERTIES: &[&str] =
+    &["width", "height", "pixelRatio", "allowed", "description"];
+const SUPPORTED_SOURCE_PROPERTIES: &[&str] = &["type", "tiles", "scheme", "attribution"];
+const SUPPORTED_LAYER_PROPERTIES: &[&str] =
+    &["id", "type", "source", "source-layer", "paint", "metadata"];
+const SUPPORTED_LAYER_TYPES: &[&str] = &["fill", "line"];
+const SUPPORTED_PAINT_PROPERTIES: &[&str] = &["fill-color", "line-color"];
+
+#[derive(Args)]
+pub struct RenderTestsArgs {
+    /// Directory which contains the fixtures
+    #[clap(default_value = "test-data/render-tests")]
+    fixtures: PathBuf,
+    /// Only run fixtures whose name contains this string
+    #[clap(long)]
+    filter: Option<String>,
+    /// Fraction of pixels which may differ. Overrides `allowed` of the fixtures
+    #[clap(long)]
+    allowed: Option<f64>,
+    /// Difference of a pixel between 0 and 1 above which the pixel counts as different
+    #[clap(long, default_value_t = 0.1)]
+    threshold: f64,
+    /// Write the rendered images to `expected.png` instead of comparing them
+    #[clap(long)]
+    update: bool,
+    /// Maximum number of frames which are rendered while waiting for tiles
+    #[clap(long, default_value_t = 1000)]
+    max_frames: u64,
+    /// Render with a software adapter instead of the GPU
+    #[clap(long)]
+    software: bool,
+}
+
+/// The outcome of a single fixture.
+enum Outcome {
+    /// The fraction of different pixels is within the allowed tolerance.
+    Passed {
+        difference: f64,
+    },
+    Failed {
+        difference: f64,
+        allowed: f64,
+    },
+    /// The fixture uses unsupported style properties.
+    Skipped {
+        unsupported: Vec<String>,
+    },
+    /// The expected image has been written.
+    Updated,
+    Errored(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Passed { difference } => write!(f, "passed ({:.5})", difference),
+            Outcome::Failed {
+                difference,
+                allowed,
+            } => write!(f, "failed ({:.5} > {:.5})", difference, allowed),
+            Outcome::Skipped { unsupported } => {
+                write!(f, "skipped (unsupported: {})", unsupported.join(", "))
+            }
+            Outcome::Updated => write!(f, "updated"),
+            Outcome::Errored(e) => write!(f, "errored ({})", e),
+        }
+    }
+}
+
+/// The options in the `metadata.test` object of a fixture.
+struct TestOptions {
+    width: u32,
+    height: u32,
+    pixel_ratio: f64,
+    allowed: f64,
+}
+
+impl TestOptions {
+    fn from_json(test: Option<&Value>) -> Self {
+        let get = |key: &str| test.and_then(|test| test.get(key)).and_then(Value::as_f64);
+        Self {
+            width: get("width").map_or(DEFAULT_SIZE, |width| width as u32),
+            height: get("height").map_or(DEFAULT_SIZE, |height| height as u32),
+            pixel_ratio: get("pixelRatio").unwrap_or(1.0),
+            allowed: get("allowed").unwrap_or(DEFAULT_ALLOWED),
+        }
+    }
+}
+
+/// Returns the paths of the style properties which are not supported.
+fn unsupported_properties(style: &Value) -> Vec<String> {
+    let mut unsupported = Vec::new();
+
+    let unknown_keys = |value: &Value, supported: &[&str], prefix: &str| -> Vec<String> {
+        value
+            .as_object()
+            .map(|object| {
+                object
+                    .keys()
+                    .filter(|key| !supported.contains(&key.as_str()))
+                    .map(|key| format!("{}{}", prefix, key))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let mut supported_root = SUPPORTED_ROOT_PROPERTIES.to_vec();
+    supported_root.extend_from_slice(IGNORED_ROOT_PROPERTIES);
+    unsupported.extend(unknown_keys(style, &supported_root, ""));
+
+    if let Some(test) = style.pointer("/metadata/test") {
+        unsupported.extend(unknown_keys(
+            test,
+            SUPPORTED_TEST_PROPERTIES,
+            "metadata.test.",
+        ));
+    }
+
+    if let Some(sources) = style.get("sources").and_then(Value::as_object) {
+        for (id, source) in sources {
+            let prefix = format!("sources.{}.", id);
+            unsupported.extend(unknown_keys(source, SUPPORTED_SOURCE_PROPERTIES, &prefix));
+
+            if source.get("type").and_then(Value::as_str) != Some("vector") {
+                unsupported.push(format!("{}type", prefix));
+            }
+
+            let local = match source.get("tiles").and_then(Value::as_array) {
+                Some(tiles) => tiles
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .all(|url| url.starts_with("mbtiles://") || url.starts_with("file://")),
+                None => false,
+            };
+            if !local {
+                unsupported.push(format!("{}tiles", prefix));
+            }
+        }
+    }
+
+    if let Some(layers) = style.get("layers").and_then(Value::as_array) {
+        for layer in layers {
+            let id = layer.get("id").and_then(Value::as_str).unwrap_or_default();
+            let prefix = format!("layers.{}.", id);
+            unsupported.extend(unknown_keys(layer, SUPPORTED_LAYER_PROPERTIES, &prefix));
+
+            let typ = layer
+                .get("type")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            if !SUPPORTED_LAYER_TYPES.contains(&typ) {
+                unsupported.push(format!("{}type={}", prefix, typ));
+            }
+
+            if let Some(paint) = layer.get("paint") {
+                let prefix = format!("{}paint.", prefix);
+                unsupported.extend(unknown_keys(paint, SUPPORTED_PAINT_PROPERTIES, &prefix));
+
+                // Expressions and functions are not supported
+                if let Some(paint) = paint.as_object() {
+                    unsupported.extend(
+                        paint
+                            .iter()
+                            .filter(|(key, value)| {
+                                SUPPORTED_PAINT_PROPERTIES.contains(&key.as_str())
+                                    && !value.is_string()
+                            })
+                            .map(|(key, _)| format!("{}{} (expression)", prefix, key)),
+                    );
+                }
+            }
+        }
+    }
+
+    unsupported.sort();
+    unsupported.dedup();
+    unsupported
+}
+
+/// The result of comparing two images.
+struct Comparison {
+    /// The fraction of pixels which differ.
+    difference: f64,
+    /// Different pixels are red, the other pixels show a faded version of the expected image.
+    diff: RgbaImage,
+}
+
+/// Compares the images pixel by pixel. A pixel counts as different if one of its channels
+/// differs by more than `threshold`, which is between 0 and 1.
+fn compare(actual: &RgbaImage, expected: &RgbaImage, threshold: f64) -> Result<Comparison, String> {
+    if (actual.width(), actual.height()) != (expected.width(), expected.height()) {
+        return Err(format!(
+            "expected an image of {}x{} but got {}x{}",
+            expected.width(),
+            expected.height(),
+            actual.width(),
+            actual.height()
+        ));
+    }
+
+    let mut different_pixels = 0;
+    let mut diff = Vec::with_capacity(actual.data().len());
+    for (actual, expected) in actual.data().chunks(4).zip(expected.data().chunks(4)) {
+        let max_difference = actual
+            .iter()
+            .zip(expected)
+            .map(|(a, b)| (*a as i16 - *b as i16).unsigned_abs())
+            .max()
+            .unwrap_or(0);
+
+        if max_difference as f64 / 255.0 > threshold {
+            different_pixels += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let luma = 0.299 * expected[0] as f64
+                + 0.587 * expected[1] as f64
+                + 0.114 * expected[2] as f64;
+            let faded = (255.0 - (255.0 - luma) * 0.1) as u8;
+            diff.extend_from_slice(&[faded, faded, faded, 255]);
+        }
+    }
+
+    let total_pixels = actual.width() as f64 * actual.height() as f64;
+    Ok(Comparison {
+        difference: different_pixels as f64 / total_pixels,
+        diff: RgbaImage::new(actual.width(), actual.height(), diff),
+    })
+}
+
+/// Returns the directories below `path` which contain a `style.json`.
+fn find_fixtures(path: &Path, fixtures: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if path.join("style.json").is_file() {
+        fixtures.push(path.to_path_buf());
+    }
+
+    let mut entries = std::fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    entries.sort();
+    for entry in entries {
+        find_fixtures(&entry, fixtures)?;
+    }
+    Ok(())
+}
+
+async fn run_fixture(fixture: &Path, args: &RenderTestsArgs) -> Result<Outcome, String> {
+    let json = std::fs::read_to_string(fixture.join("style.json")).map_err(|e| e.to_string())?;
+    let mut json: Value = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+
+    let unsupported = unsupported_properties(&json);
+    if !unsupported.is_empty() {
+        return Ok(Outcome::Skipped { unsupported });
+    }
+
+    let options = TestOptions::from_json(json.pointer("/metadata/test"));
+    let get = |key: &str| json.get(key).and_then(Value::as_f64).unwrap_or(0.0);
+    let center = json
+        .get("center")
+        .and_then(Value::as_array)
+        .map(|center| center.iter().filter_map(Value::as_f64).collect::<Vec<_>>())
+        .filter(|center| center.len() == 2)
+        .map_or(LngLat::new(0.0, 0.0), |center| {
+            LngLat::new(center[0], center[1])
+        });
+    let (zoom, bearing, pitch) = (get("zoom"), get("bearing"), get("pitch"));
+
+    // The metadata of the test is not a valid style metadata
+    if let Some(style) = json.as_object_mut() {
+        style.remove("metadata");
+    }
+    let style = style_from_json(json)?;
+    let source_client = style_source_client(&style, fixture).await?;
+
+    let snapshot = Snapshot {
+        style,
+        source_client,
+        center,
+        zoom,
+        bearing,
+        pitch,
+        size: (options.width, options.height),
+        ratio: options.pixel_ratio,
+    };
+    let actual = render_snapshot(snapshot, args.software, args.max_frames).await?;
+
+    if args.update {
+        actual
+            .save_png(fixture.join("expected.png"))
+            .map_err(|e| e.to_string())?;
+        return Ok(Outcome::Updated);
+    }
+
+    actual
+        .save_png(fixture.join("actual.png"))
+        .map_err(|e| e.to_string())?;
+
+    let expected = RgbaImage::load_png(fixture.join("expected.png"))
+        .map_err(|e| format!("failed to read expected.png: {}", e))?;
+    let comparison = compare(&actual, &expected, args.threshold)?;
+    comparison
+        .diff
+        .save_png(fixture.join("diff.png"))
+        .map_err(|e| e.to_string())?;
+
+    let allowed = args.allowed.unwrap_or(options.allowed);
+    if comparison.difference <= allowed {
+        Ok(Outcome::Passed {
+            difference: comparison.difference,
+        })
+    } else {
+        Ok(Outcome::Failed {
+            difference: comparison.difference,
+            allowed,
+        })
+    }
+}
+
+pub fn run(args: RenderTestsArgs) -> Result<(), String> {
+    let mut fixtures = Vec::new();
+    find_fixtures(&args.fixtures, &mut fixtures)
+        .map_err(|e| format!("failed to read fixtures in {:?}: {}", args.fixtures, e))?;
+
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+
+    run_multithreaded(async {
+        for fixture in &fixtures {
+            let name = fixture
+                .strip_prefix(&args.fixtures)
+                .unwrap_or(fixture)
+                .display()
+                .to_string();
+            if let Some(filter) = &args.filter {
+                if !name.contains(filter.as_str()) {
+                    continue;
+                }
+            }
+
+            let outcome = run_fixture(fixture, &args)
+                .await
+                .unwrap_or_else(Outcome::Errored);
+            match outcome {
+                Outcome::Passed { .. } | Outcome::Updated => passed += 1,
+                Outcome::Failed { .. } | Outcome::Errored(_) => failed += 1,
+                Outcome::Skipped { .. } => skipped += 1,
+            }
+            println!("{}: {}", name, outcome);
+        }
+    });
+
+    println!("{} passed, {} failed, {} skipped", passed, failed, skipped);
+
+    if failed > 0 {
+        Err(format!("{} render tests failed", failed))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare, find_fixtures, unsupported_properties};
+    use maplibre::headless::RgbaImage;
+    use serde_json::{json, Value};
+    use std::path::Path;
+
+    #[test]
+    fn test_unsupported_properties() {
+        let style = json!({
+            "version": 8,
+            "metadata": {"test": {"width": 64, "height": 64, "operations": []}},
+            "sources": {
+                "local": {"type": "vector", "tiles": ["file://tiles/{z}/{x}/{y}.pbf"]},
+                "remote": {"type": "vector", "url": "https://example.com/tiles.json"}
+            },
+            "glyphs": "https://example.com/{fontstack}/{range}.pbf",
+            "layers": [
+                {"id": "water", "type": "fill", "source": "local", "source-layer": "water",
+                    "paint": {"fill-color": "#0000ff"}},
+                {"id": "roads", "type": "line", "source": "local", "source-layer": "roads",
+                    "filter": ["==", "class", "motorway"],
+                    "paint": {"line-color": ["get", "color"], "line-width": 2}},
+                {"id": "labels", "type": "symbol", "source": "local", "source-layer": "labels"}
+            ]
+        });
+
+        assert_eq!(
+            unsupported_properties(&style),
+            vec![
+                "layers.labels.type=symbol",
+                "layers.roads.filter",
+                "layers.roads.paint.line-color (expression)",
+                "layers.roads.paint.line-width",
+                "metadata.test.operations",
+                "sources.remote.tiles",
+                "sources.remote.url",
+            ]
+        );
+
+        let style = json!({
+            "version": 8,
+            "sources": {"local": {"type": "vector", "tiles": ["mbtiles://tiles.mbtiles"]}},
+            "layers": [{"id": "water", "type": "fill", "source": "local",
+                "source-layer": "water", "paint": {"fill-color": "#0000ff"}}]
+        });
+        assert!(unsupported_properties(&style).is_empty());
+    }
+
+    #[test]
+    fn test_compare() {
+        let expected = RgbaImage::new(2, 1, vec![0, 0, 0, 255, 255, 255, 255, 255]);
+        let actual = RgbaImage::new(2, 1, vec![10, 0, 0, 255, 0, 255, 255, 255]);
+
+        let comparison = compare(&actual, &expected, 0.1).unwrap();
+        assert_eq!(comparison.difference, 0.5);
+        assert_eq!(comparison.diff.pixel(0, 0), [229, 229, 229, 255]);
+        assert_eq!(comparison.diff.pixel(1, 0), [255, 0, 0, 255]);
+
+        let comparison = compare(&actual, &expected, 1.0).unwrap();
+        assert_eq!(comparison.difference, 0.0);
+
+        assert!(compare(&RgbaImage::new(1, 1, vec![0; 4]), &expected, 0.1).is_err());
+    }
+
+    #[test]
+    fn test_fixtures() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../test-data/render-tests");
+        let mut fixtures = Vec::new();
+        find_fixtures(&root, &mut fixtures).unwrap();
+        assert_eq!(fixtures.len(), 3);
+
+        let mut skipped = Vec::new();
+        for fixture in &fixtures {
+            let json = std::fs::read_to_string(fixture.join("style.json")).unwrap();
+            let style: Value = serde_json::from_str(&json).unwrap();
+            if unsupported_properties(&style).is_empty() {
+                assert!(fixture.join("expected.png").is_file());
+            } else {
+                skipped.push(fixture.strip_prefix(&root).unwrap().to_path_buf());
+            }
+        }
+        assert_eq!(skipped, vec![Path::new("line-width").join("literal")]);
+    }
+}
@@ -4,11 +4,12 @@ use std::time::Duration;
 
 use cgmath::Vector2;
 
-use winit::event::{DeviceEvent, KeyboardInput, TouchPhase, WindowEvent};
+use winit::event::{DeviceEvent, ElementState, KeyboardInput, TouchPhase, WindowEvent};
 
 use crate::input::pan_handler::PanHandler;
 use crate::input::pinch_handler::PinchHandler;
 use crate::input::query_handler::QueryHandler;
+use crate::input::rotate_handler::RotateHandler;
 use crate::input::shift_handler::ShiftHandler;
 use crate::input::tilt_handler::TiltHandler;
 use crate::input::zoom_handler::ZoomHandler;
@@ -17,6 +18,7 @@ use maplibre::map_state::ViewState;
 mod pan_handler;
 mod pinch_handler;
 mod query_handler;
+mod rotate_handler;
 mod shift_handler;
 mod tilt_handler;
 mod zoom_handler;
@@ -28,6 +30,11 @@ pub struct InputController {
     tilt_handler: TiltHandler,
     shift_handler: ShiftHandler,
     query_handler: QueryHandler,
+    rotate_handler: RotateHandler,
+
+    /// Set if the user interacted with the map since the last update. Running camera
+    /// animations are cancelled in this case.
+    interrupted: bool,
 }
 
 impl InputController {
@@ -49,6 +56,8 @@ impl InputController {
             tilt_handler: TiltHandler::new(speed, sensitivity),
             shift_handler: ShiftHandler::new(speed, sensitivity),
             query_handler: QueryHandler::new(),
+            rotate_handler: RotateHandler::new(0.8),
+            interrupted: false,
         }
     }
 
@@ -68,6 +77,8 @@ impl InputController {
                     .process_window_position(&Vector2::from(position), false);
                 self.zoom_handler
                     .process_window_position(&Vector2::from(position), false);
+                self.rotate_handler
+                    .process_window_position(&Vector2::from(position), false);
                 true
             }
             WindowEvent::KeyboardInput {
@@ -79,9 +90,14 @@ impl InputController {
                     },
                 ..
             } => {
+                self.interrupted = true;
                 if !self.shift_handler.process_key_press(*key, *state) {
                     if !self.tilt_handler.process_key_press(*key, *state) {
-                        self.zoom_handler.process_key_press(*key, *state)
+                        if !self.zoom_handler.process_key_press(*key, *state) {
+                            self.rotate_handler.process_key_press(*key, *state)
+                        } else {
+                            false
+                        }
                     } else {
                         false
                     }
@@ -89,35 +105,45 @@ impl InputController {
                     false
                 }
             }
-            WindowEvent::Touch(touch) => match touch.phase {
-                TouchPhase::Started => {
-                    self.pan_handler.process_touch_start();
-                    self.query_handler.process_touch_start();
-                    true
-                }
-                TouchPhase::Ended => {
-                    self.pan_handler.process_touch_end();
-                    self.query_handler.process_touch_end();
-                    true
-                }
-                TouchPhase::Moved => {
-                    let position: (f64, f64) = touch.location.to_owned().into();
-                    self.pan_handler
-                        .process_window_position(&Vector2::from(position), true);
-                    self.query_handler
-                        .process_window_position(&Vector2::from(position), true);
-                    self.zoom_handler
-                        .process_window_position(&Vector2::from(position), true);
-                    true
+            WindowEvent::Touch(touch) => {
+                let position: (f64, f64) = touch.location.to_owned().into();
+                self.rotate_handler
+                    .process_touch(touch.id, touch.phase, &Vector2::from(position));
+                match touch.phase {
+                    TouchPhase::Started => {
+                        self.interrupted = true;
+                        self.pan_handler.process_touch_start();
+                        self.query_handler.process_touch_start();
+                        true
+                    }
+                    TouchPhase::Ended => {
+                        self.pan_handler.process_touch_end();
+                        self.query_handler.process_touch_end();
+                        true
+                    }
+                    TouchPhase::Moved => {
+                        self.pan_handler
+                            .process_window_position(&Vector2::from(position), true);
+                        self.query_handler
+                            .process_window_position(&Vector2::from(position), true);
+                        self.zoom_handler
+                            .process_window_position(&Vector2::from(position), true);
+                        true
+                    }
+                    TouchPhase::Cancelled => false,
                 }
-                TouchPhase::Cancelled => false,
-            },
+            }
             WindowEvent::MouseWheel { delta, .. } => {
+                self.interrupted = true;
                 self.shift_handler.process_scroll(delta);
                 self.zoom_handler.process_scroll(delta);
                 true
             }
             WindowEvent::MouseInput { button, state, .. } => {
+                if *state == ElementState::Pressed {
+                    self.interrupted = true;
+                }
+                self.rotate_handler.process_mouse_key_press(button, state);
                 self.pan_handler.process_mouse_key_press(button, state);
                 self.query_handler.process_mouse_key_press(button, state)
             }
@@ -132,11 +158,17 @@ pub trait UpdateState {
 
 impl UpdateState for InputController {
     fn update_state(&mut self, state: &mut ViewState, dt: Duration) {
+        if self.interrupted {
+            state.cancel_animation();
+            self.interrupted = false;
+        }
+
         self.pan_handler.update_state(state, dt);
         self.pinch_handler.update_state(state, dt);
         self.zoom_handler.update_state(state, dt);
         self.tilt_handler.update_state(state, dt);
         self.shift_handler.update_state(state, dt);
         self.query_handler.update_state(state, dt);
+        self.rotate_handler.update_state(state, dt);
     }
 }
@@ -0,0 +1,140 @@
+use super::UpdateState;
+
+use maplibre::animation::AnimationOptions;
+use maplibre::map_state::ViewState;
+
+use cgmath::Vector2;
+
+use std::collections::HashMap;
+use std::time::Duration;
+use winit::event::{ElementState, MouseButton, TouchPhase};
+
+/// Rotates the map by dragging with the right mouse button or by rotating two fingers. Pressing
+/// `N` rotates the map back to north.
+pub struct RotateHandler {
+    window_position: Option<Vector2<f64>>,
+    is_rotating: bool,
+
+    /// The currently active touches by their id.
+    touches: HashMap<u64, Vector2<f64>>,
+    /// The angle in degrees of the line between two touches.
+    last_touch_angle: Option<f64>,
+
+    bearing_delta: f64,
+    /// The window position around which the map is rotated. If `None`, then the map is rotated
+    /// around the center of the window.
+    anchor: Option<Vector2<f64>>,
+    reset_north: bool,
+
+    /// Degrees of rotation per pixel the mouse moved.
+    sensitivity: f64,
+}
+
+impl UpdateState for RotateHandler {
+    fn update_state(&mut self, state: &mut ViewState, _dt: Duration) {
+        if self.reset_north {
+            state.reset_north(Some(&AnimationOptions::new()));
+            self.reset_north = false;
+        }
+
+        if self.bearing_delta != 0.0 {
+            match &self.anchor {
+                Some(anchor) => state.rotate_around(self.bearing_delta, anchor),
+                None => state.set_bearing(state.bearing() + self.bearing_delta),
+            }
+            self.bearing_delta = 0.0;
+        }
+    }
+}
+
+impl RotateHandler {
+    pub fn new(sensitivity: f64) -> Self {
+        Self {
+            window_position: None,
+            is_rotating: false,
+            touches: HashMap::new(),
+            last_touch_angle: None,
+            bearing_delta: 0.0,
+            anchor: None,
+            reset_north: false,
+            sensitivity,
+        }
+    }
+
+    pub fn process_window_position(&mut self, window_position: &Vector2<f64>, touch: bool) -> bool {
+        if self.is_rotating && !touch {
+            if let Some(last_position) = self.window_position {
+                self.bearing_delta += (window_position.x - last_position.x) * self.sensitivity;
+                self.anchor = None;
+            }
+        }
+
+        self.window_position = Some(*window_position);
+        true
+    }
+
+    pub fn process_mouse_key_press(&mut self, key: &MouseButton, state: &ElementState) -> bool {
+        if *key != MouseButton::Right {
+            return false;
+        }
+
+        self.is_rotating = *state == ElementState::Pressed;
+        true
+    }
+
+    pub fn process_touch(&mut self, id: u64, phase: TouchPhase, location: &Vector2<f64>) -> bool {
+        match phase {
+            TouchPhase::Started | TouchPhase::Moved => {
+                if phase == TouchPhase::Started {
+                    self.last_touch_angle = None;
+                }
+                self.touches.insert(id, *location);
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.touches.remove(&id);
+                self.last_touch_angle = None;
+                return true;
+            }
+        }
+
+        if self.touches.len() != 2 {
+            self.last_touch_angle = None;
+            return true;
+        }
+
+        // Order the touches by id such that the angle does not flip
+        let mut touches = self.touches.iter().collect::<Vec<_>>();
+        touches.sort_by_key(|(id, _)| **id);
+        let (first, second) = (touches[0].1, touches[1].1);
+
+        let direction = second - first;
+        let angle = direction.y.atan2(direction.x).to_degrees();
+
+        if let Some(last_angle) = self.last_touch_angle {
+            // Take the shorter direction if the angle wrapped around
+            let delta = (angle - last_angle + 540.0).rem_euclid(360.0) - 180.0;
+            // Rotating the fingers clockwise rotates the map clockwise, which decreases the bearing
+            self.bearing_delta -= delta;
+            self.anchor = Some((first + second) / 2.0);
+        }
+        self.last_touch_angle = Some(angle);
+
+        true
+    }
+
+    pub fn process_key_press(
+        &mut self,
+        key: winit::event::VirtualKeyCode,
+        state: winit::event::ElementState,
+    ) -> bool {
+        match key {
+            winit::event::VirtualKeyCode::N => {
+                if state == winit::event::ElementState::Pressed {
+                    self.reset_north = true;
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use super::UpdateState;
 
-use cgmath::{Vector3, Zero};
+use cgmath::{Deg, Matrix3, Vector3, Zero};
 use maplibre::map_state::ViewState;
 use std::time::Duration;
 
@@ -16,7 +16,9 @@ impl UpdateState for ShiftHandler {
         let dt = dt.as_secs_f64() * (1.0 / self.speed);
 
         let delta = self.camera_translate * dt;
-        state.camera.position += delta;
+        // The translation is relative to the screen, so it is rotated by the bearing of the map
+        let rotation = Matrix3::from_angle_z(Deg(state.bearing()));
+        state.camera.position += rotation * delta;
         self.camera_translate -= delta;
     }
 }
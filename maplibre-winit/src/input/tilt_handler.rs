@@ -2,7 +2,7 @@ use super::UpdateState;
 
 use maplibre::map_state::ViewState;
 
-use cgmath::{Deg, Rad, Zero};
+use cgmath::{Deg, Zero};
 
 use std::time::Duration;
 
@@ -18,7 +18,8 @@ impl UpdateState for TiltHandler {
         let dt = dt.as_secs_f64() * (1.0 / self.speed);
 
         let delta = self.delta_pitch * dt;
-        state.camera.pitch += Rad::from(delta);
+        // The pitch is clamped to the pitch limits of the view state
+        state.set_pitch(state.pitch() + delta.0);
         self.delta_pitch -= delta;
     }
 }
@@ -18,9 +18,10 @@ impl UpdateState for ZoomHandler {
         if let Some(zoom_delta) = self.zoom_delta {
             if let Some(window_position) = self.window_position {
                 let current_zoom = state.zoom();
-                let next_zoom = current_zoom + zoom_delta;
 
-                state.update_zoom(next_zoom);
+                // The zoom is clamped to the zoom limits of the view state
+                state.update_zoom(current_zoom + zoom_delta);
+                let next_zoom = state.zoom();
                 self.zoom_delta = None;
 
                 let view_proj = state.view_projection();
@@ -10,11 +10,10 @@ use super::WinitMapWindow;
 use super::WinitWindow;
 
 use super::WinitMapWindowConfig;
-use maplibre::window::{MapWindow, WindowSize};
+use maplibre::window::{HeadedMapWindow, MapWindow, WindowSize};
 
 impl MapWindow for WinitMapWindow {
     type EventLoop = WinitEventLoop;
-    type Window = WinitWindow;
     type MapWindowConfig = WinitMapWindowConfig;
 
     fn create(map_window_config: &Self::MapWindowConfig) -> Self {
@@ -43,6 +42,10 @@ impl MapWindow for WinitMapWindow {
             WindowSize::new(size.width, size.height).expect("failed to get window dimensions.");
         window_size
     }
+}
+
+impl HeadedMapWindow for WinitMapWindow {
+    type Window = WinitWindow;
 
     fn inner(&self) -> &Self::Window {
         &self.window
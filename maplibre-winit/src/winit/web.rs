@@ -5,12 +5,11 @@ use super::WinitMapWindow;
 use super::WinitMapWindowConfig;
 use super::WinitWindow;
 
-use maplibre::window::{MapWindow, WindowSize};
+use maplibre::window::{HeadedMapWindow, MapWindow, WindowSize};
 use winit::platform::web::WindowBuilderExtWebSys;
 
 impl MapWindow for WinitMapWindow {
     type EventLoop = WinitEventLoop;
-    type Window = WinitWindow;
     type MapWindowConfig = WinitMapWindowConfig;
 
     fn create(map_window_config: &Self::MapWindowConfig) -> Self {
@@ -34,6 +33,10 @@ impl MapWindow for WinitMapWindow {
 
         WindowSize::new(size.width, size.height).expect("failed to get window dimensions.")
     }
+}
+
+impl HeadedMapWindow for WinitMapWindow {
+    type Window = WinitWindow;
 
     fn inner(&self) -> &Self::Window {
         &self.window
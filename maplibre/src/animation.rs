@@ -0,0 +1,624 @@
+//! Animated camera transitions like [`crate::map_state::ViewState::ease_to`] and
+//! [`crate::map_state::ViewState::fly_to`].
+
+use std::fmt;
+use std::time::Duration;
+
+use instant::Instant;
+
+use crate::coords::{MercatorCoordinate, Zoom};
+use crate::map_state::{CameraOptions, ViewState};
+
+/// The duration of an [`crate::map_state::ViewState::ease_to`] animation if no duration is given.
+pub const DEFAULT_EASE_DURATION: Duration = Duration::from_millis(500);
+
+/// The zooming "curve" of a [`crate::map_state::ViewState::fly_to`] animation. This value is
+/// named `rho` in the paper of van Wijk and Nuij. The default of `1.42` is the average value
+/// which was selected by participants of their user study.
+pub const DEFAULT_FLY_CURVE: f64 = 1.42;
+
+/// The average speed of a [`crate::map_state::ViewState::fly_to`] animation if no duration is
+/// given. A speed of `1.2` means that the map appears to move `1.2` screenfuls per second.
+pub const DEFAULT_FLY_SPEED: f64 = 1.2;
+
+/// Source of the current time which is used to advance animations. Implement this trait to
+/// control time, for example in tests.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// [`Clock`] which returns the current system time.
+#[derive(Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Maps the linear progress of an animation within `[0, 1]` to the progress of the camera.
+#[derive(Clone, Copy, Debug)]
+pub enum Easing {
+    Linear,
+    /// A cubic bézier curve from `(0, 0)` to `(1, 1)` with the control points `(x1, y1)` and
+    /// `(x2, y2)`, like the CSS `cubic-bezier()` function.
+    CubicBezier(f64, f64, f64, f64),
+    Custom(fn(f64) -> f64),
+}
+
+impl Easing {
+    /// Starts slowly and ends slowly. This is the default easing of maplibre-gl-js.
+    pub const EASE: Easing = Easing::CubicBezier(0.25, 0.1, 0.25, 1.0);
+    pub const EASE_IN: Easing = Easing::CubicBezier(0.42, 0.0, 1.0, 1.0);
+    pub const EASE_OUT: Easing = Easing::CubicBezier(0.0, 0.0, 0.58, 1.0);
+    pub const EASE_IN_OUT: Easing = Easing::CubicBezier(0.42, 0.0, 0.58, 1.0);
+
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match *self {
+            Easing::Linear => t,
+            Easing::CubicBezier(x1, y1, x2, y2) => cubic_bezier(x1, y1, x2, y2, t),
+            Easing::Custom(f) => f(t),
+        }
+    }
+}
+
+impl Default for Easing {
+    fn default() -> Self {
+        Easing::EASE
+    }
+}
+
+/// Solves the cubic bézier curve for `x` and returns the corresponding `y`.
+fn cubic_bezier(x1: f64, y1: f64, x2: f64, y2: f64, x: f64) -> f64 {
+    // Polynomial coefficients of the curve with the end points (0, 0) and (1, 1)
+    let cx = 3.0 * x1;
+    let bx = 3.0 * (x2 - x1) - cx;
+    let ax = 1.0 - cx - bx;
+    let cy = 3.0 * y1;
+    let by = 3.0 * (y2 - y1) - cy;
+    let ay = 1.0 - cy - by;
+
+    let sample_x = |t: f64| ((ax * t + bx) * t + cx) * t;
+    let sample_y = |t: f64| ((ay * t + by) * t + cy) * t;
+    let sample_dx = |t: f64| (3.0 * ax * t + 2.0 * bx) * t + cx;
+
+    const EPSILON: f64 = 1e-7;
+
+    // Newton's method converges quickly for most curves
+    let mut t = x;
+    for _ in 0..8 {
+        let error = sample_x(t) - x;
+        if error.abs() < EPSILON {
+            return sample_y(t);
+        }
+        let dx = sample_dx(t);
+        if dx.abs() < EPSILON {
+            break;
+        }
+        t -= error / dx;
+    }
+
+    // Fall back to bisection, which always converges because x(t) is monotonic
+    let (mut low, mut high) = (0.0, 1.0);
+    t = x;
+    while low < high {
+        let current = sample_x(t);
+        if (current - x).abs() < EPSILON {
+            break;
+        }
+        if x > current {
+            low = t;
+        } else {
+            high = t;
+        }
+        t = (high - low) / 2.0 + low;
+        if high - low < EPSILON {
+            break;
+        }
+    }
+    sample_y(t)
+}
+
+/// Options which control the timing of an animation.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AnimationOptions {
+    /// The duration of the animation. If `None`, then a default depending on the kind of
+    /// animation is used.
+    pub duration: Option<Duration>,
+    pub easing: Easing,
+    /// Only used by [`crate::map_state::ViewState::fly_to`]. See [`DEFAULT_FLY_CURVE`].
+    pub curve: Option<f64>,
+    /// Only used by [`crate::map_state::ViewState::fly_to`] if no `duration` is given.
+    /// See [`DEFAULT_FLY_SPEED`].
+    pub speed: Option<f64>,
+}
+
+impl AnimationOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    pub fn with_curve(mut self, curve: f64) -> Self {
+        self.curve = Some(curve);
+        self
+    }
+
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        self.speed = Some(speed);
+        self
+    }
+}
+
+/// Reported to the progress callback of an animation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnimationProgress {
+    /// The animation advanced. The value is the linear progress within `[0, 1]`.
+    Running(f64),
+    /// The animation reached its target.
+    Finished,
+    /// The animation was interrupted, for example because the user moved the map.
+    Cancelled,
+}
+
+pub type ProgressCallback = Box<dyn FnMut(AnimationProgress)>;
+
+/// Describes how the camera moves between the start and the target.
+enum Path {
+    /// Interpolates all properties linearly.
+    Ease,
+    /// Zooms out and in again while moving, as described by van Wijk and Nuij in
+    /// "Smooth and efficient zooming and panning".
+    Fly(FlyPath),
+}
+
+struct FlyPath {
+    /// `rho` in the paper.
+    curve: f64,
+    /// The length of the path `S`.
+    length: f64,
+    /// `r(0)` in the paper.
+    r0: f64,
+    /// The distance `u1` between start and target in mercator units.
+    distance: f64,
+    /// The width `w0` of the viewport at the start in mercator units.
+    w0: f64,
+    /// Set if start and target are at the same position. Then the zoom changes exponentially.
+    zoom_direction: Option<f64>,
+}
+
+impl FlyPath {
+    fn new(curve: f64, w0: f64, w1: f64, distance: f64) -> Option<Self> {
+        let rho2 = curve * curve;
+
+        // r(i) in the paper
+        let r = |i: u8| {
+            let (sign, w) = if i == 0 { (1.0, w0) } else { (-1.0, w1) };
+            let b = (w1 * w1 - w0 * w0 + sign * rho2 * rho2 * distance * distance)
+                / (2.0 * w * rho2 * distance);
+            ((b * b + 1.0).sqrt() - b).ln()
+        };
+
+        let r0 = r(0);
+        let length = (r(1) - r0) / curve;
+
+        if distance.abs() > 1e-9 && length.is_finite() {
+            return Some(Self {
+                curve,
+                length,
+                r0,
+                distance,
+                w0,
+                zoom_direction: None,
+            });
+        }
+
+        // The camera only zooms
+        if (w0 - w1).abs() < 1e-9 {
+            return None;
+        }
+        let direction = if w1 < w0 { -1.0 } else { 1.0 };
+        Some(Self {
+            curve,
+            length: (w1 / w0).ln().abs() / curve,
+            r0,
+            distance,
+            w0,
+            zoom_direction: Some(direction),
+        })
+    }
+
+    /// The width of the viewport relative to `w0` at `s`.
+    fn w(&self, s: f64) -> f64 {
+        match self.zoom_direction {
+            Some(direction) => (direction * self.curve * s).exp(),
+            None => self.r0.cosh() / (self.r0 + self.curve * s).cosh(),
+        }
+    }
+
+    /// The fraction of the distance between start and target which is covered at `s`.
+    fn u(&self, s: f64) -> f64 {
+        match self.zoom_direction {
+            Some(_) => 0.0,
+            None => {
+                let rho2 = self.curve * self.curve;
+                self.w0
+                    * ((self.r0.cosh() * (self.r0 + self.curve * s).tanh() - self.r0.sinh()) / rho2)
+                    / self.distance
+            }
+        }
+    }
+}
+
+/// A running camera animation. Created by [`crate::map_state::ViewState::ease_to`] and
+/// [`crate::map_state::ViewState::fly_to`].
+pub struct CameraAnimation {
+    from: MercatorCoordinate,
+    to: MercatorCoordinate,
+    from_zoom: Zoom,
+    to_zoom: Zoom,
+    from_bearing: f64,
+    bearing_delta: f64,
+    from_pitch: f64,
+    to_pitch: f64,
+
+    path: Path,
+    duration: Duration,
+    easing: Easing,
+
+    /// Set when the animation is advanced for the first time.
+    start: Option<Instant>,
+    callback: Option<ProgressCallback>,
+}
+
+impl fmt::Debug for CameraAnimation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CameraAnimation")
+            .field("to", &self.to)
+            .field("to_zoom", &self.to_zoom)
+            .field("duration", &self.duration)
+            .finish()
+    }
+}
+
+impl CameraAnimation {
+    pub(crate) fn ease(
+        view_state: &ViewState,
+        target: &CameraOptions,
+        options: &AnimationOptions,
+    ) -> Self {
+        let mut animation = Self::new(view_state, target, options, Path::Ease);
+        animation.duration = options.duration.unwrap_or(DEFAULT_EASE_DURATION);
+        animation
+    }
+
+    pub(crate) fn fly(
+        view_state: &ViewState,
+        target: &CameraOptions,
+        options: &AnimationOptions,
+    ) -> Self {
+        let mut animation = Self::new(view_state, target, options, Path::Ease);
+
+        let curve = options.curve.unwrap_or(DEFAULT_FLY_CURVE);
+        let (width, height) = view_state.viewport_size();
+        let w0 = width.max(height) / animation.from_zoom.world_size();
+        let w1 = w0 / animation.from_zoom.scale_delta(&animation.to_zoom);
+        let distance = ((animation.to.x - animation.from.x).powi(2)
+            + (animation.to.y - animation.from.y).powi(2))
+        .sqrt();
+
+        if let Some(fly_path) = FlyPath::new(curve, w0, w1, distance) {
+            animation.duration = options.duration.unwrap_or_else(|| {
+                Duration::from_secs_f64(
+                    fly_path.length / options.speed.unwrap_or(DEFAULT_FLY_SPEED),
+                )
+            });
+            animation.path = Path::Fly(fly_path);
+        } else {
+            animation.duration = options.duration.unwrap_or(DEFAULT_EASE_DURATION);
+        }
+
+        animation
+    }
+
+    fn new(
+        view_state: &ViewState,
+        target: &CameraOptions,
+        options: &AnimationOptions,
+        path: Path,
+    ) -> Self {
+        let from_bearing = view_state.bearing();
+        let to_bearing = target.bearing.unwrap_or(from_bearing);
+        let from_pitch = view_state.pitch();
+
+        Self {
+            from: view_state.center().into_mercator(),
+            to: target
+                .center
+                .unwrap_or_else(|| view_state.center())
+                .into_mercator(),
+            from_zoom: view_state.zoom(),
+            to_zoom: target.zoom.unwrap_or_else(|| view_state.zoom()),
+            from_bearing,
+            // Rotate along the shorter direction
+            bearing_delta: (to_bearing - from_bearing + 540.0).rem_euclid(360.0) - 180.0,
+            from_pitch,
+            to_pitch: target.pitch.unwrap_or(from_pitch),
+            path,
+            duration: Duration::ZERO,
+            easing: options.easing,
+            start: None,
+            callback: None,
+        }
+    }
+
+    pub(crate) fn set_callback(&mut self, callback: ProgressCallback) {
+        self.callback = Some(callback);
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Calculates the camera at the linear progress `t` within `[0, 1]`.
+    fn camera_at(&self, t: f64) -> CameraOptions {
+        let k = self.easing.apply(t);
+
+        let (center, zoom) = match &self.path {
+            Path::Ease => (
+                MercatorCoordinate::new(
+                    interpolate(self.from.x, self.to.x, k),
+                    interpolate(self.from.y, self.to.y, k),
+                ),
+                Zoom::new(interpolate(self.from_zoom.value(), self.to_zoom.value(), k)),
+            ),
+            Path::Fly(fly_path) => {
+                let s = k * fly_path.length;
+                let u = if t >= 1.0 { 1.0 } else { fly_path.u(s) };
+                let zoom = if t >= 1.0 {
+                    self.to_zoom
+                } else {
+                    Zoom::new(self.from_zoom.value() - fly_path.w(s).log2())
+                };
+                (
+                    MercatorCoordinate::new(
+                        interpolate(self.from.x, self.to.x, u),
+                        interpolate(self.from.y, self.to.y, u),
+                    ),
+                    zoom,
+                )
+            }
+        };
+
+        CameraOptions {
+            center: Some(center.into_lng_lat()),
+            zoom: Some(zoom),
+            bearing: Some(self.from_bearing + self.bearing_delta * k),
+            pitch: Some(interpolate(self.from_pitch, self.to_pitch, k)),
+        }
+    }
+
+    /// Moves the camera of `view_state` according to the time `now`. Returns `true` if the
+    /// animation is finished.
+    pub(crate) fn update(&mut self, view_state: &mut ViewState, now: Instant) -> bool {
+        let start = *self.start.get_or_insert(now);
+        let t = self.progress(start, now);
+
+        view_state.jump_to(&self.camera_at(t));
+
+        if let Some(callback) = &mut self.callback {
+            callback(AnimationProgress::Running(t));
+            if t >= 1.0 {
+                callback(AnimationProgress::Finished);
+            }
+        }
+
+        t >= 1.0
+    }
+
+    /// The linear progress within `[0, 1]` at the time `now`.
+    fn progress(&self, start: Instant, now: Instant) -> f64 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+
+        let elapsed = now.saturating_duration_since(start);
+        (elapsed.as_secs_f64() / self.duration.as_secs_f64()).min(1.0)
+    }
+
+    /// Samples `samples` cameras along the path which remains after the time `now`. The last
+    /// camera is the target of the animation.
+    pub fn remaining_path(&self, now: Instant, samples: usize) -> Vec<CameraOptions> {
+        let t0 = self
+            .start
+            .map(|start| self.progress(start, now))
+            .unwrap_or(0.0);
+
+        (1..=samples)
+            .map(|i| self.camera_at(t0 + (1.0 - t0) * i as f64 / samples as f64))
+            .collect()
+    }
+
+    pub(crate) fn cancel(mut self) {
+        if let Some(callback) = &mut self.callback {
+            callback(AnimationProgress::Cancelled);
+        }
+    }
+}
+
+fn interpolate(from: f64, to: f64, t: f64) -> f64 {
+    from + (to - from) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use instant::Instant;
+
+    use crate::animation::{AnimationOptions, AnimationProgress, Easing};
+    use crate::coords::{LngLat, Zoom};
+    use crate::map_state::{CameraOptions, ViewState};
+    use crate::window::WindowSize;
+
+    fn view_state() -> ViewState {
+        ViewState::new(
+            WindowSize::new(800, 600).unwrap(),
+            &CameraOptions::new()
+                .with_center(LngLat::new(11.5583, 48.1402))
+                .with_zoom(Zoom::new(10.0)),
+        )
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_easing() {
+        for easing in [
+            Easing::Linear,
+            Easing::EASE,
+            Easing::EASE_IN,
+            Easing::EASE_OUT,
+            Easing::EASE_IN_OUT,
+        ] {
+            assert_close(easing.apply(0.0), 0.0);
+            assert_close(easing.apply(1.0), 1.0);
+        }
+        assert_close(Easing::EASE_IN_OUT.apply(0.5), 0.5);
+        assert!(Easing::EASE_IN.apply(0.25) < 0.25);
+        assert!(Easing::EASE_OUT.apply(0.25) > 0.25);
+    }
+
+    #[test]
+    fn test_ease_to() {
+        let mut view_state = view_state();
+        let berlin = LngLat::new(13.4050, 52.5200);
+
+        let progress = Rc::new(RefCell::new(Vec::new()));
+        let callback_progress = progress.clone();
+
+        view_state.ease_to(
+            &CameraOptions::new()
+                .with_center(berlin)
+                .with_zoom(Zoom::new(12.0))
+                .with_bearing(350.0),
+            &AnimationOptions::new()
+                .with_duration(Duration::from_secs(1))
+                .with_easing(Easing::Linear),
+            Some(Box::new(move |p| callback_progress.borrow_mut().push(p))),
+        );
+
+        let start = Instant::now();
+        view_state.update_animation(start);
+        assert!(view_state.is_animating());
+        assert_close(view_state.zoom().value(), 10.0);
+
+        view_state.update_animation(start + Duration::from_millis(500));
+        assert_close(view_state.zoom().value(), 11.0);
+        // The bearing rotates along the shorter direction
+        assert_close(view_state.bearing(), 355.0);
+
+        let path = view_state
+            .animation()
+            .unwrap()
+            .remaining_path(start + Duration::from_millis(500), 2);
+        assert_close(path[0].zoom.unwrap().value(), 11.5);
+        assert_close(path[1].zoom.unwrap().value(), 12.0);
+        assert_close(path[1].center.unwrap().lng, berlin.lng);
+
+        view_state.update_animation(start + Duration::from_secs(2));
+        assert!(!view_state.is_animating());
+        assert_close(view_state.zoom().value(), 12.0);
+        assert_close(view_state.center().lng, berlin.lng);
+        assert_close(view_state.center().lat, berlin.lat);
+
+        assert_eq!(
+            *progress.borrow(),
+            vec![
+                AnimationProgress::Running(0.0),
+                AnimationProgress::Running(0.5),
+                AnimationProgress::Running(1.0),
+                AnimationProgress::Finished
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fly_to() {
+        let mut view_state = view_state();
+        let paris = LngLat::new(2.3522, 48.8566);
+
+        view_state.fly_to(
+            &CameraOptions::new()
+                .with_center(paris)
+                .with_zoom(Zoom::new(10.0)),
+            &AnimationOptions::new(),
+            None,
+        );
+
+        let start = Instant::now();
+        view_state.update_animation(start);
+        let duration = view_state.animation().unwrap().duration();
+        assert!(duration > Duration::ZERO);
+
+        // The camera zooms out while flying
+        view_state.update_animation(start + duration / 2);
+        assert!(view_state.zoom().value() < 10.0);
+
+        view_state.update_animation(start + duration);
+        assert!(!view_state.is_animating());
+        assert_close(view_state.zoom().value(), 10.0);
+        assert_close(view_state.center().lng, paris.lng);
+        assert_close(view_state.center().lat, paris.lat);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut view_state = view_state();
+
+        let progress = Rc::new(RefCell::new(Vec::new()));
+        let callback_progress = progress.clone();
+
+        view_state.fly_to(
+            &CameraOptions::new().with_zoom(Zoom::new(14.0)),
+            &AnimationOptions::new().with_duration(Duration::from_secs(1)),
+            Some(Box::new(move |p| callback_progress.borrow_mut().push(p))),
+        );
+
+        let start = Instant::now();
+        view_state.update_animation(start);
+        view_state.cancel_animation();
+        assert!(!view_state.is_animating());
+
+        view_state.update_animation(start + Duration::from_secs(1));
+        assert_close(view_state.zoom().value(), 10.0);
+
+        assert_eq!(
+            *progress.borrow(),
+            vec![
+                AnimationProgress::Running(0.0),
+                AnimationProgress::Cancelled
+            ]
+        );
+    }
+}
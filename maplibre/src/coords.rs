@@ -1,14 +1,17 @@
 //! Provides utilities related to coordinates.
 
+use std::collections::HashSet;
+use std::f64::consts::PI;
 use std::fmt;
 use std::fmt::Formatter;
 
 use cgmath::num_traits::Pow;
-use cgmath::{AbsDiffEq, Matrix4, Point3, Vector3};
+use cgmath::{AbsDiffEq, Matrix4, Point2, Point3, Vector3};
+use serde::{Deserialize, Serialize};
 
 use crate::style::source::TileAddressingScheme;
 
-use crate::util::math::{div_floor, Aabb2};
+use crate::util::math::{bounds_from_points, div_floor, intersects_convex_polygon_aabb2, Aabb2};
 use crate::util::SignificantlyDifferent;
 
 pub const EXTENT_UINT: u32 = 4096;
@@ -17,6 +20,15 @@ pub const EXTENT: f64 = EXTENT_UINT as f64;
 pub const TILE_SIZE: f64 = 512.0;
 pub const MAX_ZOOM: usize = 32;
 
+/// Equatorial radius of the WGS84 ellipsoid in meters. Web Mercator treats the earth as a sphere
+/// with this radius.
+pub const EARTH_RADIUS: f64 = 6378137.0;
+/// Circumference of the earth at the equator in meters.
+pub const EARTH_CIRCUMFERENCE: f64 = 2.0 * PI * EARTH_RADIUS;
+/// The maximum latitude which can be represented in Web Mercator. At this latitude the projected
+/// world is a square.
+pub const MAX_LATITUDE: f64 = 85.05112877980659;
+
 // FIXME: MAX_ZOOM is 32, which means max bound is 2^32, which wouldn't fit in u32 or i32
 // Bounds are generated 0..=31
 pub const ZOOM_BOUNDS: [u32; MAX_ZOOM] = create_zoom_bounds::<MAX_ZOOM>();
@@ -62,7 +74,7 @@ impl fmt::Debug for Quadkey {
 
 /// `Zoom` is an exponential scale that defines the zoom of the camera on the map.
 /// We can derive the `ZoomLevel` from `Zoom` by using the `[crate::coords::ZOOM_BOUNDS]`.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
 pub struct Zoom(f64);
 
 impl Zoom {
@@ -115,6 +127,15 @@ impl Zoom {
     pub fn level(&self) -> u8 {
         self.0.floor() as u8
     }
+
+    pub fn value(&self) -> f64 {
+        self.0
+    }
+
+    /// The size of the whole world in [`WorldCoords`] at this zoom.
+    pub fn world_size(&self) -> f64 {
+        TILE_SIZE * 2.0_f64.powf(self.0)
+    }
 }
 
 impl SignificantlyDifferent for Zoom {
@@ -332,6 +353,61 @@ impl WorldTileCoords {
             z: self.z - 1,
         })
     }
+
+    /// Returns the four tiles which are one zoom level higher and are contained in this one.
+    pub fn children(&self) -> [WorldTileCoords; 4] {
+        let (x, y, z) = (self.x << 1, self.y << 1, self.z + 1);
+        [
+            WorldTileCoords { x, y, z },
+            WorldTileCoords { x: x + 1, y, z },
+            WorldTileCoords { x, y: y + 1, z },
+            WorldTileCoords {
+                x: x + 1,
+                y: y + 1,
+                z,
+            },
+        ]
+    }
+
+    /// Returns the tile at zoom level `z` which contains this one. Returns `None` if `z` is
+    /// higher than the zoom level of this tile.
+    pub fn ancestor_at(&self, z: u8) -> Option<WorldTileCoords> {
+        if z > self.z {
+            return None;
+        }
+
+        let shift = self.z - z;
+        Some(WorldTileCoords {
+            x: self.x >> shift,
+            y: self.y >> shift,
+            z,
+        })
+    }
+
+    /// Returns the tile which provides the data for this tile. Tiles beyond the `max_zoom` of a
+    /// source are overzoomed and use the data of their ancestor at `max_zoom`.
+    pub fn overzoom_source(&self, max_zoom: Option<u8>) -> WorldTileCoords {
+        match max_zoom {
+            Some(max_zoom) if self.z > max_zoom => self.ancestor_at(max_zoom).unwrap_or(*self),
+            _ => *self,
+        }
+    }
+
+    /// Returns the geographic area which is covered by this tile.
+    pub fn lng_lat_bounds(&self) -> LngLatBounds {
+        let tiles = tiles_with_z(self.z);
+        let north_west = MercatorCoordinate::new(self.x as f64 / tiles, self.y as f64 / tiles);
+        let south_east =
+            MercatorCoordinate::new((self.x + 1) as f64 / tiles, (self.y + 1) as f64 / tiles);
+
+        let north_west = north_west.into_lng_lat();
+        let south_east = south_east.into_lng_lat();
+
+        LngLatBounds::new(
+            LngLat::new(north_west.lng, south_east.lat),
+            LngLat::new(south_east.lng, north_west.lat),
+        )
+    }
 }
 
 impl From<(i32, i32, u8)> for WorldTileCoords {
@@ -406,6 +482,16 @@ impl WorldCoords {
         Self { x, y }
     }
 
+    /// Normalizes the world coordinates which are scaled for the given `zoom`.
+    pub fn into_mercator(self, zoom: Zoom) -> MercatorCoordinate {
+        let world_size = zoom.world_size();
+        MercatorCoordinate::new(self.x / world_size, self.y / world_size)
+    }
+
+    pub fn into_lng_lat(self, zoom: Zoom) -> LngLat {
+        self.into_mercator(zoom).into_lng_lat()
+    }
+
     pub fn into_world_tile(self, z: u8, zoom: Zoom) -> WorldTileCoords {
         let tile_scale = zoom.scale_to_zoom_level(z) / TILE_SIZE; // TODO: Deduplicate
         let x = self.x * tile_scale;
@@ -446,6 +532,204 @@ impl From<Point3<f64>> for WorldCoords {
     }
 }
 
+/// Geographic coordinates in degrees on the WGS84 ellipsoid. The longitude `lng` is east-positive
+/// and the latitude `lat` is north-positive.
+///
+/// Latitudes beyond [`crate::coords::MAX_LATITUDE`] can not be projected with Web Mercator and are
+/// clamped during conversions.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct LngLat {
+    pub lng: f64,
+    pub lat: f64,
+}
+
+impl LngLat {
+    pub fn new(lng: f64, lat: f64) -> Self {
+        Self { lng, lat }
+    }
+
+    /// Returns a new [`LngLat`] with the longitude wrapped into the range `[-180, 180)`.
+    pub fn wrap(&self) -> LngLat {
+        LngLat {
+            lng: (self.lng + 180.0).rem_euclid(360.0) - 180.0,
+            lat: self.lat,
+        }
+    }
+
+    /// Projects the coordinates onto the unit square of Web Mercator.
+    pub fn into_mercator(self) -> MercatorCoordinate {
+        let lat = self.lat.clamp(-MAX_LATITUDE, MAX_LATITUDE);
+        MercatorCoordinate {
+            x: (180.0 + self.lng) / 360.0,
+            y: (180.0 - (180.0 / PI * (PI / 4.0 + lat * PI / 360.0).tan().ln())) / 360.0,
+        }
+    }
+
+    /// Returns the coordinates within the 3D world when it is scaled for the given `zoom`.
+    pub fn into_world(self, zoom: Zoom) -> WorldCoords {
+        self.into_mercator().into_world(zoom)
+    }
+
+    /// Returns the tile at the zoom level `z` which contains these coordinates.
+    pub fn into_world_tile(self, z: u8) -> WorldTileCoords {
+        self.into_mercator().into_world_tile(z)
+    }
+
+    /// Returns the tile at the zoom level `z` which contains these coordinates according to an
+    /// addressing scheme.
+    pub fn into_tile(self, z: u8, scheme: TileAddressingScheme) -> Option<TileCoords> {
+        self.into_world_tile(z).into_tile(scheme)
+    }
+
+    /// The ground resolution in meters which one pixel covers at this latitude and the given
+    /// `zoom`.
+    pub fn meters_per_pixel(&self, zoom: Zoom) -> f64 {
+        let lat = self.lat.clamp(-MAX_LATITUDE, MAX_LATITUDE);
+        EARTH_CIRCUMFERENCE * lat.to_radians().cos() / zoom.world_size()
+    }
+}
+
+impl From<(f64, f64)> for LngLat {
+    fn from(tuple: (f64, f64)) -> Self {
+        LngLat {
+            lng: tuple.0,
+            lat: tuple.1,
+        }
+    }
+}
+
+/// A rectangular geographic area which is defined by its south-west and north-east corner.
+///
+/// *Note:* Bounds which cross the antimeridian are not supported. The `sw` longitude has to be
+/// smaller than the `ne` longitude.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LngLatBounds {
+    pub sw: LngLat,
+    pub ne: LngLat,
+}
+
+impl LngLatBounds {
+    pub fn new(sw: LngLat, ne: LngLat) -> Self {
+        Self { sw, ne }
+    }
+
+    /// Creates the smallest bounds which contain all `points`. Returns `None` if there are
+    /// no points.
+    pub fn from_points<I: IntoIterator<Item = LngLat>>(points: I) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let mut bounds = LngLatBounds::new(first, first);
+        for point in points {
+            bounds.extend(&point);
+        }
+        Some(bounds)
+    }
+
+    pub fn west(&self) -> f64 {
+        self.sw.lng
+    }
+
+    pub fn south(&self) -> f64 {
+        self.sw.lat
+    }
+
+    pub fn east(&self) -> f64 {
+        self.ne.lng
+    }
+
+    pub fn north(&self) -> f64 {
+        self.ne.lat
+    }
+
+    pub fn north_west(&self) -> LngLat {
+        LngLat::new(self.west(), self.north())
+    }
+
+    pub fn south_east(&self) -> LngLat {
+        LngLat::new(self.east(), self.south())
+    }
+
+    /// The center of the bounds in Web Mercator. This is not the geographic midpoint of the
+    /// latitudes.
+    pub fn center(&self) -> LngLat {
+        let north_west = self.north_west().into_mercator();
+        let south_east = self.south_east().into_mercator();
+        MercatorCoordinate::new(
+            (north_west.x + south_east.x) / 2.0,
+            (north_west.y + south_east.y) / 2.0,
+        )
+        .into_lng_lat()
+    }
+
+    pub fn contains(&self, point: &LngLat) -> bool {
+        point.lng >= self.west()
+            && point.lng <= self.east()
+            && point.lat >= self.south()
+            && point.lat <= self.north()
+    }
+
+    /// Grows the bounds such that `point` is contained.
+    pub fn extend(&mut self, point: &LngLat) {
+        self.sw.lng = self.sw.lng.min(point.lng);
+        self.sw.lat = self.sw.lat.min(point.lat);
+        self.ne.lng = self.ne.lng.max(point.lng);
+        self.ne.lat = self.ne.lat.max(point.lat);
+    }
+
+    /// Returns all tiles at the zoom level `z` which intersect with the bounds.
+    pub fn tile_cover(&self, z: u8) -> impl Iterator<Item = WorldTileCoords> {
+        let min_tile = self.north_west().into_world_tile(z);
+        let max_tile = self.south_east().into_world_tile(z);
+
+        (min_tile.x..=max_tile.x)
+            .flat_map(move |x| (min_tile.y..=max_tile.y).map(move |y| (x, y, z).into()))
+    }
+}
+
+/// A position on the Web Mercator projection of the world which is normalized to the unit square.
+/// This means that `x` and `y` are within `[0, 1]` for any valid position on the map.
+///
+/// # Coordinate System Origin
+///
+/// The origin of the coordinate system is in the upper-left corner, which corresponds to
+/// `lng = -180` and `lat = MAX_LATITUDE`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MercatorCoordinate {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl MercatorCoordinate {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+
+    pub fn into_lng_lat(self) -> LngLat {
+        LngLat {
+            lng: self.x * 360.0 - 180.0,
+            lat: 360.0 / PI * ((180.0 - self.y * 360.0) * PI / 180.0).exp().atan() - 90.0,
+        }
+    }
+
+    /// Scales the normalized coordinates to the 3D world for the given `zoom`.
+    pub fn into_world(self, zoom: Zoom) -> WorldCoords {
+        let world_size = zoom.world_size();
+        WorldCoords::at_ground(self.x * world_size, self.y * world_size)
+    }
+
+    /// Returns the tile at the zoom level `z` which contains this coordinate. Coordinates outside
+    /// of the world are clamped to the tiles at the edge.
+    pub fn into_world_tile(self, z: u8) -> WorldTileCoords {
+        let tiles = tiles_with_z(z);
+        let max = tiles as i32 - 1;
+        WorldTileCoords {
+            x: ((self.x * tiles).floor() as i32).clamp(0, max),
+            y: ((self.y * tiles).floor() as i32).clamp(0, max),
+            z,
+        }
+    }
+}
+
 /// Defines a bounding box on a tiled map with a [`ZoomLevel`] and a padding.
 #[derive(Debug)]
 pub struct ViewRegion {
@@ -453,6 +737,10 @@ pub struct ViewRegion {
     max_tile: WorldTileCoords,
     z: u8,
     padding: i32,
+    /// The visible area in [`WorldCoords`]. If set, then tiles within the bounding box which do
+    /// not intersect the polygon are not in view. This is the case for rotated views.
+    polygon: Option<Vec<Point2<f64>>>,
+    zoom: Zoom,
 }
 
 impl ViewRegion {
@@ -467,9 +755,30 @@ impl ViewRegion {
             max_tile: max_world_tile,
             z,
             padding,
+            polygon: None,
+            zoom,
         }
     }
 
+    /// Creates a view region from the convex `polygon` of the visible area. See
+    /// [`crate::render::camera::Camera::view_region_polygon`].
+    pub fn from_polygon(
+        polygon: Vec<Point2<f64>>,
+        padding: i32,
+        zoom: Zoom,
+        z: u8,
+    ) -> Option<Self> {
+        let (min, max) = bounds_from_points(polygon.iter().map(|point| [point.x, point.y]))?;
+        let mut view_region = Self::new(
+            Aabb2::new(Point2::from(min), Point2::from(max)),
+            padding,
+            zoom,
+            z,
+        );
+        view_region.polygon = Some(polygon);
+        Some(view_region)
+    }
+
     pub fn zoom_level(&self) -> u8 {
         self.z
     }
@@ -480,15 +789,69 @@ impl ViewRegion {
             && world_coords.x >= self.min_tile.x - self.padding
             && world_coords.y >= self.min_tile.y - self.padding
             && world_coords.z == self.z
+            && self.intersects_polygon(&world_coords)
+    }
+
+    fn intersects_polygon(&self, world_coords: &WorldTileCoords) -> bool {
+        let polygon = if let Some(polygon) = &self.polygon {
+            polygon
+        } else {
+            return true;
+        };
+
+        let tile_size = TILE_SIZE / self.zoom.scale_to_zoom_level(world_coords.z);
+        let padding = self.padding as f64 * tile_size;
+        let tile = Aabb2::new(
+            Point2::new(
+                world_coords.x as f64 * tile_size - padding,
+                world_coords.y as f64 * tile_size - padding,
+            ),
+            Point2::new(
+                (world_coords.x + 1) as f64 * tile_size + padding,
+                (world_coords.y + 1) as f64 * tile_size + padding,
+            ),
+        );
+        intersects_convex_polygon_aabb2(polygon, &tile)
     }
 
     pub fn iter(&self) -> impl Iterator<Item = WorldTileCoords> + '_ {
-        (self.min_tile.x - self.padding..self.max_tile.x + 1 + self.padding).flat_map(move |x| {
-            (self.min_tile.y - self.padding..self.max_tile.y + 1 + self.padding).map(move |y| {
-                let tile_coord: WorldTileCoords = (x, y, self.z as u8).into();
-                tile_coord
+        (self.min_tile.x - self.padding..self.max_tile.x + 1 + self.padding)
+            .flat_map(move |x| {
+                (self.min_tile.y - self.padding..self.max_tile.y + 1 + self.padding).map(move |y| {
+                    let tile_coord: WorldTileCoords = (x, y, self.z as u8).into();
+                    tile_coord
+                })
             })
-        })
+            .filter(move |tile_coord| self.intersects_polygon(tile_coord))
+    }
+
+    /// Iterates over the tiles within `ring` tiles around the bounding box of the view region
+    /// which are not in view.
+    pub fn iter_ring(&self, ring: i32) -> impl Iterator<Item = WorldTileCoords> + '_ {
+        let padding = self.padding + ring;
+        (self.min_tile.x - padding..self.max_tile.x + 1 + padding)
+            .flat_map(move |x| {
+                (self.min_tile.y - padding..self.max_tile.y + 1 + padding)
+                    .map(move |y| WorldTileCoords::from((x, y, self.z)))
+            })
+            .filter(move |tile_coord| !self.is_in_view(tile_coord))
+    }
+
+    /// Iterates over the tiles which provide the data for the tiles in view, given the distinct
+    /// `max_zooms` of the sources. Each tile is only returned once, even if it is the overzoom
+    /// source of multiple tiles in view. See [`WorldTileCoords::overzoom_source`].
+    pub fn iter_sources<'a>(
+        &'a self,
+        max_zooms: &'a [Option<u8>],
+    ) -> impl Iterator<Item = WorldTileCoords> + 'a {
+        let mut returned = HashSet::new();
+        self.iter()
+            .flat_map(move |coords| {
+                max_zooms
+                    .iter()
+                    .map(move |max_zoom| coords.overzoom_source(*max_zoom))
+            })
+            .filter(move |coords| returned.insert(*coords))
     }
 }
 
@@ -521,6 +884,12 @@ impl fmt::Display for WorldCoords {
     }
 }
 
+impl fmt::Display for LngLat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LngLat({lng}, {lat})", lng = self.lng, lat = self.lat)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use cgmath::{Point2, Vector4};
@@ -528,7 +897,8 @@ mod tests {
     use crate::style::source::TileAddressingScheme;
 
     use crate::coords::{
-        Quadkey, TileCoords, ViewRegion, WorldCoords, WorldTileCoords, Zoom, EXTENT,
+        LngLat, LngLatBounds, Quadkey, TileCoords, ViewRegion, WorldCoords, WorldTileCoords, Zoom,
+        EXTENT, MAX_LATITUDE, TILE_SIZE,
     };
     use crate::util::math::Aabb2;
 
@@ -599,4 +969,119 @@ mod tests {
             println!("{}", tile_coords);
         }
     }
+
+    #[test]
+    fn test_lng_lat_to_mercator() {
+        let munich = LngLat::new(11.5583, 48.1402);
+        let mercator = munich.into_mercator();
+        assert!((mercator.x - 0.532106).abs() < 1e-6);
+        assert!((mercator.y - 0.347032).abs() < 1e-6);
+
+        let back = mercator.into_lng_lat();
+        assert!((back.lng - munich.lng).abs() < 1e-9);
+        assert!((back.lat - munich.lat).abs() < 1e-9);
+
+        let north_west = LngLat::new(-180.0, MAX_LATITUDE).into_mercator();
+        assert!(north_west.x.abs() < 1e-9 && north_west.y.abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_lng_lat_to_world() {
+        let munich = LngLat::new(11.5583, 48.1402);
+        let zoom = Zoom::new(15.0);
+
+        let world = munich.into_world(zoom);
+        let back = world.into_lng_lat(zoom);
+        assert!((back.lng - munich.lng).abs() < 1e-9);
+        assert!((back.lat - munich.lat).abs() < 1e-9);
+
+        // The world coordinates have to be within the tile which is derived from them
+        assert_eq!(world.into_world_tile(15, zoom), munich.into_world_tile(15));
+        assert_eq!(munich.into_world_tile(15), (17436, 11371, 15).into());
+
+        let center = LngLat::new(0.0, 0.0).into_world(Zoom::new(0.0));
+        assert_eq!(
+            center,
+            WorldCoords::at_ground(TILE_SIZE / 2.0, TILE_SIZE / 2.0)
+        );
+    }
+
+    #[test]
+    fn test_meters_per_pixel() {
+        let equator = LngLat::new(0.0, 0.0);
+        assert!((equator.meters_per_pixel(Zoom::new(0.0)) - 78271.516964).abs() < 1e-3);
+        assert!((equator.meters_per_pixel(Zoom::new(1.0)) - 39135.758482).abs() < 1e-3);
+
+        let north = LngLat::new(0.0, 60.0);
+        assert!((north.meters_per_pixel(Zoom::new(0.0)) - 78271.516964 / 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_tile_bounds() {
+        let tile: WorldTileCoords = (17436, 11371, 15).into();
+        let bounds = tile.lng_lat_bounds();
+        assert!(bounds.contains(&LngLat::new(11.5583, 48.1402)));
+
+        let world = WorldTileCoords::from((0, 0, 0)).lng_lat_bounds();
+        assert!((world.west() + 180.0).abs() < 1e-9);
+        assert!((world.north() - MAX_LATITUDE).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_tile_cover() {
+        let bounds = LngLatBounds::new(LngLat::new(-10.0, -10.0), LngLat::new(10.0, 10.0));
+        let tiles: Vec<WorldTileCoords> = bounds.tile_cover(1).collect();
+        assert_eq!(tiles.len(), 4);
+
+        let tiles: Vec<WorldTileCoords> = bounds.tile_cover(0).collect();
+        assert_eq!(tiles, vec![(0, 0, 0).into()]);
+
+        let bounds =
+            LngLatBounds::from_points([LngLat::new(11.55, 48.13), LngLat::new(11.57, 48.15)])
+                .unwrap();
+        for tile in bounds.tile_cover(15) {
+            assert_eq!(tile.z, 15);
+            assert!(tile.x >= 17435 && tile.x <= 17437);
+        }
+    }
+
+    #[test]
+    fn test_overzoom_source() {
+        let coords = WorldTileCoords::from((13, 6, 4));
+        assert_eq!(
+            coords.ancestor_at(2),
+            Some(WorldTileCoords::from((3, 1, 2)))
+        );
+        assert_eq!(coords.ancestor_at(4), Some(coords));
+        assert_eq!(coords.ancestor_at(5), None);
+        for child in coords.children() {
+            assert_eq!(child.get_parent(), Some(coords));
+        }
+
+        assert_eq!(coords.overzoom_source(Some(2)), (3, 1, 2).into());
+        assert_eq!(coords.overzoom_source(Some(14)), coords);
+        assert_eq!(coords.overzoom_source(None), coords);
+
+        let view_region = ViewRegion::new(
+            Aabb2::new(Point2::new(0.0, 0.0), Point2::new(1000.0, 1000.0)),
+            0,
+            Zoom::new(3.0),
+            3,
+        );
+        assert_eq!(view_region.iter().count(), 4);
+        assert_eq!(
+            view_region.iter_sources(&[Some(2)]).collect::<Vec<_>>(),
+            vec![WorldTileCoords::from((0, 0, 2))]
+        );
+        assert_eq!(view_region.iter_sources(&[None]).count(), 4);
+        // Each source overzooms separately
+        assert_eq!(view_region.iter_sources(&[Some(2), None]).count(), 5);
+        assert_eq!(view_region.iter_sources(&[Some(1), Some(2)]).count(), 2);
+
+        // The ring around the 2x2 tiles in view
+        assert_eq!(view_region.iter_ring(1).count(), 12);
+        assert!(view_region
+            .iter_ring(1)
+            .all(|coords| !view_region.is_in_view(&coords)));
+    }
 }
@@ -0,0 +1,135 @@
+//! Embeds maps into applications which already own a wgpu device. Frames are rendered into
+//! texture views or recorded into command encoders of the application, which also presents them.
+
+use crate::error::Error;
+use crate::io::scheduler::ScheduleMethod;
+use crate::io::source_client::HTTPClient;
+use crate::map_state::MapState;
+use crate::render::render_state::RenderState;
+use crate::window::{MapWindow, MapWindowConfig, WindowSize};
+use crate::UninitializedMap;
+use std::sync::Arc;
+
+/// Configures the size of the texture views the map is rendered into.
+#[derive(Clone, Copy)]
+pub struct EmbeddedMapWindowConfig {
+    size: WindowSize,
+}
+
+impl EmbeddedMapWindowConfig {
+    pub fn new(size: WindowSize) -> Self {
+        Self { size }
+    }
+}
+
+impl MapWindowConfig for EmbeddedMapWindowConfig {
+    type MapWindow = EmbeddedMapWindow;
+}
+
+/// A window which is owned by the application and only describes the size of the rendered frames.
+pub struct EmbeddedMapWindow {
+    size: WindowSize,
+}
+
+impl MapWindow for EmbeddedMapWindow {
+    type EventLoop = ();
+    type MapWindowConfig = EmbeddedMapWindowConfig;
+
+    fn create(map_window_config: &Self::MapWindowConfig) -> Self {
+        Self {
+            size: map_window_config.size,
+        }
+    }
+
+    fn size(&self) -> WindowSize {
+        self.size
+    }
+}
+
+impl<SM, HC> UninitializedMap<EmbeddedMapWindowConfig, SM, HC>
+where
+    SM: ScheduleMethod,
+    HC: HTTPClient,
+{
+    /// Initializes the rendering with the device and queue of the application. The map renders
+    /// into texture views of the given `format`.
+    /// Fails if the device does not support the render settings.
+    pub fn initialize_with_device(
+        self,
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        format: wgpu::TextureFormat,
+    ) -> Result<EmbeddedMap<SM, HC>, Error> {
+        let window = EmbeddedMapWindow::create(&self.map_window_config);
+        let window_size = window.size();
+
+        let render_state = RenderState::initialize_with_device(
+            device,
+            queue,
+            format,
+            window_size.width(),
+            window_size.height(),
+            self.render_settings.clone(),
+        )?;
+
+        Ok(EmbeddedMap {
+            map_state: MapState::new(
+                self.map_window_config,
+                window_size,
+                Some(render_state),
+                self.scheduler,
+                self.source_client,
+                self.style,
+                self.initial_camera,
+                self.clock,
+                self.render_settings,
+                self.io_settings,
+                self.tile_error_handler,
+            ),
+        })
+    }
+}
+
+/// A map which renders into targets of the application which embeds it.
+pub struct EmbeddedMap<SM, HC>
+where
+    SM: ScheduleMethod,
+    HC: HTTPClient,
+{
+    map_state: MapState<EmbeddedMapWindowConfig, SM, HC>,
+}
+
+impl<SM, HC> EmbeddedMap<SM, HC>
+where
+    SM: ScheduleMethod,
+    HC: HTTPClient,
+{
+    pub fn map_state(&self) -> &MapState<EmbeddedMapWindowConfig, SM, HC> {
+        &self.map_state
+    }
+
+    pub fn map_state_mut(&mut self) -> &mut MapState<EmbeddedMapWindowConfig, SM, HC> {
+        &mut self.map_state
+    }
+
+    /// Resizes the map. The views passed afterwards must have the new size.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.map_state.resize(width, height)
+    }
+
+    /// Renders a frame into `view` and submits it to the queue. Presenting is left to the
+    /// application.
+    pub fn render(&mut self, view: &wgpu::TextureView) {
+        self.map_state.update();
+        self.map_state.render_state_mut().render_to(view);
+    }
+
+    /// Records a frame into `encoder` which renders into `view`. The application submits the
+    /// encoder, for example together with its own passes. In order to draw the map over the
+    /// contents of `view`, set [`crate::render::settings::RenderSettings::color_load_op`] to
+    /// [`wgpu::LoadOp::Load`].
+    pub fn encode(&mut self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        self.map_state.update();
+        self.map_state.render_state().encode_render(encoder, view);
+    }
+}
@@ -1,5 +1,7 @@
 //! Errors which can happen in various parts of the library.
 
+use crate::render::settings::SettingsError;
+use geozero::error::GeozeroError;
 use lyon::tessellation::TessellationError;
 use std::fmt;
 use std::fmt::Formatter;
@@ -9,12 +11,23 @@ use wgpu::SurfaceError;
 #[derive(Debug)]
 pub enum RenderError {
     Surface(wgpu::SurfaceError),
+    /// Reading back a headless frame failed.
+    Readback(wgpu::BufferAsyncError),
+    /// No adapter which matches the render settings is available.
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+    /// The render settings are not supported by the device.
+    Settings(SettingsError),
 }
 
 impl fmt::Display for RenderError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             RenderError::Surface(e) => write!(f, "{}", e),
+            RenderError::Readback(e) => write!(f, "{}", e),
+            RenderError::NoAdapter => write!(f, "no suitable graphics adapter found"),
+            RenderError::RequestDevice(e) => write!(f, "{}", e),
+            RenderError::Settings(e) => write!(f, "{}", e),
         }
     }
 }
@@ -26,6 +39,10 @@ impl RenderError {
                 SurfaceError::OutOfMemory => true,
                 _ => false,
             },
+            RenderError::Readback(_) => false,
+            RenderError::NoAdapter | RenderError::RequestDevice(_) | RenderError::Settings(_) => {
+                true
+            }
         }
     }
 }
@@ -35,22 +52,67 @@ impl RenderError {
 pub enum Error {
     Schedule,
     Network(String),
+    /// The requested resource does not exist, for example a tile outside of the coverage of a
+    /// source. Unlike for network errors, retrying the request does not help.
+    NotFound(String),
+    /// A tile or another resource is malformed and could not be decoded.
+    Decode(String),
+    /// Reading from or writing to a local database failed, for example an MBTiles file.
+    Storage(String),
+    /// The server rejected the request, for example because it is not authorized. Retrying the
+    /// request does not help.
+    Rejected(String),
     Tesselation(TessellationError),
     Render(RenderError),
 }
 
+impl Error {
+    /// Returns true if the failed operation might succeed if it is retried.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Error::Network(_))
+    }
+
+    /// Maps a failed HTTP response with the given `status` to an error. Only request timeouts,
+    /// rate limits and server errors are transient.
+    pub fn from_status(status: u16, message: String) -> Self {
+        match status {
+            404 | 410 => Error::NotFound(message),
+            408 | 429 | 500..=599 => Error::Network(message),
+            _ => Error::Rejected(message),
+        }
+    }
+}
+
 impl From<SurfaceError> for Error {
     fn from(e: SurfaceError) -> Self {
         Error::Render(RenderError::Surface(e))
     }
 }
 
+impl From<RenderError> for Error {
+    fn from(e: RenderError) -> Self {
+        Error::Render(e)
+    }
+}
+
 impl From<TessellationError> for Error {
     fn from(e: TessellationError) -> Self {
         Error::Tesselation(e)
     }
 }
 
+impl From<prost::DecodeError> for Error {
+    fn from(e: prost::DecodeError) -> Self {
+        Error::Decode(e.to_string())
+    }
+}
+
+impl From<GeozeroError> for Error {
+    fn from(e: GeozeroError) -> Self {
+        Error::Decode(e.to_string())
+    }
+}
+
 impl<T> From<SendError<T>> for Error {
     fn from(_e: SendError<T>) -> Self {
         Error::Schedule
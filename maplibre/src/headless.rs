@@ -0,0 +1,282 @@
+//! Renders maps without a window into an offscreen texture. The pixels of the rendered frames
+//! are read back and can be stored as PNG images.
+
+use crate::error::Error;
+use crate::io::scheduler::ScheduleMethod;
+use crate::io::source_client::HTTPClient;
+use crate::map_state::MapState;
+use crate::render::render_state::RenderState;
+use crate::window::{MapWindow, MapWindowConfig, WindowSize};
+use crate::UninitializedMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Configures the size of the rendered images.
+#[derive(Clone, Copy)]
+pub struct HeadlessMapWindowConfig {
+    size: WindowSize,
+    force_fallback_adapter: bool,
+}
+
+impl HeadlessMapWindowConfig {
+    pub fn new(size: WindowSize) -> Self {
+        Self {
+            size,
+            force_fallback_adapter: false,
+        }
+    }
+
+    /// Forces the usage of a software adapter. This allows rendering on machines without a GPU,
+    /// for example in CI.
+    pub fn with_force_fallback_adapter(mut self, force_fallback_adapter: bool) -> Self {
+        self.force_fallback_adapter = force_fallback_adapter;
+        self
+    }
+}
+
+impl MapWindowConfig for HeadlessMapWindowConfig {
+    type MapWindow = HeadlessMapWindow;
+}
+
+/// A window which is never shown and only describes the size of the rendered images.
+pub struct HeadlessMapWindow {
+    size: WindowSize,
+}
+
+impl MapWindow for HeadlessMapWindow {
+    type EventLoop = ();
+    type MapWindowConfig = HeadlessMapWindowConfig;
+
+    fn create(map_window_config: &Self::MapWindowConfig) -> Self {
+        Self {
+            size: map_window_config.size,
+        }
+    }
+
+    fn size(&self) -> WindowSize {
+        self.size
+    }
+}
+
+impl<SM, HC> UninitializedMap<HeadlessMapWindowConfig, SM, HC>
+where
+    SM: ScheduleMethod,
+    HC: HTTPClient,
+{
+    /// Initializes the rendering to an offscreen texture.
+    /// Fails if no suitable adapter is available or the render settings are not supported.
+    pub async fn initialize_headless(self) -> Result<HeadlessMap<SM, HC>, Error> {
+        let instance = wgpu::Instance::new(self.render_settings.backends);
+
+        let window = HeadlessMapWindow::create(&self.map_window_config);
+        let window_size = window.size();
+
+        let render_state = RenderState::initialize_headless(
+            instance,
+            window_size.width(),
+            window_size.height(),
+            self.map_window_config.force_fallback_adapter,
+            self.render_settings.clone(),
+        )
+        .await?;
+
+        Ok(HeadlessMap {
+            map_state: MapState::new(
+                self.map_window_config,
+                window_size,
+                Some(render_state),
+                self.scheduler,
+                self.source_client,
+                self.style,
+                self.initial_camera,
+                self.clock,
+                self.render_settings,
+                self.io_settings,
+                self.tile_error_handler,
+            ),
+        })
+    }
+}
+
+/// A map which renders to images instead of a window.
+pub struct HeadlessMap<SM, HC>
+where
+    SM: ScheduleMethod,
+    HC: HTTPClient,
+{
+    map_state: MapState<HeadlessMapWindowConfig, SM, HC>,
+}
+
+impl<SM, HC> HeadlessMap<SM, HC>
+where
+    SM: ScheduleMethod,
+    HC: HTTPClient,
+{
+    pub fn map_state(&self) -> &MapState<HeadlessMapWindowConfig, SM, HC> {
+        &self.map_state
+    }
+
+    pub fn map_state_mut(&mut self) -> &mut MapState<HeadlessMapWindowConfig, SM, HC> {
+        &mut self.map_state
+    }
+
+    /// Renders a single frame and returns its pixels. Tiles which are not yet loaded are missing
+    /// in the image.
+    pub async fn render_frame(&mut self) -> Result<RgbaImage, Error> {
+        self.map_state.update_and_redraw()?;
+        self.map_state
+            .render_state()
+            .read_frame()
+            .await
+            .expect("headless maps always render to an offscreen texture")
+    }
+
+    /// Renders frames until all tiles in view are loaded and returns the pixels of the last frame.
+    /// At most `max_frames` frames are rendered.
+    pub async fn render_snapshot(&mut self, max_frames: u64) -> Result<RgbaImage, Error> {
+        let mut image = self.render_frame().await?;
+        for _ in 1..max_frames {
+            if self.map_state.is_idle() {
+                break;
+            }
+            image = self.render_frame().await?;
+        }
+        Ok(image)
+    }
+}
+
+/// An image with 8-bit RGBA pixels which are stored row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RgbaImage {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl RgbaImage {
+    pub fn new(width: u32, height: u32, data: Vec<u8>) -> Self {
+        assert_eq!(
+            data.len(),
+            width as usize * height as usize * 4,
+            "image data does not match the dimensions"
+        );
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_raw(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Returns the RGBA components of the pixel at the given position.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let offset = (y as usize * self.width as usize + x as usize) * 4;
+        let mut pixel = [0; 4];
+        pixel.copy_from_slice(&self.data[offset..offset + 4]);
+        pixel
+    }
+
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.data)
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), png::EncodingError> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+
+    /// Reads a PNG image. Images without an alpha channel or with a different bit depth are
+    /// converted to 8-bit RGBA.
+    pub fn read_png<R: Read>(reader: R) -> Result<Self, png::DecodingError> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+        buffer.truncate(info.buffer_size());
+
+        let data = match info.color_type {
+            png::ColorType::Rgba => buffer,
+            png::ColorType::Rgb => buffer
+                .chunks(3)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buffer
+                .chunks(2)
+                .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+                .collect(),
+            png::ColorType::Grayscale => buffer
+                .iter()
+                .flat_map(|value| [*value, *value, *value, 255])
+                .collect(),
+            // Indexed images are expanded to RGB or RGBA
+            png::ColorType::Indexed => unreachable!(),
+        };
+
+        Ok(Self::new(info.width, info.height, data))
+    }
+
+    pub fn load_png<P: AsRef<Path>>(path: P) -> Result<Self, png::DecodingError> {
+        Self::read_png(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::headless::RgbaImage;
+
+    #[test]
+    fn test_png_roundtrip() {
+        let data = (0..2 * 3 * 4).map(|i| i as u8).collect::<Vec<_>>();
+        let image = RgbaImage::new(2, 3, data.clone());
+        assert_eq!(image.pixel(1, 2), [20, 21, 22, 23]);
+
+        let mut png = Vec::new();
+        image.write_png(&mut png).unwrap();
+
+        let decoder = png::Decoder::new(png.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut decoded = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut decoded).unwrap();
+
+        assert_eq!((info.width, info.height), (2, 3));
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        assert_eq!(decoded, data);
+
+        assert_eq!(RgbaImage::read_png(png.as_slice()).unwrap(), image);
+    }
+
+    #[test]
+    fn test_read_rgb_png() {
+        let mut png = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut png, 2, 1);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[1, 2, 3, 4, 5, 6]).unwrap();
+        }
+
+        let image = RgbaImage::read_png(png.as_slice()).unwrap();
+        assert_eq!(image.data(), &[1, 2, 3, 255, 4, 5, 6, 255]);
+    }
+}
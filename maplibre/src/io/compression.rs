@@ -0,0 +1,149 @@
+//! Detection and decompression of compressed tile payloads.
+//!
+//! Many tile servers and MBTiles files deliver compressed tiles without a `Content-Encoding`
+//! header, such that the payload is still compressed when it reaches the worker.
+
+use crate::error::Error;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use std::borrow::Cow;
+use std::io::Read;
+
+/// The magic bytes at the start of gzip compressed data.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// The first byte of a vector tile, which is the key of a layer.
+const MVT_LAYER_KEY: u8 = 0x1a;
+/// The buffer size of the brotli decoder.
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// The compression of a tile payload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zlib,
+    /// Brotli streams do not start with magic bytes. Payloads which are neither gzip, zlib nor
+    /// look like a vector tile are assumed to be brotli compressed.
+    Brotli,
+    None,
+}
+
+impl Compression {
+    /// Sniffs the compression of `data`.
+    pub fn detect(data: &[u8]) -> Self {
+        match data {
+            [] => Compression::None,
+            _ if data.starts_with(&GZIP_MAGIC) => Compression::Gzip,
+            // The compression method is deflate and the header checksum is valid
+            [cmf, flg, ..]
+                if cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0 =>
+            {
+                Compression::Zlib
+            }
+            [MVT_LAYER_KEY, ..] => Compression::None,
+            _ => Compression::Brotli,
+        }
+    }
+}
+
+/// Reads at most one byte more than `max_size` from `reader`, such that decompression bombs are
+/// detected without decompressing them completely.
+fn read_limited<R: Read>(reader: R, max_size: usize) -> std::io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    reader
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Decompresses `data` if it is compressed. The decompressed data must not be larger than
+/// `max_size` bytes.
+pub fn decompress(data: &[u8], max_size: usize) -> Result<Cow<'_, [u8]>, Error> {
+    let decompressed = match Compression::detect(data) {
+        Compression::Gzip => read_limited(GzDecoder::new(data), max_size),
+        Compression::Zlib => read_limited(ZlibDecoder::new(data), max_size),
+        Compression::Brotli => {
+            let decoder = brotli_decompressor::Decompressor::new(data, BROTLI_BUFFER_SIZE);
+            match read_limited(decoder, max_size) {
+                Ok(decompressed) => Ok(decompressed),
+                // The guess was wrong, the tile decoder reports whatever is wrong with the data
+                Err(_) => return Ok(Cow::Borrowed(data)),
+            }
+        }
+        Compression::None => return Ok(Cow::Borrowed(data)),
+    }
+    .map_err(|e| Error::Decode(e.to_string()))?;
+
+    if decompressed.len() > max_size {
+        return Err(Error::Decode(format!(
+            "decompressed tile exceeds the maximum size of {} bytes",
+            max_size
+        )));
+    }
+    Ok(Cow::Owned(decompressed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress, Compression};
+    use crate::error::Error;
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use std::io::Write;
+
+    /// A vector tile with an empty layer named water.
+    const TILE: [u8; 9] = [0x1a, 0x07, 0x0a, 0x05, b'w', b'a', b't', b'e', b'r'];
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn brotli(data: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        brotli::CompressorWriter::new(&mut compressed, 4096, 11, 22)
+            .write_all(data)
+            .unwrap();
+        compressed
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Compression::detect(&TILE), Compression::None);
+        assert_eq!(Compression::detect(&[]), Compression::None);
+        assert_eq!(Compression::detect(&gzip(&TILE)), Compression::Gzip);
+        assert_eq!(Compression::detect(&zlib(&TILE)), Compression::Zlib);
+        assert_eq!(Compression::detect(&brotli(&TILE)), Compression::Brotli);
+    }
+
+    #[test]
+    fn test_decompress() {
+        for compressed in [TILE.to_vec(), gzip(&TILE), zlib(&TILE), brotli(&TILE)] {
+            assert_eq!(decompress(&compressed, 1024).unwrap().as_ref(), &TILE);
+        }
+
+        // Data which is not brotli is passed on unchanged
+        let garbage = [0x07, 0x0a, 0x0d];
+        assert_eq!(decompress(&garbage, 1024).unwrap().as_ref(), &garbage);
+    }
+
+    #[test]
+    fn test_decompression_bomb() {
+        let bomb = vec![0; 1024 * 1024];
+        for compressed in [gzip(&bomb), zlib(&bomb), brotli(&bomb)] {
+            assert!(compressed.len() < 10 * 1024);
+            assert!(matches!(
+                decompress(&compressed, 1024 * 1024 - 1),
+                Err(Error::Decode(_))
+            ));
+            assert_eq!(
+                decompress(&compressed, 1024 * 1024).unwrap().len(),
+                bomb.len()
+            );
+        }
+    }
+}
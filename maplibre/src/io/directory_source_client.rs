@@ -0,0 +1,377 @@
+//! Reads tiles from a local directory.
+
+use crate::coords::WorldTileCoords;
+use crate::error::Error;
+use crate::style::source::TileAddressingScheme;
+use crate::tilejson::{TileJSON, VectorLayer};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The name of the file which describes the tiles of a directory. It is either a TileJSON document
+/// or the metadata of an MBTiles file as written by `maplibre_build_tools::mbtiles::extract`.
+const METADATA_FILE: &str = "metadata.json";
+
+/// Reads tiles from a directory in which tiles are stored as `{z}/{x}/{y}.pbf`, using the XYZ
+/// scheme. This is the layout which is created by `maplibre_build_tools::mbtiles::extract`.
+/// Other layouts are read using a path template, for example from a `file://` URL of a source.
+#[derive(Clone)]
+pub struct DirectorySourceClient {
+    /// A path which contains the place holders `{z}`, `{x}` and `{y}`.
+    template: String,
+    scheme: TileAddressingScheme,
+}
+
+/// The value of the `json` key of MBTiles metadata, which describes the layers of vector tiles.
+#[derive(Deserialize)]
+struct MbtilesJson {
+    vector_layers: Option<Vec<VectorLayer>>,
+}
+
+/// Decodes the percent-encoded bytes of a URL.
+fn percent_decode(input: &str) -> Result<String, Error> {
+    let invalid = || Error::Decode(format!("invalid percent-encoding in {}", input));
+
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut iter = input.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [
+                iter.next().ok_or_else(invalid)?,
+                iter.next().ok_or_else(invalid)?,
+            ];
+            let hex = std::str::from_utf8(&hex).map_err(|_| invalid())?;
+            bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+/// Reads a file, a missing file is reported as [`Error::NotFound`] and other failures as
+/// [`Error::Storage`].
+async fn read_file(path: &Path) -> Result<Vec<u8>, Error> {
+    tokio::fs::read(path).await.map_err(|e| {
+        let message = format!("failed to read {:?}: {}", path, e);
+        if e.kind() == std::io::ErrorKind::NotFound {
+            Error::NotFound(message)
+        } else {
+            Error::Storage(message)
+        }
+    })
+}
+
+impl DirectorySourceClient {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let template = path.as_ref().join("{z}").join("{x}").join("{y}.pbf");
+        Self::from_template(template.to_string_lossy())
+    }
+
+    /// Reads the tiles from the paths which are created by replacing the place holders `{z}`,
+    /// `{x}` and `{y}` in `template`.
+    pub fn from_template<S: Into<String>>(template: S) -> Self {
+        Self {
+            template: template.into(),
+            scheme: TileAddressingScheme::XYZ,
+        }
+    }
+
+    /// Reads the tiles from a `file://` URL template like `file:///data/tiles/{z}/{x}/{y}.pbf`.
+    pub fn from_url(url: &str) -> Result<Self, Error> {
+        let path = url
+            .strip_prefix("file://")
+            .ok_or_else(|| Error::Decode(format!("{} is not a file:// URL", url)))?;
+        // The host is either empty or localhost
+        let path = path.strip_prefix("localhost").unwrap_or(path);
+        let path = percent_decode(path)?;
+
+        // Windows paths look like file:///C:/tiles
+        let path = match path.as_bytes() {
+            [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => path[1..].to_string(),
+            _ => path,
+        };
+        Ok(Self::from_template(path))
+    }
+
+    /// Resolves a relative path template against `base_dir`. Absolute templates are kept.
+    pub fn with_base_dir<P: AsRef<Path>>(mut self, base_dir: P) -> Self {
+        self.template = base_dir
+            .as_ref()
+            .join(&self.template)
+            .to_string_lossy()
+            .into_owned();
+        self
+    }
+
+    /// Opens the directory at `path`. If the directory has a `metadata.json`, then the scheme and
+    /// a `file://` tile template of it are used, see [`DirectorySourceClient::tile_json`].
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let client = Self::new(&path);
+        let tile_json = match client.tile_json().await {
+            Ok(tile_json) => tile_json,
+            Err(Error::NotFound(_)) => return Ok(client),
+            Err(e) => return Err(e),
+        };
+
+        let client = match tile_json.tiles.first() {
+            Some(url)
+                if url.starts_with("file://") && *url != format!("file://{}", client.template) =>
+            {
+                Self::from_url(url)?.with_base_dir(&path)
+            }
+            _ => client,
+        };
+        Ok(match tile_json.scheme.as_deref() {
+            Some("tms") => client.with_scheme(TileAddressingScheme::TMS),
+            _ => client.with_scheme(TileAddressingScheme::XYZ),
+        })
+    }
+
+    /// Sets the scheme of the tile coordinates in the paths.
+    pub fn with_scheme(mut self, scheme: TileAddressingScheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// The directory which contains all tiles and the `metadata.json`.
+    fn root(&self) -> PathBuf {
+        match self.template.find('{') {
+            Some(index) if self.template[..index].ends_with(['/', '\\'].as_ref()) => {
+                PathBuf::from(&self.template[..index])
+            }
+            Some(index) => Path::new(&self.template[..index])
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            None => PathBuf::from(&self.template),
+        }
+    }
+
+    /// Reads the `metadata.json` of the directory as TileJSON. The metadata of an MBTiles file is
+    /// converted to TileJSON, such that the tiles are read from this directory.
+    pub async fn tile_json(&self) -> Result<TileJSON, Error> {
+        let path = self.root().join(METADATA_FILE);
+        let data = read_file(&path).await?;
+        let invalid = |e: serde_json::Error| Error::Decode(format!("invalid {:?}: {}", path, e));
+
+        let value: serde_json::Value = serde_json::from_slice(&data).map_err(invalid)?;
+        if value.get("tilejson").is_some() {
+            let mut tile_json: TileJSON = serde_json::from_value(value).map_err(invalid)?;
+            if tile_json.tiles.is_empty() {
+                tile_json.tiles = vec![format!("file://{}", self.template)];
+            }
+            return Ok(tile_json);
+        }
+
+        // MBTiles metadata values are strings, but some tools write numbers
+        let metadata: HashMap<String, serde_json::Value> =
+            serde_json::from_value(value).map_err(invalid)?;
+        let get = |key: &str| {
+            metadata.get(key).map(|value| match value {
+                serde_json::Value::String(value) => value.clone(),
+                value => value.to_string(),
+            })
+        };
+        let vector_layers = match get("json") {
+            Some(json) => {
+                serde_json::from_str::<MbtilesJson>(&json)
+                    .map_err(invalid)?
+                    .vector_layers
+            }
+            None => None,
+        };
+        let numbers = |key: &str| -> Option<Vec<f64>> {
+            get(key)?
+                .split(',')
+                .map(|number| number.trim().parse().ok())
+                .collect()
+        };
+
+        Ok(TileJSON {
+            tilejson: "2.2.0".to_string(),
+            id: None,
+            name: get("name"),
+            description: get("description"),
+            version: get("version"),
+            attribution: get("attribution"),
+            template: None,
+            legend: None,
+            scheme: Some(
+                match self.scheme {
+                    TileAddressingScheme::XYZ => "xyz",
+                    TileAddressingScheme::TMS => "tms",
+                }
+                .to_string(),
+            ),
+            tiles: vec![format!("file://{}", self.template)],
+            grids: None,
+            data: None,
+            minzoom: get("minzoom").and_then(|zoom| zoom.parse().ok()),
+            maxzoom: get("maxzoom").and_then(|zoom| zoom.parse().ok()),
+            bounds: numbers("bounds")
+                .map(|bounds| bounds.into_iter().map(|bound| bound as f32).collect()),
+            center: numbers("center"),
+            vector_layers,
+        })
+    }
+
+    pub async fn fetch(&self, coords: &WorldTileCoords) -> Result<Vec<u8>, Error> {
+        let tile_coords = coords
+            .into_tile(self.scheme.clone())
+            .ok_or_else(|| Error::NotFound(format!("tile {} is out of bounds", coords)))?;
+
+        let tile_path = self
+            .template
+            .replace("{z}", &tile_coords.z.to_string())
+            .replace("{x}", &tile_coords.x.to_string())
+            .replace("{y}", &tile_coords.y.to_string());
+
+        read_file(Path::new(&tile_path)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DirectorySourceClient;
+    use crate::coords::WorldTileCoords;
+    use crate::error::Error;
+    use crate::io::source_client::SourceClient;
+    use crate::platform::http_client::ReqwestHttpClient;
+    use crate::style::source::{TileAddressingScheme, VectorSource};
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let path = std::env::temp_dir().join(format!("maplibre-test-tiles-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(path.join("1/1")).unwrap();
+        std::fs::write(path.join("1/1/0.pbf"), [1, 2, 3]).unwrap();
+
+        let client = DirectorySourceClient::new(&path);
+        let tile: WorldTileCoords = (1, 0, 1).into();
+        assert_eq!(client.fetch(&tile).await.unwrap(), vec![1, 2, 3]);
+        let tile: WorldTileCoords = (0, 0, 1).into();
+        assert!(matches!(client.fetch(&tile).await, Err(Error::NotFound(_))));
+        let tile: WorldTileCoords = (5, 5, 1).into();
+        assert!(matches!(client.fetch(&tile).await, Err(Error::NotFound(_))));
+
+        // A tile which can not be read is not missing
+        std::fs::create_dir_all(path.join("1/0/1.pbf")).unwrap();
+        let tile: WorldTileCoords = (0, 1, 1).into();
+        assert!(matches!(client.fetch(&tile).await, Err(Error::Storage(_))));
+
+        // The row is flipped in the TMS scheme
+        let url = format!("file://{}/{{z}}/{{x}}/{{y}}.pbf", path.to_str().unwrap());
+        let client = DirectorySourceClient::from_url(&url)
+            .unwrap()
+            .with_scheme(TileAddressingScheme::TMS);
+        let tile: WorldTileCoords = (1, 1, 1).into();
+        assert_eq!(client.fetch(&tile).await.unwrap(), vec![1, 2, 3]);
+
+        // Style sources with a file:// URL are read from the directory
+        let source = VectorSource {
+            attribution: None,
+            bounds: None,
+            maxzoom: None,
+            minzoom: None,
+            scheme: Some(TileAddressingScheme::TMS),
+            tiles: Some(vec![url]),
+            url: None,
+        };
+        let client = SourceClient::from_source(ReqwestHttpClient::new(None), &source);
+        assert!(matches!(client, SourceClient::Directory(_)));
+        assert_eq!(client.fetch(&tile).await.unwrap(), vec![1, 2, 3]);
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open() {
+        let path = std::env::temp_dir().join(format!("maplibre-test-open-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(path.join("tiles")).unwrap();
+        std::fs::write(path.join("tiles/1-1-0.mvt"), [1, 2, 3]).unwrap();
+
+        // language=JSON
+        std::fs::write(
+            path.join("metadata.json"),
+            r#"{"tilejson": "2.2.0", "tiles": ["file://tiles/{z}-{x}-{y}.mvt"], "scheme": "tms"}"#,
+        )
+        .unwrap();
+        let client = DirectorySourceClient::open(&path).await.unwrap();
+        let tile: WorldTileCoords = (1, 1, 1).into();
+        assert_eq!(client.fetch(&tile).await.unwrap(), vec![1, 2, 3]);
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_from_url() {
+        let client = DirectorySourceClient::from_url("file:///my%20tiles/{z}/{x}/{y}.pbf").unwrap();
+        assert_eq!(client.template, "/my tiles/{z}/{x}/{y}.pbf");
+        let client =
+            DirectorySourceClient::from_url("file://localhost/tiles/{z}-{x}-{y}.mvt").unwrap();
+        assert_eq!(client.template, "/tiles/{z}-{x}-{y}.mvt");
+        assert_eq!(client.root(), std::path::PathBuf::from("/tiles"));
+        let client = DirectorySourceClient::from_url("file:///C:/tiles/{z}/{x}/{y}.pbf").unwrap();
+        assert_eq!(client.template, "C:/tiles/{z}/{x}/{y}.pbf");
+
+        // Invalid URLs are not retried
+        assert!(matches!(
+            DirectorySourceClient::from_url("https://example.com/{z}/{x}/{y}.pbf"),
+            Err(Error::Decode(_))
+        ));
+        assert!(matches!(
+            DirectorySourceClient::from_url("file:///tiles%2"),
+            Err(Error::Decode(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tile_json() {
+        let path =
+            std::env::temp_dir().join(format!("maplibre-test-metadata-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        let client = DirectorySourceClient::new(&path);
+        assert!(matches!(client.tile_json().await, Err(Error::NotFound(_))));
+
+        // language=JSON
+        std::fs::write(
+            path.join("metadata.json"),
+            r#"{"name": "munich", "format": "pbf", "minzoom": "0", "maxzoom": 14,
+                "bounds": "11.4,48.0,11.8,48.3", "center": "11.58,48.14,12",
+                "json": "{\"vector_layers\": [{\"id\": \"water\", \"fields\": {\"class\": \"String\"}}]}"}"#,
+        )
+        .unwrap();
+        let tile_json = client.tile_json().await.unwrap();
+        assert_eq!(tile_json.name.as_deref(), Some("munich"));
+        assert_eq!((tile_json.minzoom, tile_json.maxzoom), (Some(0), Some(14)));
+        assert_eq!(tile_json.bounds, Some(vec![11.4, 48.0, 11.8, 48.3]));
+        assert_eq!(tile_json.center, Some(vec![11.58, 48.14, 12.0]));
+        assert_eq!(tile_json.scheme.as_deref(), Some("xyz"));
+        let vector_layers = tile_json.vector_layers.unwrap();
+        assert_eq!(vector_layers.len(), 1);
+        assert_eq!(vector_layers[0].id, "water");
+        assert_eq!(vector_layers[0].fields["class"], "String");
+        assert_eq!(
+            tile_json.tiles,
+            vec![format!("file://{}", path.join("{z}/{x}/{y}.pbf").display())]
+        );
+
+        // language=JSON
+        std::fs::write(
+            path.join("metadata.json"),
+            r#"{"tilejson": "2.2.0", "tiles": ["https://example.com/{z}/{x}/{y}.pbf"], "maxzoom": 4}"#,
+        )
+        .unwrap();
+        let tile_json = client.tile_json().await.unwrap();
+        assert_eq!(tile_json.maxzoom, Some(4));
+        assert_eq!(tile_json.tiles, vec!["https://example.com/{z}/{x}/{y}.pbf"]);
+
+        std::fs::write(path.join("metadata.json"), "[]").unwrap();
+        assert!(matches!(client.tile_json().await, Err(Error::Decode(_))));
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
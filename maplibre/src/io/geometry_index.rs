@@ -32,6 +32,13 @@ impl GeometryIndex {
             .and_then(|key| self.index.insert(key, tile_index));
     }
 
+    /// Removes the index of a tile which is no longer loaded.
+    pub fn remove_tile(&mut self, coords: &WorldTileCoords) {
+        coords
+            .build_quad_key()
+            .and_then(|key| self.index.remove(&key));
+    }
+
     pub fn query_point(
         &self,
         world_coords: &WorldCoords,
@@ -240,8 +247,7 @@ impl PropertyProcessor for IndexProcessor {
         value: &ColumnValue,
     ) -> Result<bool, GeozeroError> {
         self.properties
-            .as_mut()
-            .unwrap()
+            .get_or_insert_with(HashMap::new)
             .insert(name.to_string(), value.to_string());
         Ok(true)
     }
@@ -279,18 +285,22 @@ impl FeatureProcessor for IndexProcessor {
     }
     /// End of feature geometry processing.
     fn geometry_end(&mut self) -> Result<(), GeozeroError> {
-        let geometry = self.geo_writer.geometry().cloned().unwrap();
-
-        match geometry {
-            Geometry::Polygon(polygon) => self.geometries.push(
-                IndexedGeometry::from_polygon(polygon, self.properties.take().unwrap()).unwrap(),
-            ),
-            Geometry::LineString(linestring) => self.geometries.push(
-                IndexedGeometry::from_linestring(linestring, self.properties.take().unwrap())
-                    .unwrap(),
-            ),
-            _ => {}
+        let geometry = self
+            .geo_writer
+            .geometry()
+            .cloned()
+            .ok_or(GeozeroError::GeometryFormat)?;
+        let properties = self.properties.take().unwrap_or_default();
+
+        // Geometries without points can not be indexed and are skipped
+        let indexed = match geometry {
+            Geometry::Polygon(polygon) => IndexedGeometry::from_polygon(polygon, properties),
+            Geometry::LineString(linestring) => {
+                IndexedGeometry::from_linestring(linestring, properties)
+            }
+            _ => None,
         };
+        self.geometries.extend(indexed);
 
         Ok(())
     }
@@ -0,0 +1,133 @@
+//! Caching of HTTP responses according to their `Cache-Control`, `Expires`, `ETag` and
+//! `Last-Modified` headers.
+
+use async_trait::async_trait;
+
+/// A response which is stored in a [`HttpCache`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedResponse {
+    pub data: Vec<u8>,
+    /// The `ETag` header, which is sent as `If-None-Match` when the response is revalidated.
+    pub etag: Option<String>,
+    /// The `Last-Modified` header, which is sent as `If-Modified-Since` when the response is
+    /// revalidated.
+    pub last_modified: Option<String>,
+    /// The time in seconds since the Unix epoch until which the response can be used without
+    /// revalidating it.
+    pub expires: u64,
+}
+
+impl CachedResponse {
+    pub fn is_fresh(&self, now: u64) -> bool {
+        now < self.expires
+    }
+
+    /// Whether the response can be revalidated with a conditional request.
+    pub fn has_validator(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+}
+
+/// How a response may be cached, see [`CachePolicy::from_headers`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CachePolicy {
+    /// Set if the response may be stored at all.
+    pub storable: bool,
+    /// The time in seconds since the Unix epoch until which the response is fresh.
+    pub expires: u64,
+}
+
+impl CachePolicy {
+    /// Determines the caching policy from the `Cache-Control` header and the already parsed
+    /// `Expires` header. `max-age` takes precedence over `Expires`. Responses without any
+    /// freshness information are stored, but revalidated before they are used.
+    pub fn from_headers(cache_control: Option<&str>, expires: Option<u64>, now: u64) -> Self {
+        let mut max_age = None;
+        let mut no_cache = false;
+
+        for directive in cache_control.unwrap_or_default().split(',') {
+            let directive = directive.trim().to_ascii_lowercase();
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name.trim().to_string(), Some(value.trim().to_string())),
+                None => (directive, None),
+            };
+
+            match name.as_str() {
+                "no-store" => {
+                    return CachePolicy {
+                        storable: false,
+                        expires: now,
+                    }
+                }
+                "no-cache" => no_cache = true,
+                "max-age" => {
+                    max_age = value.and_then(|value| value.trim_matches('"').parse::<u64>().ok())
+                }
+                _ => {}
+            }
+        }
+
+        let expires = if no_cache {
+            now
+        } else if let Some(max_age) = max_age {
+            now.saturating_add(max_age)
+        } else {
+            expires.unwrap_or(now)
+        };
+
+        CachePolicy {
+            storable: true,
+            expires,
+        }
+    }
+}
+
+/// Stores HTTP responses by their URL. Implementations are expected to bound their size and to
+/// treat unreadable or corrupted entries as missing, such that a broken cache never breaks the
+/// loading of tiles.
+///
+/// On the web platform the futures do not need to be thread-safe, see [`crate::io::source_client::HTTPClient`].
+#[cfg_attr(feature = "no-thread-safe-futures", async_trait(?Send))]
+#[cfg_attr(not(feature = "no-thread-safe-futures"), async_trait)]
+pub trait HttpCache: Send + Sync + 'static {
+    async fn get(&self, url: &str) -> Option<CachedResponse>;
+
+    async fn put(&self, url: &str, response: CachedResponse);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CachePolicy, CachedResponse};
+
+    #[test]
+    fn test_cache_policy() {
+        let now = 1000;
+        assert_eq!(
+            CachePolicy::from_headers(Some("public, max-age=60"), Some(5000), now),
+            CachePolicy {
+                storable: true,
+                expires: 1060
+            }
+        );
+        assert_eq!(
+            CachePolicy::from_headers(None, Some(5000), now).expires,
+            5000
+        );
+        assert_eq!(CachePolicy::from_headers(None, None, now).expires, now);
+        assert_eq!(
+            CachePolicy::from_headers(Some("No-Cache, max-age=60"), None, now).expires,
+            now
+        );
+        assert!(!CachePolicy::from_headers(Some("no-store"), None, now).storable);
+
+        let response = CachedResponse {
+            data: vec![],
+            etag: None,
+            last_modified: None,
+            expires: 1060,
+        };
+        assert!(response.is_fresh(1059));
+        assert!(!response.is_fresh(1060));
+        assert!(!response.has_validator());
+    }
+}
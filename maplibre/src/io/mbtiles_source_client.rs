@@ -0,0 +1,131 @@
+//! Reads tiles from a local MBTiles file.
+
+use crate::coords::WorldTileCoords;
+use crate::error::Error;
+use crate::style::source::TileAddressingScheme;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::Storage(err.to_string())
+    }
+}
+
+/// Reads tiles from an [MBTiles](https://github.com/mapbox/mbtiles-spec) file, which is a SQLite
+/// database. Tiles in MBTiles files are addressed using the TMS scheme. Tiles are returned as they
+/// are stored, they are usually gzip compressed and decompressed when they are processed.
+#[derive(Clone)]
+pub struct MbtilesSourceClient {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl MbtilesSourceClient {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        if !path.is_file() {
+            return Err(Error::NotFound(format!(
+                "MBTiles file {:?} not found",
+                path
+            )));
+        }
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(Connection::open(path)?)),
+        })
+    }
+
+    pub async fn fetch(&self, coords: &WorldTileCoords) -> Result<Vec<u8>, Error> {
+        let tile_coords = coords
+            .into_tile(TileAddressingScheme::TMS)
+            .ok_or_else(|| Error::NotFound(format!("tile {} is out of bounds", coords)))?;
+
+        // SQLite blocks, therefore the query must not run on the threads of the async runtime
+        let connection = self.connection.clone();
+        let data = tokio::task::spawn_blocking(move || -> Result<Option<Vec<u8>>, Error> {
+            let connection = connection
+                .lock()
+                .map_err(|_| Error::Storage("MBTiles connection is poisoned".to_string()))?;
+            // language=SQL
+            let data = connection
+                .query_row(
+                    "SELECT tile_data FROM tiles
+                        WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3;",
+                    params![tile_coords.z, tile_coords.x, tile_coords.y],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(data)
+        })
+        .await
+        .map_err(|e| Error::Storage(e.to_string()))??;
+
+        data.ok_or_else(|| Error::NotFound(format!("tile {} not found in MBTiles file", coords)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MbtilesSourceClient;
+    use crate::coords::WorldTileCoords;
+    use crate::error::Error;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use rusqlite::{params, Connection};
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let path =
+            std::env::temp_dir().join(format!("maplibre-test-{}.mbtiles", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[1, 2, 3]).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE tiles (zoom_level integer, tile_column integer, tile_row integer, tile_data blob);",
+            )
+            .unwrap();
+        // The row is flipped, this is the tile x=1, y=0 at z=1
+        connection
+            .execute(
+                "INSERT INTO tiles VALUES (?1, ?2, ?3, ?4);",
+                params![1, 1, 1, compressed],
+            )
+            .unwrap();
+        connection
+            .execute(
+                "INSERT INTO tiles VALUES (?1, ?2, ?3, ?4);",
+                params![1, 0, 0, vec![4u8, 5, 6]],
+            )
+            .unwrap();
+        drop(connection);
+
+        let client = MbtilesSourceClient::open(&path).unwrap();
+        let tile: WorldTileCoords = (1, 0, 1).into();
+        assert_eq!(client.fetch(&tile).await.unwrap(), compressed);
+        let tile: WorldTileCoords = (0, 1, 1).into();
+        assert_eq!(client.fetch(&tile).await.unwrap(), vec![4, 5, 6]);
+        let tile: WorldTileCoords = (0, 0, 1).into();
+        assert!(matches!(client.fetch(&tile).await, Err(Error::NotFound(_))));
+        let out_of_bounds: WorldTileCoords = (5, 5, 1).into();
+        assert!(matches!(
+            client.fetch(&out_of_bounds).await,
+            Err(Error::NotFound(_))
+        ));
+
+        // A file which is not a SQLite database is a permanent error
+        std::fs::write(&path, [0u8; 512]).unwrap();
+        let client = MbtilesSourceClient::open(&path).unwrap();
+        let error = client.fetch(&tile).await.unwrap_err();
+        assert!(matches!(error, Error::Storage(_)));
+        assert!(!error.is_transient());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,22 +1,40 @@
 //! Handles IO related processing as well as multithreading.
 
 use crate::coords::WorldTileCoords;
+use crate::error::Error;
 
 use crate::render::ShaderVertex;
 use crate::tessellation::{IndexDataType, OverAlignedVertexBuffer};
 
 use geozero::mvt::tile;
+use prost::Message;
 use std::collections::HashSet;
 use std::fmt;
+use std::mem::size_of;
 
+pub mod compression;
 pub mod scheduler;
 pub mod source_client;
 pub mod static_tile_fetcher;
 
 pub mod geometry_index;
+pub mod http_cache;
+pub mod prefetch;
+pub mod settings;
 pub mod shared_thread_state;
 pub mod tile_cache;
+pub mod tile_errors;
 pub mod tile_request_state;
+pub mod tile_validation;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod directory_source_client;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod mbtiles_source_client;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod offline_manager;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod offline_store;
 
 /// Contains a `Tile` if the fetch was successful otherwise `Unavailable`.
 pub enum TileFetchResult {
@@ -46,6 +64,8 @@ impl fmt::Debug for TileFetchResult {
 pub enum TessellateMessage {
     Tile(TileTessellateMessage),
     Layer(LayerTessellateMessage),
+    /// The tile could not be loaded. It is retried if the error is transient.
+    TileFailed(TileFailedMessage),
 }
 
 ///  The result of the tessellation of a tile.
@@ -54,6 +74,13 @@ pub struct TileTessellateMessage {
     pub coords: WorldTileCoords,
 }
 
+/// A tile request which failed to fetch or decode the tile.
+pub struct TileFailedMessage {
+    pub request_id: TileRequestID,
+    pub coords: WorldTileCoords,
+    pub error: Error,
+}
+
 /// `TessellatedLayer` contains the result of the tessellation for a specific layer, otherwise
 /// `UnavailableLayer` if the layer doesn't exist.
 pub enum LayerTessellateMessage {
@@ -90,6 +117,26 @@ impl LayerTessellateMessage {
             LayerTessellateMessage::TessellatedLayer { layer_data, .. } => &layer_data.name,
         }
     }
+
+    /// Estimates the memory in bytes which is used by this message. The size of the raw layer is
+    /// approximated by its encoded size.
+    pub fn size_bytes(&self) -> usize {
+        size_of::<Self>()
+            + match self {
+                LayerTessellateMessage::UnavailableLayer { layer_name, .. } => layer_name.len(),
+                LayerTessellateMessage::TessellatedLayer {
+                    buffer,
+                    feature_indices,
+                    layer_data,
+                    ..
+                } => {
+                    buffer.buffer.vertices.capacity() * size_of::<ShaderVertex>()
+                        + buffer.buffer.indices.capacity() * size_of::<IndexDataType>()
+                        + feature_indices.capacity() * size_of::<u32>()
+                        + layer_data.encoded_len()
+                }
+            }
+    }
 }
 
 /// A request for a tile at the given coordinates and in the given layers.
@@ -0,0 +1,779 @@
+//! Downloads regions of the map into an [`OfflineStore`], such that they can be shown without a
+//! network connection.
+
+use crate::coords::{LngLat, LngLatBounds, WorldTileCoords};
+use crate::error::Error;
+use crate::io::offline_store::{OfflineError, OfflineStore};
+use crate::io::source_client::{HTTPClient, ResourceKind, SourceTiles};
+use crate::style::source::Source;
+use crate::style::Style;
+use futures::future::join_all;
+use geo::prelude::*;
+use geo_types::{LineString, Polygon, Rect};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The number of glyphs which are stored in a single glyph resource.
+const GLYPH_RANGE_SIZE: u32 = 256;
+/// Glyph resources cover the unicode range `0..=65535`.
+const GLYPH_RANGES: u32 = 65536 / GLYPH_RANGE_SIZE;
+
+/// The area of an offline region.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum OfflineRegionGeometry {
+    Bounds(LngLatBounds),
+    /// The exterior ring of a polygon. Holes are not supported.
+    Polygon(Vec<LngLat>),
+}
+
+impl OfflineRegionGeometry {
+    /// Returns all tiles at the zoom level `z` which intersect with the geometry. The tiles are
+    /// computed lazily, such that large regions can be counted without storing their tiles.
+    pub fn tile_cover(&self, z: u8) -> Box<dyn Iterator<Item = WorldTileCoords> + '_> {
+        match self {
+            OfflineRegionGeometry::Bounds(bounds) => Box::new(bounds.tile_cover(z)),
+            OfflineRegionGeometry::Polygon(points) => {
+                let bounds = match LngLatBounds::from_points(points.iter().copied()) {
+                    Some(bounds) => bounds,
+                    None => return Box::new(std::iter::empty()),
+                };
+
+                // Intersect in Web Mercator such that the edges of tiles are straight lines
+                let polygon = Polygon::new(
+                    LineString::from(
+                        points
+                            .iter()
+                            .map(|point| {
+                                let mercator = point.into_mercator();
+                                (mercator.x, mercator.y)
+                            })
+                            .collect::<Vec<_>>(),
+                    ),
+                    vec![],
+                );
+                let tiles_per_side = f64::from(1u32 << z);
+
+                Box::new(bounds.tile_cover(z).filter(move |tile| {
+                    let tile_rect = Rect::new(
+                        (
+                            f64::from(tile.x) / tiles_per_side,
+                            f64::from(tile.y) / tiles_per_side,
+                        ),
+                        (
+                            f64::from(tile.x + 1) / tiles_per_side,
+                            f64::from(tile.y + 1) / tiles_per_side,
+                        ),
+                    );
+                    polygon.intersects(&tile_rect)
+                }))
+            }
+        }
+    }
+}
+
+/// Describes what is downloaded for an offline region.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OfflineRegionDefinition {
+    pub name: String,
+    pub geometry: OfflineRegionGeometry,
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+    /// The style whose sources, glyphs and sprites are downloaded. The layers of the style are
+    /// not stored with the region.
+    pub style: Style,
+    /// The font stacks for which glyphs are downloaded, for example `Open Sans Regular`.
+    #[serde(default)]
+    pub font_stacks: Vec<String>,
+}
+
+/// A region which is stored in an [`OfflineStore`].
+#[derive(Clone, Debug)]
+pub struct OfflineRegion {
+    pub id: i64,
+    pub definition: OfflineRegionDefinition,
+}
+
+/// Limits for the downloading of offline regions.
+#[derive(Clone, Debug)]
+pub struct OfflineSettings {
+    /// The maximum size in bytes of all resources in the store.
+    pub max_store_size: u64,
+    /// The maximum number of resources a single region may require.
+    pub max_region_resources: usize,
+    /// The number of resources which are downloaded at the same time.
+    pub concurrent_downloads: usize,
+}
+
+impl Default for OfflineSettings {
+    fn default() -> Self {
+        Self {
+            max_store_size: 1024 * 1024 * 512,
+            max_region_resources: 100_000,
+            concurrent_downloads: 8,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DownloadState {
+    Downloading,
+    Paused,
+    Complete,
+    /// The download finished, but some resources could not be downloaded, see
+    /// [`DownloadProgress::failed_resources`].
+    Failed,
+}
+
+/// Reports the progress of the download of an offline region.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DownloadProgress {
+    pub state: DownloadState,
+    pub total_resources: usize,
+    /// The number of resources which are stored, including resources which were stored before
+    /// the download started.
+    pub completed_resources: usize,
+    /// The number of resources which could not be downloaded. They are retried when the download
+    /// is started again.
+    pub failed_resources: usize,
+    pub downloaded_bytes: u64,
+}
+
+/// Pauses a running download. The download stops after the resources which are currently
+/// downloaded and is continued by calling [`OfflineManager::download`] again.
+#[derive(Clone, Debug, Default)]
+pub struct DownloadControl {
+    paused: Arc<AtomicBool>,
+}
+
+impl DownloadControl {
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+}
+
+/// Manages offline regions and downloads their resources into an [`OfflineStore`].
+pub struct OfflineManager<HC>
+where
+    HC: HTTPClient,
+{
+    store: OfflineStore,
+    http_client: HC,
+    settings: OfflineSettings,
+}
+
+impl<HC> OfflineManager<HC>
+where
+    HC: HTTPClient,
+{
+    pub fn new(store: OfflineStore, http_client: HC, settings: OfflineSettings) -> Self {
+        Self {
+            store,
+            http_client,
+            settings,
+        }
+    }
+
+    pub fn store(&self) -> &OfflineStore {
+        &self.store
+    }
+
+    /// Stores the definition of a region. The resources are stored by [`OfflineManager::download`].
+    pub async fn create_region(
+        &self,
+        definition: OfflineRegionDefinition,
+    ) -> Result<OfflineRegion, OfflineError> {
+        if definition.min_zoom > definition.max_zoom {
+            return Err(OfflineError::InvalidRegion(format!(
+                "min zoom {} is greater than max zoom {}",
+                definition.min_zoom, definition.max_zoom
+            )));
+        }
+
+        let mut stored = definition.clone();
+        stored.style.layers.clear();
+        let serialized = serde_json::to_string(&stored)
+            .map_err(|e| OfflineError::InvalidRegion(e.to_string()))?;
+        let id = self.store.create_region(&serialized).await?;
+        Ok(OfflineRegion { id, definition })
+    }
+
+    pub async fn regions(&self) -> Result<Vec<OfflineRegion>, OfflineError> {
+        self.store
+            .regions()
+            .await?
+            .into_iter()
+            .map(|(id, serialized)| {
+                let definition = serde_json::from_str(&serialized)
+                    .map_err(|e| OfflineError::InvalidRegion(e.to_string()))?;
+                Ok(OfflineRegion { id, definition })
+            })
+            .collect()
+    }
+
+    /// Deletes the region and its resources which are not used by other regions.
+    pub async fn delete_region(&self, region: &OfflineRegion) -> Result<(), OfflineError> {
+        self.store.delete_region(region.id).await
+    }
+
+    /// Returns the resource from the store or downloads and stores it. `store_size` is the size
+    /// of the store in bytes and is increased by the size of a downloaded resource.
+    async fn fetch_resource(
+        &self,
+        region: &OfflineRegion,
+        url: &str,
+        store_size: &mut u64,
+    ) -> Result<Vec<u8>, OfflineError> {
+        let data = match self.store.get_resource(url).await? {
+            Some(data) => data,
+            None => {
+                let data = self.http_client.fetch(url, ResourceKind::Source).await?;
+                self.store_resource(url, &data, store_size).await?;
+                data
+            }
+        };
+        self.store.add_region_resource(region.id, url).await?;
+        Ok(data)
+    }
+
+    /// Stores a downloaded resource if it fits into the store and adds its size to `store_size`.
+    async fn store_resource(
+        &self,
+        url: &str,
+        data: &[u8],
+        store_size: &mut u64,
+    ) -> Result<(), OfflineError> {
+        let required = *store_size + data.len() as u64;
+        if required > self.settings.max_store_size {
+            return Err(OfflineError::SizeLimitExceeded {
+                limit: self.settings.max_store_size,
+                required,
+            });
+        }
+        self.store.put_resource(url, data).await?;
+        *store_size = required;
+        Ok(())
+    }
+
+    /// Collects the URLs of all tiles, glyphs and sprites of the region. TileJSON documents are
+    /// downloaded and stored while resolving the tile URLs via [`SourceTiles::resolve`], which is
+    /// also used to look up tiles in [`crate::io::source_client::HttpSourceClient`].
+    ///
+    /// The tiles are counted before their URLs are collected, such that regions which exceed
+    /// [`OfflineSettings::max_region_resources`] are rejected without allocating their URLs.
+    async fn resource_urls(
+        &self,
+        region: &OfflineRegion,
+        store_size: &mut u64,
+    ) -> Result<Vec<(String, ResourceKind)>, OfflineError> {
+        let definition = &region.definition;
+        let mut urls = Vec::new();
+
+        let mut sources = Vec::new();
+        for source in definition.style.sources.values() {
+            let source_tiles = match source {
+                Source::Vector(source) | Source::Raster(source) => {
+                    urls.extend(source.url.clone().map(|url| (url, ResourceKind::Source)));
+                    // The TileJSON is stored, such that the tile URLs can be resolved offline
+                    let store_size = &mut *store_size;
+                    SourceTiles::resolve(source, |url| async move {
+                        self.fetch_resource(region, &url, store_size).await
+                    })
+                    .await?
+                }
+            };
+
+            let min_zoom = definition.min_zoom.max(source_tiles.minzoom.unwrap_or(0));
+            let max_zoom = definition
+                .max_zoom
+                .min(source_tiles.maxzoom.unwrap_or(u8::MAX));
+            sources.push((source_tiles, min_zoom..=max_zoom));
+        }
+
+        if let Some(glyphs) = &definition.style.glyphs {
+            for font_stack in &definition.font_stacks {
+                for range in 0..GLYPH_RANGES {
+                    let start = range * GLYPH_RANGE_SIZE;
+                    let url = glyphs.replace("{fontstack}", font_stack).replace(
+                        "{range}",
+                        &format!("{}-{}", start, start + GLYPH_RANGE_SIZE - 1),
+                    );
+                    urls.push((url, ResourceKind::Glyphs));
+                }
+            }
+        }
+
+        if let Some(sprite) = &definition.style.sprite {
+            for suffix in ["", "@2x"] {
+                urls.push((format!("{}{}.json", sprite, suffix), ResourceKind::Sprite));
+                urls.push((format!("{}{}.png", sprite, suffix), ResourceKind::Sprite));
+            }
+        }
+
+        // Counting stops once the limit is exceeded, because the tile cover of a large region can
+        // take long to iterate
+        let limit = self.settings.max_region_resources;
+        let mut required = urls.len();
+        for (_, zooms) in &sources {
+            for z in zooms.clone() {
+                if required > limit {
+                    return Err(OfflineError::TooManyResources { limit, required });
+                }
+                required += definition
+                    .geometry
+                    .tile_cover(z)
+                    .take(limit + 1 - required)
+                    .count();
+            }
+        }
+        if required > limit {
+            return Err(OfflineError::TooManyResources { limit, required });
+        }
+
+        for (source_tiles, zooms) in &sources {
+            for z in zooms.clone() {
+                urls.extend(definition.geometry.tile_cover(z).filter_map(|coords| {
+                    source_tiles
+                        .tile_url(&coords)
+                        .map(|url| (url, ResourceKind::Tile))
+                }));
+            }
+        }
+
+        let mut seen = HashSet::new();
+        urls.retain(|(url, _)| seen.insert(url.clone()));
+        Ok(urls)
+    }
+
+    /// Downloads all resources of the region which are not stored yet. `on_progress` is called
+    /// after each batch of downloaded resources.
+    ///
+    /// The download stops early if it is paused via `control`. Calling this function again
+    /// resumes the download, because stored resources are not downloaded again. Resources which
+    /// fail to download are counted in [`DownloadProgress::failed_resources`], the download then
+    /// ends in [`DownloadState::Failed`]. Failed resources are retried by the next download.
+    /// Resources which do not exist, for example tiles outside of the coverage of a source, are
+    /// completed.
+    pub async fn download<F>(
+        &self,
+        region: &OfflineRegion,
+        control: &DownloadControl,
+        mut on_progress: F,
+    ) -> Result<DownloadProgress, OfflineError>
+    where
+        F: FnMut(&DownloadProgress),
+    {
+        let mut store_size = self.store.size().await?;
+        let urls = self.resource_urls(region, &mut store_size).await?;
+
+        let mut progress = DownloadProgress {
+            state: DownloadState::Downloading,
+            total_resources: urls.len(),
+            completed_resources: 0,
+            failed_resources: 0,
+            downloaded_bytes: 0,
+        };
+
+        let mut missing = Vec::new();
+        for (url, kind) in urls {
+            if self.store.contains_resource(&url).await? {
+                self.store.add_region_resource(region.id, &url).await?;
+                progress.completed_resources += 1;
+            } else {
+                missing.push((url, kind));
+            }
+        }
+        on_progress(&progress);
+
+        for batch in missing.chunks(self.settings.concurrent_downloads.max(1)) {
+            if control.is_paused() {
+                progress.state = DownloadState::Paused;
+                on_progress(&progress);
+                return Ok(progress);
+            }
+
+            let results = join_all(
+                batch
+                    .iter()
+                    .map(|(url, kind)| self.http_client.fetch(url, *kind)),
+            )
+            .await;
+            for ((url, _), result) in batch.iter().zip(results) {
+                match result {
+                    Ok(data) => {
+                        self.store_resource(url, &data, &mut store_size).await?;
+                        self.store.add_region_resource(region.id, url).await?;
+                        progress.completed_resources += 1;
+                        progress.downloaded_bytes += data.len() as u64;
+                    }
+                    // The resource is stored empty, such that it is not requested again and an
+                    // empty tile is shown offline, just like online
+                    Err(Error::NotFound(_)) => {
+                        self.store_resource(url, &[], &mut store_size).await?;
+                        self.store.add_region_resource(region.id, url).await?;
+                        progress.completed_resources += 1;
+                    }
+                    Err(e) => {
+                        log::warn!("failed to download offline resource {}: {:?}", url, e);
+                        progress.failed_resources += 1;
+                    }
+                }
+            }
+            on_progress(&progress);
+        }
+
+        progress.state = if progress.failed_resources > 0 {
+            DownloadState::Failed
+        } else {
+            DownloadState::Complete
+        };
+        on_progress(&progress);
+        Ok(progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DownloadControl, DownloadState, OfflineManager, OfflineRegionDefinition,
+        OfflineRegionGeometry, OfflineSettings,
+    };
+    use crate::coords::{LngLat, LngLatBounds, WorldTileCoords};
+    use crate::error::Error;
+    use crate::io::offline_store::{OfflineError, OfflineStore};
+    use crate::io::source_client::{
+        HTTPClient, HttpSourceClient, ResourceKind, SourceClient, TransformRequest,
+    };
+    use crate::style::source::{Source, VectorSource};
+    use crate::style::Style;
+    use async_trait::async_trait;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct FakeHttpClient {
+        requests: Arc<Mutex<Vec<(String, ResourceKind)>>>,
+        /// Requests fail if their URL contains this string.
+        failing: Arc<Mutex<Option<String>>>,
+        /// Resources do not exist if their URL contains this string.
+        missing: Arc<Mutex<Option<String>>>,
+    }
+
+    impl FakeHttpClient {
+        fn fail(&self, pattern: Option<&str>) {
+            *self.failing.lock().unwrap() = pattern.map(str::to_string);
+        }
+
+        fn miss(&self, pattern: Option<&str>) {
+            *self.missing.lock().unwrap() = pattern.map(str::to_string);
+        }
+    }
+
+    #[cfg_attr(feature = "no-thread-safe-futures", async_trait(?Send))]
+    #[cfg_attr(not(feature = "no-thread-safe-futures"), async_trait)]
+    impl HTTPClient for FakeHttpClient {
+        async fn fetch(&self, url: &str, kind: ResourceKind) -> Result<Vec<u8>, Error> {
+            self.requests.lock().unwrap().push((url.to_string(), kind));
+            if let Some(pattern) = &*self.failing.lock().unwrap() {
+                if url.contains(pattern.as_str()) {
+                    return Err(Error::Network(format!("{} is not reachable", url)));
+                }
+            }
+            if let Some(pattern) = &*self.missing.lock().unwrap() {
+                if url.contains(pattern.as_str()) {
+                    return Err(Error::NotFound(format!("{} does not exist", url)));
+                }
+            }
+            if url.ends_with("tiles.json") {
+                Ok(br#"{"tilejson": "2.2.0", "tiles": ["https://example.com/{z}/{x}/{y}.pbf"], "maxzoom": 1}"#.to_vec())
+            } else {
+                Ok(vec![0; 10])
+            }
+        }
+
+        fn with_transform_request(self, _transform_request: Arc<TransformRequest>) -> Self {
+            self
+        }
+    }
+
+    fn definition(style: Style) -> OfflineRegionDefinition {
+        OfflineRegionDefinition {
+            name: "test".to_string(),
+            geometry: OfflineRegionGeometry::Bounds(LngLatBounds::new(
+                LngLat::new(-10.0, -10.0),
+                LngLat::new(10.0, 10.0),
+            )),
+            min_zoom: 0,
+            max_zoom: 3,
+            style,
+            font_stacks: vec!["Sans".to_string()],
+        }
+    }
+
+    fn style() -> Style {
+        let mut style = Style::default();
+        style.sources.insert(
+            "source".to_string(),
+            Source::Vector(VectorSource {
+                attribution: None,
+                bounds: None,
+                maxzoom: None,
+                minzoom: None,
+                scheme: None,
+                tiles: None,
+                url: Some("https://example.com/tiles.json".to_string()),
+            }),
+        );
+        style.sprite = Some("https://example.com/sprite".to_string());
+        style.glyphs = Some("https://example.com/{fontstack}/{range}.pbf".to_string());
+        style
+    }
+
+    #[test]
+    fn test_polygon_tile_cover() {
+        // A triangle covering the upper left half of the world
+        let geometry = OfflineRegionGeometry::Polygon(vec![
+            LngLat::new(-179.0, 80.0),
+            LngLat::new(179.0, 80.0),
+            LngLat::new(-179.0, -80.0),
+        ]);
+        let tiles = geometry.tile_cover(2).collect::<Vec<_>>();
+        assert!(tiles.contains(&WorldTileCoords::from((0, 0, 2))));
+        assert!(!tiles.contains(&WorldTileCoords::from((3, 3, 2))));
+        assert!(tiles.len() < 16);
+    }
+
+    #[tokio::test]
+    async fn test_download() {
+        let http_client = FakeHttpClient::default();
+        let manager = OfflineManager::new(
+            OfflineStore::open_in_memory().unwrap(),
+            http_client.clone(),
+            OfflineSettings::default(),
+        );
+        let region = manager.create_region(definition(style())).await.unwrap();
+        assert_eq!(manager.regions().await.unwrap().len(), 1);
+
+        let mut events = 0;
+        let progress = manager
+            .download(&region, &DownloadControl::default(), |_| events += 1)
+            .await
+            .unwrap();
+        // The TileJSON, 1 + 4 tiles because the TileJSON limits the zoom to 1, 256 glyph ranges and
+        // 4 sprite resources
+        assert_eq!(progress.state, DownloadState::Complete);
+        assert_eq!(progress.total_resources, 1 + 5 + 256 + 4);
+        assert_eq!(progress.completed_resources, progress.total_resources);
+        assert_eq!(progress.failed_resources, 0);
+        assert!(events > 2);
+        assert!(manager
+            .store()
+            .contains_resource("https://example.com/1/0/1.pbf")
+            .await
+            .unwrap());
+        {
+            let requests = http_client.requests.lock().unwrap();
+            assert!(requests.contains(&(
+                "https://example.com/tiles.json".to_string(),
+                ResourceKind::Source
+            )));
+            assert!(requests.contains(&(
+                "https://example.com/sprite@2x.png".to_string(),
+                ResourceKind::Sprite
+            )));
+        }
+
+        // Stored resources are not downloaded again
+        http_client.requests.lock().unwrap().clear();
+        let progress = manager
+            .download(&region, &DownloadControl::default(), |_| {})
+            .await
+            .unwrap();
+        assert_eq!(progress.downloaded_bytes, 0);
+        assert!(http_client.requests.lock().unwrap().is_empty());
+
+        manager.delete_region(&region).await.unwrap();
+        assert_eq!(manager.store().size().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_limits() {
+        let manager = OfflineManager::new(
+            OfflineStore::open_in_memory().unwrap(),
+            FakeHttpClient::default(),
+            OfflineSettings {
+                max_store_size: 100,
+                ..OfflineSettings::default()
+            },
+        );
+        let region = manager.create_region(definition(style())).await.unwrap();
+
+        let control = DownloadControl::default();
+        control.pause();
+        let progress = manager.download(&region, &control, |_| {}).await.unwrap();
+        assert_eq!(progress.state, DownloadState::Paused);
+        assert_eq!(progress.completed_resources, 1);
+
+        control.resume();
+        assert!(matches!(
+            manager.download(&region, &control, |_| {}).await,
+            Err(OfflineError::SizeLimitExceeded { limit: 100, .. })
+        ));
+
+        let mut definition = definition(style());
+        definition.min_zoom = 4;
+        assert!(matches!(
+            manager.create_region(definition).await,
+            Err(OfflineError::InvalidRegion(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_too_many_resources() {
+        let manager = OfflineManager::new(
+            OfflineStore::open_in_memory().unwrap(),
+            FakeHttpClient::default(),
+            OfflineSettings {
+                max_region_resources: 1000,
+                ..OfflineSettings::default()
+            },
+        );
+
+        // The whole world down to zoom 22 is rejected without collecting its tiles
+        let mut style = style();
+        if let Some(Source::Vector(source)) = style.sources.get_mut("source") {
+            source.maxzoom = Some(22);
+        }
+        let mut definition = definition(style);
+        definition.geometry = OfflineRegionGeometry::Bounds(LngLatBounds::new(
+            LngLat::new(-180.0, -85.0),
+            LngLat::new(180.0, 85.0),
+        ));
+        definition.max_zoom = 22;
+        let region = manager.create_region(definition).await.unwrap();
+        assert!(matches!(
+            manager
+                .download(&region, &DownloadControl::default(), |_| {})
+                .await,
+            Err(OfflineError::TooManyResources { limit: 1000, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_failed_download() {
+        let http_client = FakeHttpClient::default();
+        let manager = OfflineManager::new(
+            OfflineStore::open_in_memory().unwrap(),
+            http_client.clone(),
+            OfflineSettings::default(),
+        );
+        let region = manager.create_region(definition(style())).await.unwrap();
+
+        http_client.fail(Some("sprite"));
+        let progress = manager
+            .download(&region, &DownloadControl::default(), |_| {})
+            .await
+            .unwrap();
+        assert_eq!(progress.state, DownloadState::Failed);
+        assert_eq!(progress.failed_resources, 4);
+        assert_eq!(progress.completed_resources, progress.total_resources - 4);
+
+        // Failed resources are retried
+        http_client.fail(None);
+        let progress = manager
+            .download(&region, &DownloadControl::default(), |_| {})
+            .await
+            .unwrap();
+        assert_eq!(progress.state, DownloadState::Complete);
+        assert_eq!(progress.completed_resources, progress.total_resources);
+    }
+
+    #[tokio::test]
+    async fn test_missing_resources() {
+        let http_client = FakeHttpClient::default();
+        let manager = OfflineManager::new(
+            OfflineStore::open_in_memory().unwrap(),
+            http_client.clone(),
+            OfflineSettings::default(),
+        );
+        let region = manager.create_region(definition(style())).await.unwrap();
+
+        // Tiles which do not exist are completed and stored empty
+        http_client.miss(Some("example.com/1/"));
+        let progress = manager
+            .download(&region, &DownloadControl::default(), |_| {})
+            .await
+            .unwrap();
+        assert_eq!(progress.state, DownloadState::Complete);
+        assert_eq!(progress.failed_resources, 0);
+        assert_eq!(progress.completed_resources, progress.total_resources);
+        assert_eq!(
+            manager
+                .store()
+                .get_resource("https://example.com/1/0/0.pbf")
+                .await
+                .unwrap(),
+            Some(Vec::new())
+        );
+
+        // Missing resources are not requested again
+        let requests = http_client.requests.lock().unwrap().len();
+        manager
+            .download(&region, &DownloadControl::default(), |_| {})
+            .await
+            .unwrap();
+        assert_eq!(http_client.requests.lock().unwrap().len(), requests);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_offline() {
+        let http_client = FakeHttpClient::default();
+        let manager = OfflineManager::new(
+            OfflineStore::open_in_memory().unwrap(),
+            http_client.clone(),
+            OfflineSettings::default(),
+        );
+        let style = style();
+        let region = manager
+            .create_region(definition(style.clone()))
+            .await
+            .unwrap();
+        manager
+            .download(&region, &DownloadControl::default(), |_| {})
+            .await
+            .unwrap();
+
+        let source = match &style.sources["source"] {
+            Source::Vector(source) => source.clone(),
+            Source::Raster(_) => unreachable!(),
+        };
+        let source_client = SourceClient::Http(
+            HttpSourceClient::new(http_client.clone())
+                .with_source(source)
+                .with_offline_store(manager.store().clone()),
+        );
+
+        // Both the TileJSON and the tile are read from the store
+        http_client.fail(Some(""));
+        assert_eq!(
+            source_client
+                .fetch(&WorldTileCoords::from((0, 1, 1)))
+                .await
+                .unwrap(),
+            vec![0; 10]
+        );
+        // Tiles beyond the max zoom of the region are not stored
+        assert!(matches!(
+            source_client.fetch(&WorldTileCoords::from((0, 0, 2))).await,
+            Err(Error::Network(_))
+        ));
+    }
+}
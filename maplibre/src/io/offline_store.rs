@@ -0,0 +1,401 @@
+//! Persistent storage of tiles and other resources for offline usage.
+
+use crate::error::Error;
+use flate2::Crc;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Errors which can happen while storing or downloading offline resources.
+#[derive(Debug)]
+pub enum OfflineError {
+    Store(rusqlite::Error),
+    Network(Error),
+    /// Storing a resource of `required` bytes would exceed the configured size of the store.
+    SizeLimitExceeded {
+        limit: u64,
+        required: u64,
+    },
+    /// The region requires more resources than allowed per region. Counting stops once the limit
+    /// is exceeded, therefore `required` is a lower bound.
+    TooManyResources {
+        limit: usize,
+        required: usize,
+    },
+    /// The region definition can not be downloaded, for example because of an invalid zoom range.
+    InvalidRegion(String),
+}
+
+impl fmt::Display for OfflineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OfflineError::Store(e) => write!(f, "offline store: {}", e),
+            OfflineError::Network(e) => write!(f, "offline download: {:?}", e),
+            OfflineError::SizeLimitExceeded { limit, required } => write!(
+                f,
+                "offline store limit of {}bytes exceeded, {}bytes are required",
+                limit, required
+            ),
+            OfflineError::TooManyResources { limit, required } => write!(
+                f,
+                "offline region requires {} resources, at most {} are allowed",
+                required, limit
+            ),
+            OfflineError::InvalidRegion(reason) => write!(f, "invalid offline region: {}", reason),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for OfflineError {
+    fn from(e: rusqlite::Error) -> Self {
+        OfflineError::Store(e)
+    }
+}
+
+impl From<Error> for OfflineError {
+    fn from(e: Error) -> Self {
+        OfflineError::Network(e)
+    }
+}
+
+impl From<OfflineError> for Error {
+    fn from(e: OfflineError) -> Self {
+        match e {
+            OfflineError::Network(e) => e,
+            OfflineError::Store(e) => Error::Storage(e.to_string()),
+            // Exceeded limits and invalid regions are not resolved by retrying
+            e @ (OfflineError::SizeLimitExceeded { .. }
+            | OfflineError::TooManyResources { .. }
+            | OfflineError::InvalidRegion(_)) => Error::Storage(e.to_string()),
+        }
+    }
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+/// A SQLite database which stores resources like tiles, TileJSON, glyphs and sprites by their URL.
+/// Resources are referenced by offline regions and are deleted once no region references them
+/// anymore.
+///
+/// A CRC32 checksum is stored along with each resource. Resources which fail the check when they
+/// are read are deleted and treated as missing.
+#[derive(Clone)]
+pub struct OfflineStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl OfflineStore {
+    /// Opens the store at `path`. The database is created if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, OfflineError> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, OfflineError> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self, OfflineError> {
+        // language=SQL
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS resources (
+                url TEXT PRIMARY KEY,
+                data BLOB NOT NULL,
+                checksum INTEGER NOT NULL,
+                size INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS regions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                definition TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS region_resources (
+                region_id INTEGER NOT NULL REFERENCES regions(id),
+                url TEXT NOT NULL,
+                PRIMARY KEY (region_id, url)
+            );",
+        )?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Runs `f` with the connection. SQLite blocks, therefore `f` must not run on the threads of
+    /// the async runtime.
+    async fn run<T, F>(&self, f: F) -> Result<T, OfflineError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, OfflineError> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || f(&mut lock(&connection)))
+            .await
+            .map_err(|e| OfflineError::Network(Error::Storage(e.to_string())))?
+    }
+
+    /// Returns the resource stored for `url`. Resources which are corrupted are deleted and
+    /// `None` is returned.
+    pub async fn get_resource(&self, url: &str) -> Result<Option<Vec<u8>>, OfflineError> {
+        let url = url.to_string();
+        self.run(move |connection| {
+            // language=SQL
+            let row: Option<(Vec<u8>, u32)> = connection
+                .query_row(
+                    "SELECT data, checksum FROM resources WHERE url = ?1;",
+                    params![url],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+
+            match row {
+                Some((data, expected)) if checksum(&data) != expected => {
+                    log::warn!("offline resource {} is corrupted and was removed", url);
+                    // language=SQL
+                    connection.execute("DELETE FROM resources WHERE url = ?1;", params![url])?;
+                    Ok(None)
+                }
+                Some((data, _)) => Ok(Some(data)),
+                None => Ok(None),
+            }
+        })
+        .await
+    }
+
+    pub async fn contains_resource(&self, url: &str) -> Result<bool, OfflineError> {
+        let url = url.to_string();
+        self.run(move |connection| {
+            // language=SQL
+            Ok(connection
+                .query_row(
+                    "SELECT 1 FROM resources WHERE url = ?1;",
+                    params![url],
+                    |_row| Ok(()),
+                )
+                .optional()?
+                .is_some())
+        })
+        .await
+    }
+
+    /// Stores the resource for `url`. An existing resource is replaced.
+    pub async fn put_resource(&self, url: &str, data: &[u8]) -> Result<(), OfflineError> {
+        let url = url.to_string();
+        let data = data.to_vec();
+        self.run(move |connection| {
+            // language=SQL
+            connection.execute(
+                "INSERT OR REPLACE INTO resources (url, data, checksum, size) VALUES (?1, ?2, ?3, ?4);",
+                params![url, data, checksum(&data), data.len() as i64],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Marks the resource at `url` as part of the region `region_id`.
+    pub async fn add_region_resource(&self, region_id: i64, url: &str) -> Result<(), OfflineError> {
+        let url = url.to_string();
+        self.run(move |connection| {
+            // language=SQL
+            connection.execute(
+                "INSERT OR IGNORE INTO region_resources (region_id, url) VALUES (?1, ?2);",
+                params![region_id, url],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The size of all stored resources in bytes.
+    pub async fn size(&self) -> Result<u64, OfflineError> {
+        self.run(|connection| {
+            // language=SQL
+            let size: i64 = connection.query_row(
+                "SELECT COALESCE(SUM(size), 0) FROM resources;",
+                [],
+                |row| row.get(0),
+            )?;
+            Ok(size as u64)
+        })
+        .await
+    }
+
+    /// Checks the checksums of all stored resources. Corrupted resources are deleted and their
+    /// URLs are returned, such that they can be downloaded again.
+    pub async fn verify_integrity(&self) -> Result<Vec<String>, OfflineError> {
+        self.run(|connection| {
+            let corrupted = {
+                // language=SQL
+                let mut statement =
+                    connection.prepare("SELECT url, data, checksum FROM resources;")?;
+                let rows = statement.query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Vec<u8>>(1)?,
+                        row.get::<_, u32>(2)?,
+                    ))
+                })?;
+
+                let mut corrupted = Vec::new();
+                for row in rows {
+                    let (url, data, expected) = row?;
+                    if checksum(&data) != expected {
+                        corrupted.push(url);
+                    }
+                }
+                corrupted
+            };
+
+            for url in &corrupted {
+                log::warn!("offline resource {} is corrupted and was removed", url);
+                // language=SQL
+                connection.execute("DELETE FROM resources WHERE url = ?1;", params![url])?;
+            }
+
+            Ok(corrupted)
+        })
+        .await
+    }
+
+    /// Stores the serialized definition of a region and returns its id.
+    pub async fn create_region(&self, definition: &str) -> Result<i64, OfflineError> {
+        let definition = definition.to_string();
+        self.run(move |connection| {
+            // language=SQL
+            connection.execute(
+                "INSERT INTO regions (definition) VALUES (?1);",
+                params![definition],
+            )?;
+            Ok(connection.last_insert_rowid())
+        })
+        .await
+    }
+
+    /// Returns the ids and serialized definitions of all regions.
+    pub async fn regions(&self) -> Result<Vec<(i64, String)>, OfflineError> {
+        self.run(|connection| {
+            // language=SQL
+            let mut statement =
+                connection.prepare("SELECT id, definition FROM regions ORDER BY id;")?;
+            let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            Ok(rows.collect::<Result<_, _>>()?)
+        })
+        .await
+    }
+
+    /// Deletes the region and all resources which are not referenced by another region.
+    pub async fn delete_region(&self, region_id: i64) -> Result<(), OfflineError> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+            // language=SQL
+            transaction.execute(
+                "DELETE FROM region_resources WHERE region_id = ?1;",
+                params![region_id],
+            )?;
+            // language=SQL
+            transaction.execute("DELETE FROM regions WHERE id = ?1;", params![region_id])?;
+            // language=SQL
+            transaction.execute(
+                "DELETE FROM resources WHERE url NOT IN (SELECT url FROM region_resources);",
+                [],
+            )?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+fn lock(connection: &Mutex<Connection>) -> MutexGuard<'_, Connection> {
+    // A panic while holding the lock can not leave the database in an inconsistent state,
+    // because every statement is atomic.
+    match connection.lock() {
+        Ok(connection) => connection,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lock, OfflineError, OfflineStore};
+    use crate::error::Error;
+    use rusqlite::params;
+
+    #[test]
+    fn test_error_conversion() {
+        let network = Error::from(OfflineError::Network(Error::Network("timeout".to_string())));
+        assert!(network.is_transient());
+
+        for e in [
+            OfflineError::SizeLimitExceeded {
+                limit: 1,
+                required: 2,
+            },
+            OfflineError::TooManyResources {
+                limit: 1,
+                required: 2,
+            },
+            OfflineError::InvalidRegion("min zoom".to_string()),
+        ] {
+            assert!(matches!(Error::from(e), Error::Storage(_)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resources() {
+        let store = OfflineStore::open_in_memory().unwrap();
+        assert_eq!(store.get_resource("a").await.unwrap(), None);
+
+        store.put_resource("a", &[1, 2, 3]).await.unwrap();
+        store.put_resource("b", &[4, 5]).await.unwrap();
+        assert!(store.contains_resource("a").await.unwrap());
+        assert_eq!(store.get_resource("a").await.unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(store.size().await.unwrap(), 5);
+
+        // Corrupt the stored data
+        lock(&store.connection)
+            .execute(
+                "UPDATE resources SET data = ?1 WHERE url = 'b';",
+                params![vec![4u8, 6]],
+            )
+            .unwrap();
+        assert_eq!(
+            store.verify_integrity().await.unwrap(),
+            vec!["b".to_string()]
+        );
+        assert_eq!(store.get_resource("b").await.unwrap(), None);
+        assert_eq!(store.size().await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_delete_region() {
+        let store = OfflineStore::open_in_memory().unwrap();
+        let first = store.create_region("first").await.unwrap();
+        let second = store.create_region("second").await.unwrap();
+        assert_eq!(
+            store.regions().await.unwrap(),
+            vec![(first, "first".to_string()), (second, "second".to_string())]
+        );
+
+        store.put_resource("shared", &[1]).await.unwrap();
+        store.put_resource("only-first", &[2]).await.unwrap();
+        store.add_region_resource(first, "shared").await.unwrap();
+        store
+            .add_region_resource(first, "only-first")
+            .await
+            .unwrap();
+        store.add_region_resource(second, "shared").await.unwrap();
+
+        store.delete_region(first).await.unwrap();
+        assert_eq!(
+            store.regions().await.unwrap(),
+            vec![(second, "second".to_string())]
+        );
+        assert!(store.contains_resource("shared").await.unwrap());
+        assert!(!store.contains_resource("only-first").await.unwrap());
+    }
+}
@@ -0,0 +1,154 @@
+//! Predicts the tiles which become visible soon, so that they can be requested before they are
+//! in view.
+
+use crate::coords::{MercatorCoordinate, WorldTileCoords, Zoom};
+use instant::Instant;
+use std::time::Duration;
+
+/// Pauses between frames which are longer than this are not considered to be part of a pan.
+const MAX_FRAME_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Tracks the movement of the center of the viewport in order to extrapolate pans, including
+/// kinetic pans which continue after the user released the map.
+#[derive(Default)]
+pub struct PanTracker {
+    last: Option<(MercatorCoordinate, Instant)>,
+    /// The velocity in mercator units per second.
+    velocity: (f64, f64),
+}
+
+impl PanTracker {
+    /// Records the center of the viewport at the time `now`. This is called once per frame.
+    pub fn update(&mut self, center: MercatorCoordinate, now: Instant) {
+        if let Some((last_center, last_time)) = self.last {
+            let dt = now.saturating_duration_since(last_time);
+            if dt > MAX_FRAME_INTERVAL {
+                self.velocity = (0.0, 0.0);
+            } else if !dt.is_zero() {
+                let dt = dt.as_secs_f64();
+                let velocity = (
+                    (center.x - last_center.x) / dt,
+                    (center.y - last_center.y) / dt,
+                );
+                // Smooth the velocity, because frames are not evenly spaced
+                self.velocity = (
+                    (self.velocity.0 + velocity.0) / 2.0,
+                    (self.velocity.1 + velocity.1) / 2.0,
+                );
+            }
+        }
+
+        self.last = Some((center, now));
+    }
+
+    /// Returns the centers of the viewport within the next `lookahead`, sampled at `samples`
+    /// points in time. Returns nothing if the map is not panned.
+    pub fn predict(&self, lookahead: Duration, samples: usize) -> Vec<MercatorCoordinate> {
+        let (center, _) = match self.last {
+            Some(last) => last,
+            None => return Vec::new(),
+        };
+
+        let (vx, vy) = self.velocity;
+        if vx == 0.0 && vy == 0.0 {
+            return Vec::new();
+        }
+
+        (1..=samples)
+            .map(|i| {
+                let t = lookahead.as_secs_f64() * i as f64 / samples as f64;
+                MercatorCoordinate::new(center.x + vx * t, center.y + vy * t)
+            })
+            .collect()
+    }
+}
+
+/// Returns the tiles which cover a viewport of `width` x `height` pixels around `center` when
+/// looking straight down at `zoom`.
+pub fn tiles_in_viewport(
+    center: MercatorCoordinate,
+    zoom: Zoom,
+    (width, height): (f64, f64),
+) -> impl Iterator<Item = WorldTileCoords> {
+    let world_size = zoom.world_size();
+    let (half_width, half_height) = (width / 2.0 / world_size, height / 2.0 / world_size);
+
+    let z = zoom.level();
+    let min_tile =
+        MercatorCoordinate::new(center.x - half_width, center.y - half_height).into_world_tile(z);
+    let max_tile =
+        MercatorCoordinate::new(center.x + half_width, center.y + half_height).into_world_tile(z);
+
+    (min_tile.x..=max_tile.x)
+        .flat_map(move |x| (min_tile.y..=max_tile.y).map(move |y| (x, y, z).into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coords::{MercatorCoordinate, WorldTileCoords, Zoom};
+    use crate::io::prefetch::{tiles_in_viewport, PanTracker};
+    use instant::Instant;
+    use std::time::Duration;
+
+    #[test]
+    fn test_predict_pan() {
+        let mut tracker = PanTracker::default();
+        let start = Instant::now();
+        assert!(tracker.predict(Duration::from_secs(1), 2).is_empty());
+
+        tracker.update(MercatorCoordinate::new(0.5, 0.5), start);
+        tracker.update(
+            MercatorCoordinate::new(0.5, 0.5),
+            start + Duration::from_millis(100),
+        );
+        assert!(tracker.predict(Duration::from_secs(1), 2).is_empty());
+
+        // Moves by 0.1 per second
+        for i in 2..10 {
+            tracker.update(
+                MercatorCoordinate::new(0.5 + 0.01 * (i - 1) as f64, 0.5),
+                start + Duration::from_millis(100 * i),
+            );
+        }
+        let predicted = tracker.predict(Duration::from_secs(1), 2);
+        assert_eq!(predicted.len(), 2);
+        assert!((predicted[0].x - 0.63).abs() < 1e-3);
+        assert!((predicted[1].x - 0.68).abs() < 1e-3);
+        assert_eq!(predicted[1].y, 0.5);
+
+        // The pan stopped a while ago
+        tracker.update(
+            MercatorCoordinate::new(0.58, 0.5),
+            start + Duration::from_secs(2),
+        );
+        assert!(tracker.predict(Duration::from_secs(1), 2).is_empty());
+    }
+
+    #[test]
+    fn test_tiles_in_viewport() {
+        // A tile is 512 pixels wide at its zoom level
+        let tiles = tiles_in_viewport(
+            MercatorCoordinate::new(0.5, 0.5),
+            Zoom::new(2.0),
+            (600.0, 400.0),
+        )
+        .collect::<Vec<_>>();
+        assert_eq!(
+            tiles,
+            vec![
+                WorldTileCoords::from((1, 1, 2)),
+                (1, 2, 2).into(),
+                (2, 1, 2).into(),
+                (2, 2, 2).into()
+            ]
+        );
+
+        let tiles = tiles_in_viewport(
+            MercatorCoordinate::new(0.1, 0.1),
+            Zoom::new(2.0),
+            (200.0, 200.0),
+        )
+        .collect::<Vec<_>>();
+        assert_eq!(tiles, vec![WorldTileCoords::from((0, 0, 2))]);
+    }
+}
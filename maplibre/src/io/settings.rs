@@ -0,0 +1,122 @@
+//! Settings which configure the loading and caching of tiles.
+
+use std::time::Duration;
+
+/// Configures how tiles are loaded and cached.
+#[derive(Clone, Debug)]
+pub struct IoSettings {
+    /// The memory in bytes which tessellated tiles may use. If the budget is exceeded, then the
+    /// least recently used tiles which are not visible are evicted.
+    pub tile_cache_budget: usize,
+    /// The time per frame which is spent receiving tessellated layers from the workers. At least
+    /// one message is received per frame.
+    pub drain_time_budget: Duration,
+    /// The estimated size of the tessellated layers in bytes which are received per frame.
+    pub drain_byte_budget: usize,
+    /// The maximum number of tiles which are fetched and tessellated at the same time. Further
+    /// tiles are requested once pending requests finish.
+    pub max_concurrent_requests: usize,
+    /// The number of zoom levels above the tiles in view at which parent tiles are requested
+    /// before the tiles in view. The parents are drawn while the tiles in view are loading.
+    pub fallback_parent_levels: u8,
+    /// Configures the requesting of tiles before they are in view.
+    pub prefetch: PrefetchSettings,
+    /// Set if the network connection is metered, for example a mobile data plan. See
+    /// [`PrefetchSettings::disable_on_metered_connection`].
+    pub metered_connection: bool,
+    /// Configures the retrying of tiles which failed to load because of transient errors.
+    pub retry: RetrySettings,
+    /// The maximum size in bytes of a decompressed tile. Compressed tiles which exceed it are
+    /// reported as malformed, which protects against decompression bombs.
+    pub max_tile_size: usize,
+}
+
+impl Default for IoSettings {
+    fn default() -> Self {
+        Self {
+            tile_cache_budget: if cfg!(any(target_os = "android", target_os = "ios")) {
+                1024 * 1024 * 64
+            } else {
+                1024 * 1024 * 256
+            },
+            drain_time_budget: Duration::from_millis(4),
+            drain_byte_budget: 1024 * 1024 * 16,
+            max_concurrent_requests: 16,
+            fallback_parent_levels: 3,
+            prefetch: PrefetchSettings::default(),
+            metered_connection: false,
+            retry: RetrySettings::default(),
+            max_tile_size: 1024 * 1024 * 32,
+        }
+    }
+}
+
+impl IoSettings {
+    /// Returns true if tiles are prefetched with these settings.
+    pub fn is_prefetching(&self) -> bool {
+        self.prefetch.enabled
+            && !(self.metered_connection && self.prefetch.disable_on_metered_connection)
+    }
+}
+
+/// Configures the prefetching of tiles around the viewport and along the path of the camera.
+/// Prefetched tiles are requested after all tiles in view have been requested.
+#[derive(Clone, Debug)]
+pub struct PrefetchSettings {
+    pub enabled: bool,
+    /// The width in tiles of the ring around the tiles in view which is prefetched.
+    pub ring_size: i32,
+    /// The number of cameras which are sampled along the remaining path of camera animations and
+    /// along the predicted path of pans.
+    pub path_samples: usize,
+    /// How far ahead in time the movement of a pan is extrapolated.
+    pub pan_lookahead: Duration,
+    /// The maximum number of prefetch requests which are pending at the same time. This limits
+    /// the bandwidth which is used for prefetching.
+    pub max_concurrent_requests: usize,
+    /// Tiles are only prefetched while the tile cache uses less memory in bytes than this budget.
+    pub memory_budget: usize,
+    /// Disables prefetching if [`IoSettings::metered_connection`] is set.
+    pub disable_on_metered_connection: bool,
+}
+
+impl Default for PrefetchSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ring_size: 1,
+            path_samples: 4,
+            pan_lookahead: Duration::from_millis(500),
+            max_concurrent_requests: 4,
+            memory_budget: if cfg!(any(target_os = "android", target_os = "ios")) {
+                1024 * 1024 * 32
+            } else {
+                1024 * 1024 * 128
+            },
+            disable_on_metered_connection: true,
+        }
+    }
+}
+
+/// Configures the exponential backoff of tiles which failed to load. Tiles which do not exist are
+/// not retried.
+#[derive(Clone, Debug)]
+pub struct RetrySettings {
+    /// The backoff after the first failed attempt. The backoff doubles with every further failed
+    /// attempt.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// The fraction in `[0, 1]` by which the backoff is randomly reduced. This prevents that
+    /// tiles which failed at the same time are retried at the same time.
+    pub jitter: f64,
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            jitter: 0.5,
+        }
+    }
+}
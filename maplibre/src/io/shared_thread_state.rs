@@ -2,10 +2,13 @@
 
 use crate::coords::{WorldCoords, WorldTileCoords, Zoom};
 use crate::error::Error;
+use crate::io::compression::decompress;
 use crate::io::geometry_index::{GeometryIndex, IndexProcessor, IndexedGeometry, TileIndex};
 use crate::io::tile_request_state::TileRequestState;
+use crate::io::tile_validation::validate_layer;
 use crate::io::{
-    LayerTessellateMessage, TessellateMessage, TileRequest, TileRequestID, TileTessellateMessage,
+    LayerTessellateMessage, TessellateMessage, TileFailedMessage, TileRequest, TileRequestID,
+    TileTessellateMessage,
 };
 
 use std::collections::HashSet;
@@ -22,6 +25,8 @@ pub struct SharedThreadState {
     pub tile_request_state: Arc<Mutex<TileRequestState>>,
     pub message_sender: mpsc::Sender<TessellateMessage>,
     pub geometry_index: Arc<Mutex<GeometryIndex>>,
+    /// See [`crate::io::settings::IoSettings::max_tile_size`].
+    pub max_tile_size: usize,
 }
 
 impl SharedThreadState {
@@ -41,9 +46,20 @@ impl SharedThreadState {
 
             let _span_ = tracing::span!(tracing::Level::TRACE, "parse_tile_bytes").entered();
 
-            let mut tile = geozero::mvt::Tile::decode(data.as_ref()).expect("failed to load tile");
+            // Tiles are often compressed without the server or file declaring it
+            let data = match decompress(&data, self.max_tile_size) {
+                Ok(data) => data,
+                Err(e) => return self.tile_failed(&coords, request_id, e),
+            };
+
+            let mut tile = match geozero::mvt::Tile::decode(data.as_ref()) {
+                Ok(tile) => tile,
+                Err(e) => return self.tile_failed(&coords, request_id, e.into()),
+            };
 
             let index = IndexProcessor::new();
+            // The first error of a layer which could not be tessellated
+            let mut layer_error = None;
 
             for layer in &mut tile.layers {
                 let cloned_layer = layer.clone();
@@ -55,7 +71,12 @@ impl SharedThreadState {
                 tracing::info!("layer {} at {} ready", layer_name, &coords);
 
                 let mut tessellator = ZeroTessellator::default();
-                if let Err(e) = layer.process(&mut tessellator) {
+                let result = validate_layer(layer).and_then(|_| {
+                    layer
+                        .process(&mut tessellator)
+                        .map_err(|e| tessellator.take_error().unwrap_or_else(|| e.into()))
+                });
+                if let Err(e) = result {
                     self.message_sender.send(TessellateMessage::Layer(
                         LayerTessellateMessage::UnavailableLayer {
                             coords,
@@ -69,6 +90,7 @@ impl SharedThreadState {
                         &coords,
                         e
                     );
+                    layer_error.get_or_insert(e);
                 } else {
                     self.message_sender.send(TessellateMessage::Layer(
                         LayerTessellateMessage::TessellatedLayer {
@@ -107,11 +129,17 @@ impl SharedThreadState {
 
             tracing::info!("tile tessellated at {} finished", &tile_request.coords);
 
-            self.message_sender
-                .send(TessellateMessage::Tile(TileTessellateMessage {
-                    request_id,
-                    coords: tile_request.coords,
-                }))?;
+            // The remaining layers are rendered, but the tile is reported as failed
+            match layer_error {
+                Some(error) => self.send_tile_failed(&coords, request_id, error)?,
+                None => {
+                    self.message_sender
+                        .send(TessellateMessage::Tile(TileTessellateMessage {
+                            request_id,
+                            coords: tile_request.coords,
+                        }))?
+                }
+            }
 
             if let Ok(mut geometry_index) = self.geometry_index.lock() {
                 geometry_index.index_tile(
@@ -141,8 +169,54 @@ impl SharedThreadState {
                     },
                 ))?;
             }
+
+            self.message_sender
+                .send(TessellateMessage::Tile(TileTessellateMessage {
+                    request_id,
+                    coords: tile_request.coords,
+                }))?;
+        }
+
+        Ok(())
+    }
+
+    /// Reports a tile which could not be fetched or decoded. If the error is transient, the
+    /// layers of the tile are not marked as unavailable, such that the tile is requested again.
+    pub fn tile_failed(
+        &self,
+        coords: &WorldTileCoords,
+        request_id: TileRequestID,
+        error: Error,
+    ) -> Result<(), Error> {
+        if !error.is_transient() {
+            if let Some(tile_request) = self.get_tile_request(request_id) {
+                for layer_name in tile_request.layers {
+                    self.message_sender.send(TessellateMessage::Layer(
+                        LayerTessellateMessage::UnavailableLayer {
+                            coords: *coords,
+                            layer_name,
+                        },
+                    ))?;
+                }
+            }
         }
 
+        self.send_tile_failed(coords, request_id, error)
+    }
+
+    fn send_tile_failed(
+        &self,
+        coords: &WorldTileCoords,
+        request_id: TileRequestID,
+        error: Error,
+    ) -> Result<(), Error> {
+        tracing::warn!("tile {} failed to load: {:?}", coords, error);
+        self.message_sender
+            .send(TessellateMessage::TileFailed(TileFailedMessage {
+                request_id,
+                coords: *coords,
+                error,
+            }))?;
         Ok(())
     }
 
@@ -168,3 +242,102 @@ impl SharedThreadState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SharedThreadState;
+    use crate::error::Error;
+    use crate::io::geometry_index::GeometryIndex;
+    use crate::io::tile_request_state::TileRequestState;
+    use crate::io::{LayerTessellateMessage, TessellateMessage, TileFailedMessage, TileRequest};
+    use std::collections::HashSet;
+    use std::path::Path;
+    use std::sync::{mpsc, Arc, Mutex};
+
+    const BROKEN_TILES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../test-data/broken-tiles");
+
+    fn process(data: Vec<u8>, layers: &[&str]) -> Vec<TessellateMessage> {
+        let (message_sender, message_receiver) = mpsc::channel();
+        let state = SharedThreadState {
+            tile_request_state: Arc::new(Mutex::new(TileRequestState::new())),
+            message_sender,
+            geometry_index: Arc::new(Mutex::new(GeometryIndex::new())),
+            max_tile_size: 1024 * 1024,
+        };
+        let (request_id, _) = state
+            .tile_request_state
+            .lock()
+            .unwrap()
+            .start_tile_request(TileRequest {
+                coords: (0, 0, 0).into(),
+                layers: layers.iter().map(|layer| layer.to_string()).collect(),
+            })
+            .unwrap();
+
+        state
+            .process_tile(request_id, data.into_boxed_slice())
+            .unwrap();
+        message_receiver.try_iter().collect()
+    }
+
+    #[test]
+    fn test_broken_tiles() {
+        let mut fixtures = std::fs::read_dir(BROKEN_TILES)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().map(|ext| ext == "pbf").unwrap_or(false))
+            // Tested separately, because its water layer is valid
+            .filter(|path| !path.ends_with("partially-broken.pbf"))
+            .collect::<Vec<_>>();
+        fixtures.sort();
+        assert!(!fixtures.is_empty());
+
+        for fixture in fixtures {
+            let messages = process(std::fs::read(&fixture).unwrap(), &["water"]);
+
+            let failed = messages.iter().any(|message| {
+                matches!(
+                    message,
+                    TessellateMessage::TileFailed(TileFailedMessage {
+                        error: Error::Decode(_) | Error::Tesselation(_),
+                        ..
+                    })
+                )
+            });
+            assert!(failed, "{:?} was not reported as failed", fixture);
+
+            // All requested layers are resolved, such that the tile is not requested again
+            let unavailable = messages
+                .iter()
+                .filter(|message| {
+                    matches!(
+                        message,
+                        TessellateMessage::Layer(LayerTessellateMessage::UnavailableLayer { .. })
+                    )
+                })
+                .count();
+            assert_eq!(unavailable, 1, "{:?}", fixture);
+        }
+    }
+
+    #[test]
+    fn test_partially_broken_tile() {
+        let data = std::fs::read(Path::new(BROKEN_TILES).join("partially-broken.pbf")).unwrap();
+        let messages = process(data, &["water", "transportation"]);
+
+        let layers = messages
+            .iter()
+            .filter_map(|message| match message {
+                TessellateMessage::Layer(LayerTessellateMessage::TessellatedLayer {
+                    layer_data,
+                    ..
+                }) => Some(layer_data.name.as_str()),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        assert_eq!(layers, HashSet::from(["water"]));
+        assert!(messages
+            .iter()
+            .any(|message| matches!(message, TessellateMessage::TileFailed(_))));
+    }
+}
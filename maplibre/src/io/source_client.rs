@@ -2,8 +2,17 @@
 
 use crate::coords::WorldTileCoords;
 use crate::error::Error;
-use crate::style::source::TileAddressingScheme;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::io::directory_source_client::DirectorySourceClient;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::io::mbtiles_source_client::MbtilesSourceClient;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::io::offline_store::OfflineStore;
+use crate::style::source::{TileAddressingScheme, VectorSource};
+use crate::tilejson::TileJSON;
 use async_trait::async_trait;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
 
 /// A closure that returns a HTTP client.
 pub type HTTPClientFactory<HC> = dyn Fn() -> HC;
@@ -17,7 +26,139 @@ pub type HTTPClientFactory<HC> = dyn Fn() -> HC;
 #[cfg_attr(feature = "no-thread-safe-futures", async_trait(?Send))]
 #[cfg_attr(not(feature = "no-thread-safe-futures"), async_trait)]
 pub trait HTTPClient: Clone + Sync + Send + 'static {
-    async fn fetch(&self, url: &str) -> Result<Vec<u8>, Error>;
+    /// Fetches the resource of the given `kind` at `url`. If a [`TransformRequest`] is set, then
+    /// the request is sent as returned by it.
+    async fn fetch(&self, url: &str, kind: ResourceKind) -> Result<Vec<u8>, Error>;
+
+    /// Sets the callback which transforms each request before it is sent.
+    fn with_transform_request(self, transform_request: Arc<TransformRequest>) -> Self;
+}
+
+/// The kinds of resources which are requested by the map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ResourceKind {
+    Style,
+    /// A TileJSON document which describes a source.
+    Source,
+    Tile,
+    Glyphs,
+    /// The image or the index of a sprite.
+    Sprite,
+}
+
+/// Whether cookies and HTTP authentication are sent along with a request. This follows the
+/// [credentials mode](https://developer.mozilla.org/en-US/docs/Web/API/Request/credentials) of the
+/// Fetch API and only has an effect on the web platform.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Credentials {
+    Omit,
+    SameOrigin,
+    Include,
+}
+
+impl Default for Credentials {
+    fn default() -> Self {
+        Credentials::SameOrigin
+    }
+}
+
+/// A HTTP request as returned by a [`TransformRequest`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request {
+    pub url: String,
+    /// Additional headers, for example `Authorization`.
+    pub headers: Vec<(String, String)>,
+    pub credentials: Credentials,
+}
+
+impl Request {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            headers: Vec::new(),
+            credentials: Credentials::default(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// A callback which transforms the URL of a resource into the request which is sent, for example
+/// to add authorization headers or to sign URLs. Responses are cached by the original URL.
+pub type TransformRequest = dyn Fn(&str, ResourceKind) -> Request + Send + Sync;
+
+/// The tile URLs of a source, as given by the style source or by the [`TileJSON`] document which
+/// it references.
+#[derive(Clone, Debug)]
+pub struct SourceTiles {
+    pub tiles: Vec<String>,
+    pub minzoom: Option<u8>,
+    pub maxzoom: Option<u8>,
+    pub scheme: TileAddressingScheme,
+}
+
+impl SourceTiles {
+    /// Resolves the tile URLs of `source`. If the source has no `tiles`, then the TileJSON at
+    /// its `url` is requested via `fetch`. Properties of the source take precedence over the
+    /// TileJSON.
+    pub async fn resolve<F, Fut, E>(source: &VectorSource, fetch: F) -> Result<Self, E>
+    where
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = Result<Vec<u8>, E>>,
+        E: From<Error>,
+    {
+        let resolved = match (&source.tiles, &source.url) {
+            (Some(tiles), _) => SourceTiles {
+                tiles: tiles.clone(),
+                minzoom: None,
+                maxzoom: None,
+                scheme: TileAddressingScheme::default(),
+            },
+            (None, Some(url)) => {
+                let data = fetch(url.clone()).await?;
+                let tile_json: TileJSON = serde_json::from_slice(&data)
+                    .map_err(|e| Error::Decode(format!("invalid TileJSON {}: {}", url, e)))?;
+                SourceTiles {
+                    tiles: tile_json.tiles,
+                    minzoom: tile_json.minzoom,
+                    maxzoom: tile_json.maxzoom,
+                    scheme: match tile_json.scheme.as_deref() {
+                        Some("tms") => TileAddressingScheme::TMS,
+                        _ => TileAddressingScheme::XYZ,
+                    },
+                }
+            }
+            (None, None) => SourceTiles {
+                tiles: Vec::new(),
+                minzoom: None,
+                maxzoom: None,
+                scheme: TileAddressingScheme::default(),
+            },
+        };
+
+        Ok(SourceTiles {
+            tiles: resolved.tiles,
+            minzoom: source.minzoom.or(resolved.minzoom),
+            maxzoom: source.maxzoom.or(resolved.maxzoom),
+            scheme: source.scheme.clone().unwrap_or(resolved.scheme),
+        })
+    }
+
+    /// Returns the URL of the tile at `coords`. Only the first template is used, such that a tile
+    /// is always requested and stored by the same URL.
+    pub fn tile_url(&self, coords: &WorldTileCoords) -> Option<String> {
+        let template = self.tiles.first()?;
+        let tile = coords.into_tile(self.scheme.clone())?;
+        Some(
+            template
+                .replace("{z}", &tile.z.to_string())
+                .replace("{x}", &tile.x.to_string())
+                .replace("{y}", &tile.y.to_string()),
+        )
+    }
 }
 
 /// Gives access to the HTTP client which can be of multiple types,
@@ -28,6 +169,11 @@ where
     HC: HTTPClient,
 {
     inner_client: HC,
+    source: VectorSource,
+    /// The tile URLs of `source`, which are resolved by the first request.
+    source_tiles: Arc<Mutex<Option<SourceTiles>>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    offline_store: Option<OfflineStore>,
 }
 
 /// Defines the different types of HTTP clients such as basic HTTP and Mbtiles.
@@ -38,19 +184,68 @@ where
     HC: HTTPClient,
 {
     Http(HttpSourceClient<HC>),
-    Mbtiles {
-        // TODO
-    },
+    #[cfg(not(target_arch = "wasm32"))]
+    Mbtiles(MbtilesSourceClient),
+    #[cfg(not(target_arch = "wasm32"))]
+    Directory(DirectorySourceClient),
 }
 
 impl<HC> SourceClient<HC>
 where
     HC: HTTPClient,
 {
+    /// Creates the client which loads the tiles of the style source `source`. Tiles with a
+    /// `file://` URL are read from the local file system, all other tiles are requested via
+    /// `http_client`.
+    pub fn from_source(http_client: HC, source: &VectorSource) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(url) = source.tiles.as_ref().and_then(|tiles| tiles.first()) {
+            if url.starts_with("file://") {
+                match DirectorySourceClient::from_url(url) {
+                    Ok(client) => {
+                        return SourceClient::Directory(
+                            client.with_scheme(source.scheme.clone().unwrap_or_default()),
+                        )
+                    }
+                    Err(e) => log::error!("failed to read tiles from {}: {:?}", url, e),
+                }
+            }
+        }
+
+        SourceClient::Http(HttpSourceClient::new(http_client).with_source(source.clone()))
+    }
+
+    /// Reads tiles of HTTP clients from `offline_store` before requesting them.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_offline_store(self, offline_store: OfflineStore) -> Self {
+        match self {
+            SourceClient::Http(client) => {
+                SourceClient::Http(client.with_offline_store(offline_store))
+            }
+            client => client,
+        }
+    }
+
+    /// Sets the callback which transforms requests of HTTP clients.
+    pub fn with_transform_request(self, transform_request: Arc<TransformRequest>) -> Self {
+        match self {
+            SourceClient::Http(client) => SourceClient::Http(HttpSourceClient {
+                inner_client: client
+                    .inner_client
+                    .with_transform_request(transform_request),
+                ..client
+            }),
+            client => client,
+        }
+    }
+
     pub async fn fetch(&self, coords: &WorldTileCoords) -> Result<Vec<u8>, Error> {
         match self {
             SourceClient::Http(client) => client.fetch(coords).await,
-            SourceClient::Mbtiles { .. } => unimplemented!(),
+            #[cfg(not(target_arch = "wasm32"))]
+            SourceClient::Mbtiles(client) => client.fetch(coords).await,
+            #[cfg(not(target_arch = "wasm32"))]
+            SourceClient::Directory(client) => client.fetch(coords).await,
         }
     }
 }
@@ -59,24 +254,139 @@ impl<HC> HttpSourceClient<HC>
 where
     HC: HTTPClient,
 {
+    /// Creates a client which loads tiles from the default tile server. Use
+    /// [`HttpSourceClient::with_source`] to load the tiles of a style source.
     pub fn new(http_client: HC) -> Self {
         Self {
             inner_client: http_client,
+            source: VectorSource {
+                attribution: None,
+                bounds: None,
+                maxzoom: None,
+                minzoom: None,
+                scheme: Some(TileAddressingScheme::TMS),
+                tiles: Some(vec![
+                    "https://maps.tuerantuer.org/europe_germany/{z}/{x}/{y}.pbf".to_string(),
+                ]),
+                url: None,
+            },
+            source_tiles: Arc::new(Mutex::new(None)),
+            #[cfg(not(target_arch = "wasm32"))]
+            offline_store: None,
+        }
+    }
+
+    /// Loads the tiles of the style source `source`.
+    pub fn with_source(mut self, source: VectorSource) -> Self {
+        self.source = source;
+        self.source_tiles = Arc::new(Mutex::new(None));
+        self
+    }
+
+    /// Reads tiles from `offline_store` before requesting them via HTTP.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_offline_store(mut self, offline_store: OfflineStore) -> Self {
+        self.offline_store = Some(offline_store);
+        self
+    }
+
+    async fn source_tiles(&self) -> Result<SourceTiles, Error> {
+        let cached = self
+            .source_tiles
+            .lock()
+            .ok()
+            .and_then(|source_tiles| source_tiles.clone());
+        if let Some(source_tiles) = cached {
+            return Ok(source_tiles);
+        }
+
+        let source_tiles = SourceTiles::resolve(&self.source, |url| async move {
+            self.fetch_resource(&url, ResourceKind::Source).await
+        })
+        .await?;
+        if let Ok(mut cached) = self.source_tiles.lock() {
+            *cached = Some(source_tiles.clone());
+        }
+        Ok(source_tiles)
+    }
+
+    async fn fetch_resource(&self, url: &str, kind: ResourceKind) -> Result<Vec<u8>, Error> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(offline_store) = &self.offline_store {
+            match offline_store.get_resource(url).await {
+                Ok(Some(data)) => return Ok(data),
+                Ok(None) => {}
+                Err(e) => log::warn!("reading {} from the offline store failed: {}", url, e),
+            }
         }
+
+        self.inner_client.fetch(url, kind).await
     }
 
     pub async fn fetch(&self, coords: &WorldTileCoords) -> Result<Vec<u8>, Error> {
-        let tile_coords = coords.into_tile(TileAddressingScheme::TMS).unwrap();
-        self.inner_client
-            .fetch(
-                format!(
-                    "https://maps.tuerantuer.org/europe_germany/{z}/{x}/{y}.pbf",
-                    x = tile_coords.x,
-                    y = tile_coords.y,
-                    z = tile_coords.z
-                )
-                .as_str(),
-            )
-            .await
+        let url =
+            self.source_tiles().await?.tile_url(coords).ok_or_else(|| {
+                Error::NotFound(format!("the source has no URL for tile {}", coords))
+            })?;
+        self.fetch_resource(&url, ResourceKind::Tile).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        HTTPClient, HttpSourceClient, Request, ResourceKind, SourceClient, TransformRequest,
+    };
+    use crate::coords::WorldTileCoords;
+    use crate::error::Error;
+    use async_trait::async_trait;
+    use std::sync::{Arc, Mutex};
+
+    /// Records the requests as they would be sent.
+    #[derive(Clone, Default)]
+    struct RecordingHttpClient {
+        requests: Arc<Mutex<Vec<Request>>>,
+        transform_request: Option<Arc<TransformRequest>>,
+    }
+
+    #[cfg_attr(feature = "no-thread-safe-futures", async_trait(?Send))]
+    #[cfg_attr(not(feature = "no-thread-safe-futures"), async_trait)]
+    impl HTTPClient for RecordingHttpClient {
+        async fn fetch(&self, url: &str, kind: ResourceKind) -> Result<Vec<u8>, Error> {
+            let request = match &self.transform_request {
+                Some(transform_request) => transform_request(url, kind),
+                None => Request::new(url),
+            };
+            self.requests.lock().unwrap().push(request);
+            Ok(vec![])
+        }
+
+        fn with_transform_request(mut self, transform_request: Arc<TransformRequest>) -> Self {
+            self.transform_request = Some(transform_request);
+            self
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transform_request() {
+        let http_client = RecordingHttpClient::default();
+        let requests = http_client.requests.clone();
+        let source_client = SourceClient::Http(HttpSourceClient::new(http_client))
+            .with_transform_request(Arc::new(|url, kind| {
+                assert_eq!(kind, ResourceKind::Tile);
+                Request::new(&format!("{}?signature=abc", url))
+                    .with_header("Authorization", "Bearer token")
+            }));
+
+        let coords: WorldTileCoords = (0, 0, 0).into();
+        source_client.fetch(&coords).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].url.ends_with("/0/0/0.pbf?signature=abc"));
+        assert_eq!(
+            requests[0].headers,
+            vec![("Authorization".to_string(), "Bearer token".to_string())]
+        );
     }
 }
@@ -4,31 +4,116 @@ use crate::coords::{Quadkey, WorldTileCoords};
 
 use crate::io::LayerTessellateMessage;
 
-use std::collections::{btree_map, BTreeMap, HashSet};
+use std::collections::{btree_map, BTreeMap, BTreeSet, HashSet};
 
 /// Stores the multiple [crate::io::LayerTessellateMessage] of a cached tile.
 pub struct CachedTile {
+    coords: WorldTileCoords,
     layers: Vec<LayerTessellateMessage>,
+    /// The estimated memory of all layers in bytes
+    size: usize,
+    /// The value of the cache's clock when the tile has been used last
+    last_used: u64,
 }
 
 impl CachedTile {
     pub fn new(first_layer: LayerTessellateMessage) -> Self {
         Self {
+            coords: first_layer.get_coords(),
+            size: first_layer.size_bytes(),
             layers: vec![first_layer],
+            last_used: 0,
         }
     }
 }
 
 /// Stores and provides access to a quad tree of cached tiles with world tile coords.
-#[derive(Default)]
+///
+/// The memory of the cached tiles is limited by a budget. If it is exceeded, then the least
+/// recently used tiles are evicted. Pinned tiles are never evicted. The coordinates of evicted
+/// tiles are collected such that other stores of tile data can be kept consistent, see
+/// [`TileCache::take_evicted`].
 pub struct TileCache {
     cache: BTreeMap<Quadkey, CachedTile>,
+    /// The estimated memory of all cached tiles in bytes
+    size: usize,
+    budget: usize,
+    /// Incremented whenever tiles are used
+    clock: u64,
+    pinned: BTreeSet<Quadkey>,
+    evicted: Vec<WorldTileCoords>,
 }
 
 impl TileCache {
-    pub fn new() -> Self {
+    /// Creates a cache which holds tiles with an estimated memory of at most `budget` bytes.
+    pub fn new(budget: usize) -> Self {
         Self {
             cache: BTreeMap::new(),
+            size: 0,
+            budget,
+            clock: 0,
+            pinned: BTreeSet::new(),
+            evicted: Vec::new(),
+        }
+    }
+
+    /// The estimated memory of all cached tiles in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// The number of cached tiles.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// Pins the given tiles, for example because they are visible. Tiles which have been pinned
+    /// before are unpinned. Pinned tiles are never evicted, even if the budget is exceeded.
+    pub fn pin_tiles<'a>(&mut self, coords: impl Iterator<Item = &'a WorldTileCoords>) {
+        self.clock += 1;
+        self.pinned.clear();
+
+        for key in coords.filter_map(|coords| coords.build_quad_key()) {
+            if let Some(cached_tile) = self.cache.get_mut(&key) {
+                cached_tile.last_used = self.clock;
+            }
+            self.pinned.insert(key);
+        }
+
+        self.evict_to_budget();
+    }
+
+    /// Returns the coordinates of the tiles which have been evicted since the last call.
+    pub fn take_evicted(&mut self) -> Vec<WorldTileCoords> {
+        std::mem::take(&mut self.evicted)
+    }
+
+    /// Evicts the least recently used tiles which are not pinned until the budget is met.
+    fn evict_to_budget(&mut self) {
+        while self.size > self.budget {
+            let least_recently_used = self
+                .cache
+                .iter()
+                .filter(|(key, _)| !self.pinned.contains(key))
+                .min_by_key(|(_, cached_tile)| cached_tile.last_used)
+                .map(|(key, _)| *key);
+
+            if let Some(cached_tile) = least_recently_used.and_then(|key| self.cache.remove(&key)) {
+                tracing::trace!("Evicting tile at {} from cache", cached_tile.coords);
+                self.size -= cached_tile.size;
+                self.evicted.push(cached_tile.coords);
+            } else {
+                // All remaining tiles are pinned
+                break;
+            }
         }
     }
 
@@ -37,21 +122,32 @@ impl TileCache {
     /// [crate::io::tile_cache::CachedTile].
     /// If the space is occupied, the tessellated layer is added to the current
     /// [crate::io::tile_cache::CachedTile].
+    ///
+    /// Afterwards tiles are evicted if the budget is exceeded.
     pub fn put_tessellated_layer(&mut self, message: LayerTessellateMessage) {
+        let size = message.size_bytes();
+
         if let Some(entry) = message
             .get_coords()
             .build_quad_key()
             .map(|key| self.cache.entry(key))
         {
-            match entry {
-                btree_map::Entry::Vacant(entry) => {
-                    entry.insert(CachedTile::new(message));
-                }
-                btree_map::Entry::Occupied(mut entry) => {
-                    entry.get_mut().layers.push(message);
+            let cached_tile = match entry {
+                btree_map::Entry::Vacant(entry) => entry.insert(CachedTile::new(message)),
+                btree_map::Entry::Occupied(entry) => {
+                    let cached_tile = entry.into_mut();
+                    cached_tile.layers.push(message);
+                    cached_tile.size += size;
+                    cached_tile
                 }
-            }
+            };
+
+            self.clock += 1;
+            cached_tile.last_used = self.clock;
+            self.size += size;
         }
+
+        self.evict_to_budget();
     }
 
     /// Returns the list of tessellated layers at the given world tile coords. None if tile is
@@ -104,3 +200,67 @@ impl TileCache {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::coords::WorldTileCoords;
+    use crate::io::tile_cache::TileCache;
+    use crate::io::LayerTessellateMessage;
+
+    fn unavailable_layer(coords: WorldTileCoords, layer_name: &str) -> LayerTessellateMessage {
+        LayerTessellateMessage::UnavailableLayer {
+            coords,
+            layer_name: layer_name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let first: WorldTileCoords = (0, 0, 1).into();
+        let second: WorldTileCoords = (1, 0, 1).into();
+        let third: WorldTileCoords = (0, 1, 1).into();
+
+        let layer_size = unavailable_layer(first, "water").size_bytes();
+        let mut cache = TileCache::new(layer_size * 3);
+
+        cache.put_tessellated_layer(unavailable_layer(first, "water"));
+        cache.put_tessellated_layer(unavailable_layer(first, "roads"));
+        cache.put_tessellated_layer(unavailable_layer(second, "water"));
+        assert_eq!(cache.size(), layer_size * 3);
+        assert!(cache.take_evicted().is_empty());
+
+        // The first tile has been used least recently
+        cache.put_tessellated_layer(unavailable_layer(third, "water"));
+        assert_eq!(cache.take_evicted(), vec![first]);
+        assert_eq!(cache.size(), layer_size * 2);
+        assert!(cache.iter_tessellated_layers_at(&first).is_none());
+        assert!(cache.iter_tessellated_layers_at(&second).is_some());
+    }
+
+    #[test]
+    fn test_pinned_tiles_are_not_evicted() {
+        let first: WorldTileCoords = (0, 0, 1).into();
+        let second: WorldTileCoords = (1, 0, 1).into();
+        let third: WorldTileCoords = (0, 1, 1).into();
+
+        let layer_size = unavailable_layer(first, "water").size_bytes();
+        let mut cache = TileCache::new(layer_size * 2);
+
+        cache.put_tessellated_layer(unavailable_layer(first, "water"));
+        cache.put_tessellated_layer(unavailable_layer(second, "water"));
+        cache.pin_tiles([first, third].iter());
+
+        cache.put_tessellated_layer(unavailable_layer(third, "water"));
+        assert_eq!(cache.take_evicted(), vec![second]);
+
+        // The budget is exceeded as long as all tiles are pinned
+        cache.put_tessellated_layer(unavailable_layer(third, "roads"));
+        assert!(cache.take_evicted().is_empty());
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.size(), layer_size * 3);
+
+        cache.pin_tiles([third].iter());
+        assert_eq!(cache.take_evicted(), vec![first]);
+        assert_eq!(cache.size(), layer_size * 2);
+    }
+}
@@ -0,0 +1,200 @@
+//! Tracks tiles which failed to load and schedules their retries.
+
+use crate::coords::WorldTileCoords;
+use crate::error::Error;
+use crate::io::settings::RetrySettings;
+use instant::Instant;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+/// Reported to the application when a tile failed to load.
+#[derive(Debug)]
+pub struct TileErrorEvent {
+    pub coords: WorldTileCoords,
+    pub error: Error,
+    /// The number of consecutive failed attempts to load the tile.
+    pub attempts: u32,
+    /// The time after which the tile is requested again if it is still in view. Tiles which
+    /// failed because of an error which is not transient, like a malformed tile, are not retried.
+    pub retry_in: Option<Duration>,
+}
+
+/// A callback which is called for every [`TileErrorEvent`].
+pub type TileErrorHandler = dyn FnMut(&TileErrorEvent);
+
+struct TileError {
+    attempts: u32,
+    retry_at: Instant,
+}
+
+/// The error state of tiles which failed to load. A tile is only requested again once its backoff
+/// has elapsed. The state of tiles which leave the view is forgotten, such that they are requested
+/// immediately when they come back into view.
+#[derive(Default)]
+pub struct TileErrors {
+    errors: HashMap<WorldTileCoords, TileError>,
+}
+
+/// Returns a pseudo-random number in `[0, 1)` which is stable for a tile and attempt. This
+/// spreads the retries of tiles which failed at the same time.
+fn jitter_factor(coords: &WorldTileCoords, attempts: u32) -> f64 {
+    let mut hasher = DefaultHasher::new();
+    coords.hash(&mut hasher);
+    attempts.hash(&mut hasher);
+    (hasher.finish() % 1024) as f64 / 1024.0
+}
+
+/// The exponential backoff before the next attempt after `attempts` failed attempts. The backoff
+/// is reduced by up to [`RetrySettings::jitter`].
+pub fn backoff(settings: &RetrySettings, coords: &WorldTileCoords, attempts: u32) -> Duration {
+    let exponent = attempts.saturating_sub(1).min(16) as i32;
+    let backoff = (settings.initial_backoff.as_secs_f64() * 2f64.powi(exponent))
+        .min(settings.max_backoff.as_secs_f64());
+    let jitter = settings.jitter.clamp(0.0, 1.0) * jitter_factor(coords, attempts);
+    Duration::from_secs_f64(backoff * (1.0 - jitter))
+}
+
+impl TileErrors {
+    /// Records a failed attempt and returns the event which is reported to the application.
+    pub fn record_failure(
+        &mut self,
+        coords: &WorldTileCoords,
+        error: Error,
+        now: Instant,
+        settings: &RetrySettings,
+    ) -> TileErrorEvent {
+        if !error.is_transient() {
+            self.errors.remove(coords);
+            return TileErrorEvent {
+                coords: *coords,
+                error,
+                attempts: 1,
+                retry_in: None,
+            };
+        }
+
+        let attempts = self
+            .errors
+            .get(coords)
+            .map(|error| error.attempts + 1)
+            .unwrap_or(1);
+        let retry_in = backoff(settings, coords, attempts);
+        self.errors.insert(
+            *coords,
+            TileError {
+                attempts,
+                retry_at: now + retry_in,
+            },
+        );
+
+        TileErrorEvent {
+            coords: *coords,
+            error,
+            attempts,
+            retry_in: Some(retry_in),
+        }
+    }
+
+    /// Forgets the errors of a tile which was loaded successfully.
+    pub fn record_success(&mut self, coords: &WorldTileCoords) {
+        self.errors.remove(coords);
+    }
+
+    /// Returns true if the tile has not failed or if its backoff has elapsed.
+    pub fn can_request(&self, coords: &WorldTileCoords, now: Instant) -> bool {
+        self.errors
+            .get(coords)
+            .map(|error| error.retry_at <= now)
+            .unwrap_or(true)
+    }
+
+    /// Returns true if the backoff of a failed tile has elapsed.
+    pub fn has_due_retries(&self, now: Instant) -> bool {
+        self.errors.values().any(|error| error.retry_at <= now)
+    }
+
+    /// Forgets the errors of tiles for which `predicate` returns false.
+    pub fn retain<P>(&mut self, mut predicate: P)
+    where
+        P: FnMut(&WorldTileCoords) -> bool,
+    {
+        self.errors.retain(|coords, _| predicate(coords));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backoff, TileErrors};
+    use crate::coords::WorldTileCoords;
+    use crate::error::Error;
+    use crate::io::settings::RetrySettings;
+    use instant::Instant;
+    use std::time::Duration;
+
+    #[test]
+    fn test_backoff() {
+        let settings = RetrySettings {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+            jitter: 0.0,
+        };
+        let coords: WorldTileCoords = (0, 0, 0).into();
+        assert_eq!(backoff(&settings, &coords, 1), Duration::from_secs(1));
+        assert_eq!(backoff(&settings, &coords, 3), Duration::from_secs(4));
+        assert_eq!(backoff(&settings, &coords, 10), Duration::from_secs(10));
+
+        let settings = RetrySettings {
+            jitter: 0.5,
+            ..settings
+        };
+        for attempts in 1..10 {
+            let jittered = backoff(&settings, &coords, attempts);
+            let full = Duration::from_secs((1 << (attempts - 1)).min(10));
+            assert!(jittered <= full && jittered >= full / 2);
+        }
+    }
+
+    #[test]
+    fn test_tile_errors() {
+        let settings = RetrySettings {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+            jitter: 0.0,
+        };
+        let now = Instant::now();
+        let coords: WorldTileCoords = (0, 0, 0).into();
+        let mut errors = TileErrors::default();
+        assert!(errors.can_request(&coords, now));
+
+        let timeout = || Error::Network("timeout".to_string());
+        let event = errors.record_failure(&coords, timeout(), now, &settings);
+        assert_eq!(event.attempts, 1);
+        assert!(!errors.can_request(&coords, now));
+        assert!(!errors.has_due_retries(now));
+        assert!(errors.can_request(&coords, now + Duration::from_secs(1)));
+        assert!(errors.has_due_retries(now + Duration::from_secs(1)));
+
+        let event = errors.record_failure(&coords, timeout(), now, &settings);
+        assert_eq!(event.attempts, 2);
+        assert_eq!(event.retry_in, Some(Duration::from_secs(2)));
+
+        // Malformed tiles are not retried
+        let event = errors.record_failure(&coords, Error::Decode("".to_string()), now, &settings);
+        assert_eq!(event.retry_in, None);
+        assert!(errors.is_empty());
+
+        // Tiles which leave the view are retried immediately when they come back
+        errors.retain(|_| false);
+        assert!(errors.can_request(&coords, now));
+
+        errors.record_failure(&coords, timeout(), now, &settings);
+        errors.record_success(&coords);
+        assert!(errors.is_empty());
+    }
+}
@@ -2,13 +2,21 @@
 
 use crate::coords::WorldTileCoords;
 use crate::io::{TileRequest, TileRequestID};
+use futures::future::{AbortHandle, AbortRegistration};
 use std::collections::{HashMap, HashSet};
 
+/// A request which is being fetched or tessellated. The handle allows aborting the future which
+/// loads the tile.
+struct PendingTileRequest {
+    tile_request: TileRequest,
+    abort_handle: AbortHandle,
+}
+
 /// Stores a map of pending requests, coords and the current tile being requested.
 #[derive(Default)]
 pub struct TileRequestState {
     current_id: TileRequestID,
-    pending_tile_requests: HashMap<TileRequestID, TileRequest>,
+    pending_tile_requests: HashMap<TileRequestID, PendingTileRequest>,
     pending_coords: HashSet<WorldTileCoords>,
 }
 
@@ -25,26 +33,118 @@ impl TileRequestState {
         self.pending_coords.contains(coords)
     }
 
-    pub fn start_tile_request(&mut self, tile_request: TileRequest) -> Option<TileRequestID> {
+    /// Returns true if there are tiles which are requested but not yet tessellated.
+    pub fn has_pending_tile_requests(&self) -> bool {
+        !self.pending_tile_requests.is_empty()
+    }
+
+    /// Returns the number of tiles which are requested but not yet tessellated.
+    pub fn pending_tile_requests_count(&self) -> usize {
+        self.pending_tile_requests.len()
+    }
+
+    /// Starts a request if there is no pending request for the same coordinates. The returned
+    /// registration is used to create the abortable future which loads the tile.
+    pub fn start_tile_request(
+        &mut self,
+        tile_request: TileRequest,
+    ) -> Option<(TileRequestID, AbortRegistration)> {
         if self.is_tile_request_pending(&tile_request.coords) {
             return None;
         }
 
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
         self.pending_coords.insert(tile_request.coords);
         let id = self.current_id;
-        self.pending_tile_requests.insert(id, tile_request);
+        self.pending_tile_requests.insert(
+            id,
+            PendingTileRequest {
+                tile_request,
+                abort_handle,
+            },
+        );
         self.current_id += 1;
-        Some(id)
+        Some((id, abort_registration))
     }
 
     pub fn finish_tile_request(&mut self, id: TileRequestID) -> Option<TileRequest> {
         self.pending_tile_requests.remove(&id).map(|request| {
-            self.pending_coords.remove(&request.coords);
-            request
+            self.pending_coords.remove(&request.tile_request.coords);
+            request.tile_request
         })
     }
 
+    /// Aborts a pending request. Results which are sent by the request after it has been
+    /// cancelled are ignored.
+    pub fn cancel_tile_request(&mut self, id: TileRequestID) -> Option<TileRequest> {
+        self.pending_tile_requests.get(&id)?.abort_handle.abort();
+        self.finish_tile_request(id)
+    }
+
+    /// Aborts all pending requests for which `predicate` returns false and returns the number of
+    /// cancelled requests.
+    pub fn retain_tile_requests<P>(&mut self, mut predicate: P) -> usize
+    where
+        P: FnMut(&TileRequest) -> bool,
+    {
+        let cancelled = self
+            .pending_tile_requests
+            .iter()
+            .filter(|(_, request)| !predicate(&request.tile_request))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        for id in &cancelled {
+            self.cancel_tile_request(*id);
+        }
+
+        cancelled.len()
+    }
+
     pub fn get_tile_request(&self, id: TileRequestID) -> Option<&TileRequest> {
-        self.pending_tile_requests.get(&id)
+        self.pending_tile_requests
+            .get(&id)
+            .map(|request| &request.tile_request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coords::WorldTileCoords;
+    use crate::io::tile_request_state::TileRequestState;
+    use crate::io::TileRequest;
+    use futures::future::Abortable;
+    use futures::FutureExt;
+    use std::collections::HashSet;
+
+    fn tile_request(x: i32) -> TileRequest {
+        TileRequest {
+            coords: WorldTileCoords::from((x, 0, 1)),
+            layers: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn test_cancel_tile_requests() {
+        let mut state = TileRequestState::new();
+
+        let (first, first_registration) = state.start_tile_request(tile_request(0)).unwrap();
+        let (second, _) = state.start_tile_request(tile_request(1)).unwrap();
+        assert!(state.start_tile_request(tile_request(0)).is_none());
+        assert_eq!(state.pending_tile_requests_count(), 2);
+
+        assert_eq!(
+            state.retain_tile_requests(|request| request.coords.x == 1),
+            1
+        );
+        assert!(!state.is_tile_request_pending(&WorldTileCoords::from((0, 0, 1))));
+        assert!(state.get_tile_request(first).is_none());
+        assert!(state.get_tile_request(second).is_some());
+
+        let aborted = Abortable::new(async {}, first_registration).now_or_never();
+        assert!(matches!(aborted, Some(Err(_))));
+
+        // Cancelled tiles can be requested again
+        assert!(state.start_tile_request(tile_request(0)).is_some());
     }
 }
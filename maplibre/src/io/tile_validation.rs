@@ -0,0 +1,204 @@
+//! Validation of decoded vector tiles.
+//!
+//! The MVT reader of geozero assumes well-formed geometry commands and panics on truncated
+//! geometries or coordinates which overflow. Layers are therefore validated before they are
+//! processed.
+
+use crate::error::Error;
+use geozero::mvt::tile;
+use geozero::mvt::tile::GeomType;
+
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+const CLOSE_PATH: u32 = 7;
+
+fn command(id: u32, count: u32) -> u32 {
+    (id & 0x7) | (count << 3)
+}
+
+fn command_id(command: u32) -> u32 {
+    command & 0x7
+}
+
+fn command_count(command: u32) -> usize {
+    (command >> 3) as usize
+}
+
+fn parameter(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ (-((value & 1) as i32))
+}
+
+fn malformed(layer: &tile::Layer, feature: usize, reason: &str) -> Error {
+    Error::Decode(format!(
+        "feature {} of layer {}: {}",
+        feature, layer.name, reason
+    ))
+}
+
+/// Moves the cursor by the parameters of a command. Fails if the position does not fit into an
+/// `i32`.
+fn move_cursor(cursor: [i32; 2], parameters: &[u32]) -> Option<[i32; 2]> {
+    Some([
+        cursor[0].checked_add(parameter(parameters[0]))?,
+        cursor[1].checked_add(parameter(parameters[1]))?,
+    ])
+}
+
+/// Returns the next ring or line of a geometry, which starts with a `MoveTo` which is followed by
+/// a `LineTo`, and the remaining geometry.
+fn split_path(geometry: &[u32], closed: bool) -> Result<(&[u32], &[u32]), &'static str> {
+    if geometry.len() < 4 {
+        return Err("truncated geometry");
+    }
+    if geometry[0] != command(MOVE_TO, 1) {
+        return Err("path does not start with a MoveTo");
+    }
+    if command_id(geometry[3]) != LINE_TO {
+        return Err("MoveTo is not followed by a LineTo");
+    }
+    let size = 4 + command_count(geometry[3]) * 2 + if closed { 1 } else { 0 };
+    if geometry.len() < size {
+        return Err("truncated geometry");
+    }
+    let (path, rest) = geometry.split_at(size);
+    if closed && path[size - 1] != command(CLOSE_PATH, 1) {
+        return Err("ring is not closed");
+    }
+    Ok((path, rest))
+}
+
+/// Replays the coordinates of a path like geozero does, including the computation of the area of
+/// rings.
+fn check_path(cursor: &mut [i32; 2], path: &[u32], ring: bool) -> Result<(), &'static str> {
+    const OVERFLOW: &str = "coordinates overflow";
+
+    let mut coordinates = vec![&path[1..3]];
+    coordinates.extend(path[4..4 + command_count(path[3]) * 2].chunks(2));
+
+    if ring {
+        let mut ring_cursor = *cursor;
+        let mut area: i32 = 0;
+        for parameters in coordinates.iter().chain(std::iter::once(&&path[1..3])) {
+            let [x0, y0] = ring_cursor;
+            ring_cursor = move_cursor(ring_cursor, parameters).ok_or(OVERFLOW)?;
+            area = x0
+                .checked_mul(ring_cursor[1])
+                .zip(ring_cursor[0].checked_mul(y0))
+                .and_then(|(a, b)| a.checked_sub(b))
+                .and_then(|cross| area.checked_add(cross))
+                .ok_or(OVERFLOW)?;
+        }
+    }
+
+    for parameters in coordinates {
+        *cursor = move_cursor(*cursor, parameters).ok_or(OVERFLOW)?;
+    }
+    Ok(())
+}
+
+fn check_geometry(feature: &tile::Feature) -> Result<(), &'static str> {
+    let mut cursor = [0, 0];
+    let mut geometry: &[u32] = &feature.geometry;
+
+    match feature.r#type {
+        Some(r#type) if r#type == GeomType::Point as i32 => {
+            let count = geometry.first().map(|c| command_count(*c)).unwrap_or(0);
+            if geometry.len() != 1 + count * 2 {
+                return Err("truncated geometry");
+            }
+            for parameters in geometry[1..].chunks(2) {
+                cursor = move_cursor(cursor, parameters).ok_or("coordinates overflow")?;
+            }
+        }
+        Some(r#type) if r#type == GeomType::Linestring as i32 => {
+            if geometry.is_empty() {
+                return Err("empty geometry");
+            }
+            while !geometry.is_empty() {
+                let (path, rest) = split_path(geometry, false)?;
+                check_path(&mut cursor, path, false)?;
+                geometry = rest;
+            }
+        }
+        Some(r#type) if r#type == GeomType::Polygon as i32 => {
+            if geometry.is_empty() {
+                return Err("empty geometry");
+            }
+            while !geometry.is_empty() {
+                let (path, rest) = split_path(geometry, true)?;
+                check_path(&mut cursor, path, true)?;
+                geometry = rest;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Checks that the geometries of all features in `layer` can be processed.
+pub fn validate_layer(layer: &tile::Layer) -> Result<(), Error> {
+    for (idx, feature) in layer.features.iter().enumerate() {
+        check_geometry(feature).map_err(|reason| malformed(layer, idx, reason))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{command, validate_layer, CLOSE_PATH, LINE_TO, MOVE_TO};
+    use crate::error::Error;
+    use geozero::mvt::tile;
+    use geozero::mvt::tile::GeomType;
+
+    fn layer(r#type: GeomType, geometry: Vec<u32>) -> tile::Layer {
+        tile::Layer {
+            name: "test".to_string(),
+            features: vec![tile::Feature {
+                r#type: Some(r#type as i32),
+                geometry,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_layer() {
+        let square = vec![
+            command(MOVE_TO, 1),
+            0,
+            0,
+            command(LINE_TO, 3),
+            20,
+            0,
+            0,
+            20,
+            19,
+            0,
+            command(CLOSE_PATH, 1),
+        ];
+        assert!(validate_layer(&layer(GeomType::Polygon, square.clone())).is_ok());
+        assert!(validate_layer(&layer(GeomType::Point, vec![command(MOVE_TO, 1), 2, 2])).is_ok());
+
+        let unclosed = square[..square.len() - 1].to_vec();
+        assert!(matches!(
+            validate_layer(&layer(GeomType::Polygon, unclosed)),
+            Err(Error::Decode(_))
+        ));
+        assert!(validate_layer(&layer(GeomType::Polygon, vec![])).is_err());
+        assert!(validate_layer(&layer(GeomType::Linestring, square[..4].to_vec())).is_err());
+        assert!(validate_layer(&layer(GeomType::Point, vec![command(MOVE_TO, 2), 2, 2])).is_err());
+
+        // The deltas move the cursor beyond i32::MAX
+        let overflow = vec![
+            command(MOVE_TO, 1),
+            u32::MAX - 1,
+            0,
+            command(LINE_TO, 1),
+            u32::MAX - 1,
+            0,
+        ];
+        assert!(validate_layer(&layer(GeomType::Linestring, overflow)).is_err());
+    }
+}
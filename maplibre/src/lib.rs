@@ -4,7 +4,8 @@
 //!
 //! Maplibre-rs is a map renderer that can run natively on MacOS, Linux, Windows, Android, iOS and the web.
 //! It takes advantage of Lyon to tessellate vector tiles and WebGPU to display them efficiently.
-//! Maplibre-rs also has an headless mode (*work in progress*) that can generate rasters.
+//! Maplibre-rs also has an headless mode that can generate rasters, see [`crate::headless`].
+//! It can also be embedded into applications which own a wgpu device, see [`crate::embedded`].
 //!
 //! The official guide book can be found [here](https://maxammann.org/maplibre-rs/docs/).
 //!
@@ -16,16 +17,28 @@
 //! maplibre = "0.0.2"
 //! ```
 
+use crate::animation::{Clock, SystemClock};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::io::offline_store::OfflineStore;
 use crate::io::scheduler::{ScheduleMethod, Scheduler};
-use crate::io::source_client::HTTPClient;
-use crate::map_state::MapState;
+use crate::io::settings::IoSettings;
+use crate::io::source_client::{
+    HTTPClient, HttpSourceClient, Request, ResourceKind, SourceClient, TransformRequest,
+};
+use crate::io::tile_errors::{TileErrorEvent, TileErrorHandler};
+use crate::map_state::{CameraOptions, MapState};
 use crate::render::render_state::RenderState;
+use crate::render::settings::RenderSettings;
 use crate::style::Style;
-use crate::window::{MapWindow, MapWindowConfig, Runnable, WindowSize};
+use crate::window::{HeadedMapWindow, MapWindow, MapWindowConfig, Runnable, WindowSize};
 use std::marker::PhantomData;
+use std::sync::Arc;
 
+pub mod animation;
 pub mod coords;
+pub mod embedded;
 pub mod error;
+pub mod headless;
 pub mod io;
 pub mod platform;
 pub mod style;
@@ -38,7 +51,7 @@ pub mod benchmarking;
 pub mod map_state;
 pub mod render;
 pub(crate) mod tessellation;
-pub(crate) mod tilejson;
+pub mod tilejson;
 pub(crate) mod util;
 
 /// Map's configuration and execution.
@@ -93,8 +106,13 @@ where
     HC: HTTPClient,
 {
     scheduler: Scheduler<SM>,
-    http_client: HC,
+    source_client: SourceClient<HC>,
     style: Style,
+    initial_camera: CameraOptions,
+    clock: Box<dyn Clock>,
+    render_settings: RenderSettings,
+    io_settings: IoSettings,
+    tile_error_handler: Option<Box<TileErrorHandler>>,
 
     map_window_config: MWC,
 }
@@ -102,15 +120,14 @@ where
 impl<MWC, SM, HC> UninitializedMap<MWC, SM, HC>
 where
     MWC: MapWindowConfig,
+    MWC::MapWindow: HeadedMapWindow,
     SM: ScheduleMethod,
     HC: HTTPClient,
 {
     /// Initializes the whole rendering pipeline for the given configuration.
     /// Returns the initialized map, ready to be run.
     pub async fn initialize(self) -> Map<MWC::MapWindow, SM, HC> {
-        let instance = wgpu::Instance::new(wgpu::Backends::all());
-        //let instance = wgpu::Instance::new(wgpu::Backends::GL);
-        //let instance = wgpu::Instance::new(wgpu::Backends::VULKAN);
+        let instance = wgpu::Instance::new(self.render_settings.backends);
 
         let window = MWC::MapWindow::create(&self.map_window_config);
         let window_size = window.size();
@@ -121,19 +138,36 @@ where
             format: crate::platform::COLOR_TEXTURE_FORMAT,
             width: window_size.width(),
             height: window_size.height(),
-            // present_mode: wgpu::PresentMode::Mailbox,
-            present_mode: wgpu::PresentMode::Fifo, // VSync
+            present_mode: self.render_settings.present_mode,
         };
 
-        let render_state = RenderState::initialize(instance, surface, surface_config).await;
+        let render_state = match RenderState::initialize(
+            instance,
+            surface,
+            surface_config,
+            self.render_settings.clone(),
+        )
+        .await
+        {
+            Ok(render_state) => Some(render_state),
+            Err(e) => {
+                log::error!("Failed to initialize rendering: {}", e);
+                None
+            }
+        };
         Map {
             map_state: MapState::new(
                 self.map_window_config,
                 window_size,
                 render_state,
                 self.scheduler,
-                self.http_client,
+                self.source_client,
                 self.style,
+                self.initial_camera,
+                self.clock,
+                self.render_settings,
+                self.io_settings,
+                self.tile_error_handler,
             ),
             window,
         }
@@ -143,11 +177,21 @@ where
 pub struct MapBuilder<MWC, SM, HC>
 where
     SM: ScheduleMethod,
+    HC: HTTPClient,
 {
     schedule_method: Option<SM>,
     scheduler: Option<Scheduler<SM>>,
     http_client: Option<HC>,
+    source_client: Option<SourceClient<HC>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    offline_store: Option<OfflineStore>,
+    transform_request: Option<Arc<TransformRequest>>,
     style: Option<Style>,
+    initial_camera: Option<CameraOptions>,
+    clock: Option<Box<dyn Clock>>,
+    render_settings: Option<RenderSettings>,
+    io_settings: Option<IoSettings>,
+    tile_error_handler: Option<Box<TileErrorHandler>>,
 
     map_window_config: Option<MWC>,
 }
@@ -163,7 +207,16 @@ where
             schedule_method: None,
             scheduler: None,
             http_client: None,
+            source_client: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            offline_store: None,
+            transform_request: None,
             style: None,
+            initial_camera: None,
+            clock: None,
+            render_settings: None,
+            io_settings: None,
+            tile_error_handler: None,
             map_window_config: None,
         }
     }
@@ -183,6 +236,31 @@ where
         self
     }
 
+    /// Sets the client from which tiles are loaded. Defaults to loading the tiles of the vector
+    /// source of the style via HTTP using the configured HTTP client.
+    pub fn with_source_client(mut self, source_client: SourceClient<HC>) -> Self {
+        self.source_client = Some(source_client);
+        self
+    }
+
+    /// Reads tiles from an offline store before requesting them via HTTP. This only applies to
+    /// the default source client and is ignored if a source client is set.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_offline_store(mut self, offline_store: OfflineStore) -> Self {
+        self.offline_store = Some(offline_store);
+        self
+    }
+
+    /// Sets a callback which transforms each request before it is sent, for example to add
+    /// authorization headers or to sign URLs.
+    pub fn with_transform_request<F>(mut self, transform_request: F) -> Self
+    where
+        F: Fn(&str, ResourceKind) -> Request + Send + Sync + 'static,
+    {
+        self.transform_request = Some(Arc::new(transform_request));
+        self
+    }
+
     pub fn with_existing_scheduler(mut self, scheduler: Scheduler<SM>) -> Self {
         self.scheduler = Some(scheduler);
         self
@@ -193,17 +271,80 @@ where
         self
     }
 
+    /// Sets the camera position which is used when the map is shown for the first time.
+    pub fn with_initial_camera(mut self, initial_camera: CameraOptions) -> Self {
+        self.initial_camera = Some(initial_camera);
+        self
+    }
+
+    /// Sets the clock which is used to advance camera animations. Defaults to
+    /// [`crate::animation::SystemClock`].
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Some(Box::new(clock));
+        self
+    }
+
+    /// Sets how the map is rendered. The settings are validated against the device when the map
+    /// is initialized.
+    pub fn with_render_settings(mut self, render_settings: RenderSettings) -> Self {
+        self.render_settings = Some(render_settings);
+        self
+    }
+
+    /// Sets how tiles are loaded and cached.
+    pub fn with_io_settings(mut self, io_settings: IoSettings) -> Self {
+        self.io_settings = Some(io_settings);
+        self
+    }
+
+    /// Sets a callback which is called when a tile fails to load. Tiles which failed because of a
+    /// transient error are retried with an exponential backoff while they are in view.
+    pub fn with_tile_error_handler<F: FnMut(&TileErrorEvent) + 'static>(
+        mut self,
+        tile_error_handler: F,
+    ) -> Self {
+        self.tile_error_handler = Some(Box::new(tile_error_handler));
+        self
+    }
+
     /// Builds the UninitializedMap with the given configuration.
     pub fn build(self) -> UninitializedMap<MWC, SM, HC> {
         let scheduler = self
             .scheduler
             .unwrap_or_else(|| Scheduler::new(self.schedule_method.unwrap()));
         let style = self.style.unwrap_or_default();
+        let mut http_client = self.http_client.unwrap();
+        let mut source_client = self.source_client;
+        if let Some(transform_request) = self.transform_request {
+            http_client = http_client.with_transform_request(transform_request.clone());
+            source_client = source_client
+                .map(|source_client| source_client.with_transform_request(transform_request));
+        }
+        let source_client = match source_client {
+            Some(source_client) => source_client,
+            None => {
+                let source_client = match style.vector_source() {
+                    Some(source) => SourceClient::from_source(http_client, source),
+                    None => SourceClient::Http(HttpSourceClient::new(http_client)),
+                };
+                #[cfg(not(target_arch = "wasm32"))]
+                let source_client = match self.offline_store {
+                    Some(offline_store) => source_client.with_offline_store(offline_store),
+                    None => source_client,
+                };
+                source_client
+            }
+        };
 
         UninitializedMap {
             scheduler,
-            http_client: self.http_client.unwrap(),
+            source_client,
             style,
+            initial_camera: self.initial_camera.unwrap_or_default(),
+            clock: self.clock.unwrap_or_else(|| Box::new(SystemClock)),
+            render_settings: self.render_settings.unwrap_or_default(),
+            io_settings: self.io_settings.unwrap_or_default(),
+            tile_error_handler: self.tile_error_handler,
             map_window_config: self.map_window_config.unwrap(),
         }
     }
@@ -1,31 +1,172 @@
 //! Stores the state of the map such as `[crate::coords::Zoom]`, `[crate::camera::Camera]`, `[crate::style::Style]`, `[crate::io::tile_cache::TileCache]` and more.
 
-use crate::coords::{ViewRegion, WorldTileCoords, Zoom, TILE_SIZE};
+use crate::animation::{AnimationOptions, CameraAnimation, Clock, ProgressCallback};
+use crate::coords::{
+    LngLat, LngLatBounds, ViewRegion, WorldCoords, WorldTileCoords, Zoom, TILE_SIZE,
+};
 use crate::error::Error;
 use crate::io::geometry_index::GeometryIndex;
+use crate::io::prefetch::{tiles_in_viewport, PanTracker};
 use crate::io::scheduler::Scheduler;
+use crate::io::settings::IoSettings;
 use crate::io::shared_thread_state::SharedThreadState;
-use crate::io::source_client::{HTTPClient, HttpSourceClient, SourceClient};
+use crate::io::source_client::{HTTPClient, SourceClient};
 use crate::io::tile_cache::TileCache;
+use crate::io::tile_errors::{TileErrorHandler, TileErrors};
 use crate::io::tile_request_state::TileRequestState;
-use crate::io::{TessellateMessage, TileRequest, TileTessellateMessage};
+use crate::io::{
+    TessellateMessage, TileFailedMessage, TileRequest, TileRequestID, TileTessellateMessage,
+};
 use crate::render::camera;
 use crate::render::camera::{Camera, Perspective, ViewProjection};
 use crate::render::render_state::RenderState;
+use crate::render::settings::RenderSettings;
 use crate::style::Style;
+use crate::util::math::Aabb2;
 use crate::util::ChangeObserver;
+use crate::window::HeadedMapWindow;
 use crate::{MapWindow, MapWindowConfig, ScheduleMethod, WindowSize};
-use std::collections::HashSet;
+use cgmath::{Deg, InnerSpace, Matrix, Matrix3, Point2, Rad, SquareMatrix, Vector2, Vector3};
+use futures::future::Abortable;
+use instant::Instant;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+/// The height of the camera above the `z=0` plane in world units.
+const CAMERA_HEIGHT: f64 = 150.0;
+
+/// Describes a camera position in geographic terms. Fields which are `None` are left unchanged
+/// when the options are applied.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CameraOptions {
+    /// The geographic position which is in the center of the viewport.
+    pub center: Option<LngLat>,
+    pub zoom: Option<Zoom>,
+    /// The bearing in degrees. A bearing of zero means that north is up.
+    pub bearing: Option<f64>,
+    /// The pitch in degrees. A pitch of zero means that the camera looks straight down.
+    pub pitch: Option<f64>,
+}
+
+impl CameraOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_center<L: Into<LngLat>>(mut self, center: L) -> Self {
+        self.center = Some(center.into());
+        self
+    }
+
+    pub fn with_zoom(mut self, zoom: Zoom) -> Self {
+        self.zoom = Some(zoom);
+        self
+    }
+
+    pub fn with_bearing(mut self, bearing: f64) -> Self {
+        self.bearing = Some(bearing);
+        self
+    }
+
+    pub fn with_pitch(mut self, pitch: f64) -> Self {
+        self.pitch = Some(pitch);
+        self
+    }
+}
+
+pub const DEFAULT_MIN_ZOOM: f64 = 0.0;
+pub const DEFAULT_MAX_ZOOM: f64 = 22.0;
+/// The default minimum pitch in degrees.
+pub const DEFAULT_MIN_PITCH: f64 = 0.0;
+/// The default maximum pitch in degrees.
+pub const DEFAULT_MAX_PITCH: f64 = 60.0;
+
+/// The time constant in seconds with which the center is eased back into the max bounds. After
+/// this time about 63% of the distance is covered.
+const MAX_BOUNDS_EASE_TIME: f64 = 0.1;
+
+/// Distances in pixels from the edges of the viewport. Used to keep parts of the viewport free,
+/// for example because they are covered by UI elements.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EdgeInsets {
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+    pub left: f64,
+}
+
+impl EdgeInsets {
+    pub fn new(top: f64, right: f64, bottom: f64, left: f64) -> Self {
+        Self {
+            top,
+            right,
+            bottom,
+            left,
+        }
+    }
+
+    /// Creates insets which are equal on all sides.
+    pub fn uniform(inset: f64) -> Self {
+        Self::new(inset, inset, inset, inset)
+    }
+}
 
 /// Stores the camera configuration.
 pub struct ViewState {
     zoom: ChangeObserver<Zoom>,
     pub camera: ChangeObserver<Camera>,
     pub perspective: Perspective,
+    animation: Option<CameraAnimation>,
+
+    min_zoom: Zoom,
+    max_zoom: Zoom,
+    min_pitch: f64,
+    max_pitch: f64,
+    max_bounds: Option<LngLatBounds>,
+
+    /// The time of the last call to [`ViewState::update`].
+    last_update: Option<Instant>,
 }
 
 impl ViewState {
+    pub fn new(window_size: WindowSize, initial_camera: &CameraOptions) -> Self {
+        let zoom = initial_camera.zoom.unwrap_or_default();
+        let center = initial_camera.center.unwrap_or_default().into_world(zoom);
+
+        let camera = camera::Camera::new(
+            (center.x, center.y, CAMERA_HEIGHT),
+            cgmath::Deg(-90.0),
+            cgmath::Deg(0.0),
+            window_size.width(),
+            window_size.height(),
+        );
+
+        let perspective = camera::Perspective::new(
+            window_size.width(),
+            window_size.height(),
+            cgmath::Deg(110.0),
+            100.0,
+            2000.0,
+        );
+
+        let mut view_state = Self {
+            zoom: ChangeObserver::new(zoom),
+            camera: ChangeObserver::new(camera),
+            perspective,
+            animation: None,
+            min_zoom: Zoom::new(DEFAULT_MIN_ZOOM),
+            max_zoom: Zoom::new(DEFAULT_MAX_ZOOM),
+            min_pitch: DEFAULT_MIN_PITCH,
+            max_pitch: DEFAULT_MAX_PITCH,
+            max_bounds: None,
+            last_update: None,
+        };
+        view_state.jump_to(initial_camera);
+        view_state
+    }
+
     pub fn view_projection(&self) -> ViewProjection {
         self.camera.calc_view_proj(&self.perspective)
     }
@@ -38,10 +179,614 @@ impl ViewState {
         *self.zoom
     }
 
+    /// Changes the zoom without moving the camera. Because the world is scaled according to the
+    /// zoom, the geographic center of the viewport changes. Use [`ViewState::set_zoom`] to keep
+    /// the center.
+    ///
+    /// The zoom is clamped to the zoom limits.
     pub fn update_zoom(&mut self, new_zoom: Zoom) {
+        let new_zoom = self.clamp_zoom(new_zoom);
         *self.zoom = new_zoom;
         log::info!("zoom: {}", new_zoom);
     }
+
+    /// The world coordinates which are visible in the center of the viewport.
+    pub(crate) fn center_world(&self) -> WorldCoords {
+        let inverted_view_proj = self.view_projection().invert();
+        self.camera
+            .window_to_world_at_ground(
+                &Vector2::new(self.camera.width / 2.0, self.camera.height / 2.0),
+                &inverted_view_proj,
+            )
+            .map(|center| WorldCoords::at_ground(center.x, center.y))
+            .unwrap_or_else(|| WorldCoords::from(self.camera.position))
+    }
+
+    /// The geographic position which is visible in the center of the viewport.
+    pub fn center(&self) -> LngLat {
+        self.center_world().into_lng_lat(self.zoom())
+    }
+
+    /// The bearing in degrees within `[0, 360)`.
+    pub fn bearing(&self) -> f64 {
+        Deg::from(self.camera.bearing()).0
+    }
+
+    /// The pitch in degrees.
+    pub fn pitch(&self) -> f64 {
+        Deg::from(self.camera.pitch).0
+    }
+
+    /// Moves the camera such that `center` is visible in the center of the viewport.
+    ///
+    /// The center is clamped to the max bounds.
+    pub fn set_center<L: Into<LngLat>>(&mut self, center: L) {
+        let target = self.clamp_center(center.into()).into_world(self.zoom());
+        let current = self.center_world();
+
+        self.camera.position.x += target.x - current.x;
+        self.camera.position.y += target.y - current.y;
+    }
+
+    /// Changes the zoom while keeping the center of the viewport.
+    pub fn set_zoom(&mut self, zoom: Zoom) {
+        let center = self.center();
+        self.update_zoom(zoom);
+        self.set_center(center);
+    }
+
+    /// Rotates the map around the center of the viewport. The `bearing` is in degrees.
+    pub fn set_bearing(&mut self, bearing: f64) {
+        let center = self.center();
+        self.camera.set_bearing(Deg(bearing));
+        self.set_center(center);
+    }
+
+    /// Rotates the map by `delta` degrees around the `window_position`. The geographic position
+    /// at `window_position` stays in place.
+    pub fn rotate_around(&mut self, delta: f64, window_position: &Vector2<f64>) {
+        let before = self
+            .camera
+            .window_to_world_at_ground(window_position, &self.view_projection().invert());
+
+        let bearing = self.bearing() + delta;
+        self.camera.set_bearing(Deg(bearing));
+
+        let after = self
+            .camera
+            .window_to_world_at_ground(window_position, &self.view_projection().invert());
+
+        if let (Some(before), Some(after)) = (before, after) {
+            self.camera.position.x += before.x - after.x;
+            self.camera.position.y += before.y - after.y;
+        }
+    }
+
+    /// Rotates the map such that north is up. The rotation is animated if `animation` is set.
+    pub fn reset_north(&mut self, animation: Option<&AnimationOptions>) {
+        let target = CameraOptions::new().with_bearing(0.0);
+        match animation {
+            Some(animation) => self.ease_to(&target, animation, None),
+            None => {
+                self.cancel_animation();
+                self.jump_to(&target);
+            }
+        }
+    }
+
+    /// Tilts the map around the center of the viewport. The `pitch` is in degrees.
+    ///
+    /// The pitch is clamped to the pitch limits.
+    pub fn set_pitch(&mut self, pitch: f64) {
+        let center = self.center();
+        self.camera.pitch = Rad::from(Deg(self.clamp_pitch(pitch)));
+        self.set_center(center);
+    }
+
+    /// Applies all the `options` at once without an animation.
+    pub fn jump_to(&mut self, options: &CameraOptions) {
+        let center = options.center.unwrap_or_else(|| self.center());
+
+        if let Some(zoom) = options.zoom {
+            self.update_zoom(zoom);
+        }
+        if let Some(bearing) = options.bearing {
+            self.camera.set_bearing(Deg(bearing));
+        }
+        if let Some(pitch) = options.pitch {
+            self.camera.pitch = Rad::from(Deg(self.clamp_pitch(pitch)));
+        }
+
+        self.set_center(center);
+    }
+
+    pub fn min_zoom(&self) -> Zoom {
+        self.min_zoom
+    }
+
+    /// Sets the minimum zoom. The maximum zoom is raised if it is lower than `min_zoom`.
+    pub fn set_min_zoom(&mut self, min_zoom: Zoom) {
+        self.min_zoom = min_zoom;
+        if self.max_zoom < min_zoom {
+            self.max_zoom = min_zoom;
+        }
+        self.set_zoom(self.zoom());
+    }
+
+    pub fn max_zoom(&self) -> Zoom {
+        self.max_zoom
+    }
+
+    /// Sets the maximum zoom. The minimum zoom is lowered if it is higher than `max_zoom`.
+    pub fn set_max_zoom(&mut self, max_zoom: Zoom) {
+        self.max_zoom = max_zoom;
+        if self.min_zoom > max_zoom {
+            self.min_zoom = max_zoom;
+        }
+        self.set_zoom(self.zoom());
+    }
+
+    /// The minimum pitch in degrees.
+    pub fn min_pitch(&self) -> f64 {
+        self.min_pitch
+    }
+
+    /// Sets the minimum pitch in degrees. The maximum pitch is raised if it is lower than
+    /// `min_pitch`.
+    pub fn set_min_pitch(&mut self, min_pitch: f64) {
+        self.min_pitch = min_pitch;
+        self.max_pitch = self.max_pitch.max(min_pitch);
+        self.set_pitch(self.pitch());
+    }
+
+    /// The maximum pitch in degrees.
+    pub fn max_pitch(&self) -> f64 {
+        self.max_pitch
+    }
+
+    /// Sets the maximum pitch in degrees. The minimum pitch is lowered if it is higher than
+    /// `max_pitch`.
+    pub fn set_max_pitch(&mut self, max_pitch: f64) {
+        self.max_pitch = max_pitch;
+        self.min_pitch = self.min_pitch.min(max_pitch);
+        self.set_pitch(self.pitch());
+    }
+
+    pub fn max_bounds(&self) -> Option<LngLatBounds> {
+        self.max_bounds
+    }
+
+    /// Restricts the center of the viewport to `max_bounds`. If the center is currently outside
+    /// of the bounds, then it is eased back during the next updates.
+    pub fn set_max_bounds(&mut self, max_bounds: Option<LngLatBounds>) {
+        self.max_bounds = max_bounds;
+    }
+
+    fn clamp_zoom(&self, zoom: Zoom) -> Zoom {
+        Zoom::new(
+            zoom.value()
+                .clamp(self.min_zoom.value(), self.max_zoom.value()),
+        )
+    }
+
+    fn clamp_pitch(&self, pitch: f64) -> f64 {
+        pitch.clamp(self.min_pitch, self.max_pitch)
+    }
+
+    fn clamp_center(&self, center: LngLat) -> LngLat {
+        match &self.max_bounds {
+            Some(bounds) => LngLat::new(
+                center.lng.clamp(bounds.west(), bounds.east()),
+                center.lat.clamp(bounds.south(), bounds.north()),
+            ),
+            None => center,
+        }
+    }
+
+    /// Moves the camera back within the constraints. The camera can be moved outside of them
+    /// because the camera can be changed directly, for example by input handlers.
+    ///
+    /// Zoom and pitch are clamped immediately. The center is eased back into the max bounds
+    /// such that the map does not snap back after it was panned too far.
+    fn apply_constraints(&mut self, dt: Duration) {
+        let zoom = self.clamp_zoom(self.zoom());
+        if zoom.value() != self.zoom().value() {
+            self.set_zoom(zoom);
+        }
+
+        let pitch = self.clamp_pitch(self.pitch());
+        if pitch != self.pitch() {
+            self.set_pitch(pitch);
+        }
+
+        let center = self.center();
+        let clamped = self.clamp_center(center);
+        if clamped != center {
+            let current = center.into_world(self.zoom());
+            let target = clamped.into_world(self.zoom());
+            let (dx, dy) = (target.x - current.x, target.y - current.y);
+
+            // Snap the remaining distance if it is less than a pixel
+            let factor = if self.animation.is_some() || dx.hypot(dy) < 1.0 {
+                1.0
+            } else {
+                1.0 - (-dt.as_secs_f64() / MAX_BOUNDS_EASE_TIME).exp()
+            };
+
+            self.camera.position.x += dx * factor;
+            self.camera.position.y += dy * factor;
+        }
+    }
+
+    /// Advances the running animation and applies the constraints. This is called once per frame.
+    pub fn update(&mut self, now: Instant) {
+        let dt = self
+            .last_update
+            .map(|last_update| now.saturating_duration_since(last_update))
+            .unwrap_or_default();
+        self.last_update = Some(now);
+
+        self.update_animation(now);
+        self.apply_constraints(dt);
+    }
+
+    /// The size of the viewport in pixels.
+    pub(crate) fn viewport_size(&self) -> (f64, f64) {
+        (self.camera.width, self.camera.height)
+    }
+
+    /// Animates the camera to `target` by interpolating each property. A running animation is
+    /// cancelled.
+    pub fn ease_to(
+        &mut self,
+        target: &CameraOptions,
+        options: &AnimationOptions,
+        callback: Option<ProgressCallback>,
+    ) {
+        let animation = CameraAnimation::ease(self, target, options);
+        self.start_animation(animation, callback);
+    }
+
+    /// Animates the camera to `target` along a curve which zooms out while moving and zooms in
+    /// again at the target. A running animation is cancelled.
+    pub fn fly_to(
+        &mut self,
+        target: &CameraOptions,
+        options: &AnimationOptions,
+        callback: Option<ProgressCallback>,
+    ) {
+        let animation = CameraAnimation::fly(self, target, options);
+        self.start_animation(animation, callback);
+    }
+
+    fn start_animation(
+        &mut self,
+        mut animation: CameraAnimation,
+        callback: Option<ProgressCallback>,
+    ) {
+        self.cancel_animation();
+        if let Some(callback) = callback {
+            animation.set_callback(callback);
+        }
+        self.animation = Some(animation);
+    }
+
+    /// Stops the running animation and leaves the camera where it currently is.
+    pub fn cancel_animation(&mut self) {
+        if let Some(animation) = self.animation.take() {
+            animation.cancel();
+        }
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.animation.is_some()
+    }
+
+    pub fn animation(&self) -> Option<&CameraAnimation> {
+        self.animation.as_ref()
+    }
+
+    /// Advances the running animation to the time `now`.
+    pub fn update_animation(&mut self, now: Instant) {
+        if let Some(mut animation) = self.animation.take() {
+            if !animation.update(self, now) {
+                self.animation = Some(animation);
+            }
+        }
+    }
+
+    /// Creates a copy of the camera and perspective which can be moved without changing `self`.
+    fn snapshot(&self) -> ViewState {
+        ViewState {
+            zoom: ChangeObserver::new(self.zoom()),
+            camera: ChangeObserver::new((*self.camera).clone()),
+            perspective: self.perspective.clone(),
+            animation: None,
+            min_zoom: self.min_zoom,
+            max_zoom: self.max_zoom,
+            min_pitch: self.min_pitch,
+            max_pitch: self.max_pitch,
+            // The center is moved freely while fitting
+            max_bounds: None,
+            last_update: None,
+        }
+    }
+
+    /// Calculates the camera which shows `bounds` at the highest possible zoom. The `bounds` are
+    /// fitted into the viewport minus the `padding`. If `bearing` or `pitch` are `None`, then the
+    /// current values are used.
+    pub fn camera_for_bounds(
+        &self,
+        bounds: &LngLatBounds,
+        padding: &EdgeInsets,
+        bearing: Option<f64>,
+        pitch: Option<f64>,
+    ) -> CameraOptions {
+        let (width, height) = self.viewport_size();
+        let padded = Aabb2::new(
+            Point2::new(padding.left, padding.top),
+            Point2::new(
+                (width - padding.right).max(padding.left),
+                (height - padding.bottom).max(padding.top),
+            ),
+        );
+
+        let bearing = bearing.unwrap_or_else(|| self.bearing());
+        let pitch = pitch.unwrap_or_else(|| self.pitch());
+        let camera_at = |center: LngLat, zoom: f64| CameraOptions {
+            center: Some(center),
+            zoom: Some(Zoom::new(zoom)),
+            bearing: Some(bearing),
+            pitch: Some(pitch),
+        };
+        let (min_zoom, max_zoom) = (self.min_zoom.value(), self.max_zoom.value());
+
+        // The camera keeps its height when zooming, instead the world is scaled. Therefore, the
+        // ground which is visible within the padded area, relative to the center of the camera,
+        // is the same for all zooms and centers.
+        let mut view_state = self.snapshot();
+        view_state.jump_to(&camera_at(bounds.center(), min_zoom));
+        let inverted_view_proj = view_state.view_projection().invert();
+        let camera_center = view_state.center_world();
+        let region = match view_state
+            .camera
+            .view_region_within(&padded, &inverted_view_proj)
+        {
+            Some(region) => region
+                .iter()
+                .map(|point| Vector2::new(point.x - camera_center.x, point.y - camera_center.y))
+                .collect::<Vec<_>>(),
+            None => return camera_at(bounds.center(), min_zoom),
+        };
+        let half_planes = half_planes(&region);
+
+        // The corners of the bounds relative to their center in mercator coordinates, which are
+        // scaled by the world size of a zoom
+        let center = bounds.center().into_mercator();
+        let corners = [
+            bounds.north_west(),
+            bounds.ne,
+            bounds.south_east(),
+            bounds.sw,
+        ]
+        .map(|corner| {
+            let corner = corner.into_mercator();
+            Vector2::new(corner.x - center.x, corner.y - center.y)
+        });
+
+        let zoom = match max_scale(&half_planes, &corners) {
+            Some(scale) => (scale / TILE_SIZE).log2(),
+            None => min_zoom,
+        }
+        .clamp(min_zoom, max_zoom);
+        let scale = Zoom::new(zoom).world_size();
+
+        // The position of the center of the bounds relative to the center of the camera. If the
+        // bounds do not fit at the minimum zoom, then they are centered within the padded area.
+        let offset = center_offset(&half_planes, &corners, scale)
+            .unwrap_or_else(|| region.iter().sum::<Vector2<f64>>() / region.len() as f64);
+
+        let camera_center =
+            WorldCoords::at_ground(center.x * scale - offset.x, center.y * scale - offset.y);
+        camera_at(camera_center.into_lng_lat(Zoom::new(zoom)), zoom)
+    }
+
+    /// Moves the camera such that `bounds` are visible. See [`ViewState::camera_for_bounds`].
+    /// The camera is animated if `animation` is set.
+    pub fn fit_bounds(
+        &mut self,
+        bounds: &LngLatBounds,
+        padding: &EdgeInsets,
+        bearing: Option<f64>,
+        pitch: Option<f64>,
+        animation: Option<&AnimationOptions>,
+    ) {
+        let camera = self.camera_for_bounds(bounds, padding, bearing, pitch);
+        match animation {
+            Some(animation) => self.ease_to(&camera, animation, None),
+            None => {
+                self.cancel_animation();
+                self.jump_to(&camera);
+            }
+        }
+    }
+
+    /// Returns the current camera position as [`CameraOptions`].
+    pub fn camera_options(&self) -> CameraOptions {
+        CameraOptions {
+            center: Some(self.center()),
+            zoom: Some(self.zoom()),
+            bearing: Some(self.bearing()),
+            pitch: Some(self.pitch()),
+        }
+    }
+}
+
+/// A half-plane which contains the points `p` with `normal · p <= distance`.
+struct HalfPlane {
+    normal: Vector2<f64>,
+    distance: f64,
+}
+
+impl HalfPlane {
+    /// The constraint for the center of a shape which is scaled by `scale`, such that all of its
+    /// `corners` are within the half-plane.
+    fn for_center(&self, corners: &[Vector2<f64>], scale: f64) -> (Vector2<f64>, f64) {
+        (self.normal, self.distance - scale * self.reach(corners))
+    }
+
+    /// The maximum distance of the `corners` along the normal.
+    fn reach(&self, corners: &[Vector2<f64>]) -> f64 {
+        corners
+            .iter()
+            .map(|corner| self.normal.dot(*corner))
+            .fold(f64::NEG_INFINITY, f64::max)
+    }
+}
+
+/// Returns the half-planes whose intersection is the convex `polygon`.
+fn half_planes(polygon: &[Vector2<f64>]) -> Vec<HalfPlane> {
+    let centroid = polygon.iter().sum::<Vector2<f64>>() / polygon.len() as f64;
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .filter_map(|(a, b)| {
+            let edge = b - a;
+            if edge.magnitude() < 1e-9 {
+                return None;
+            }
+            let mut normal = Vector2::new(edge.y, -edge.x).normalize();
+            if normal.dot(centroid - a) > 0.0 {
+                normal = -normal;
+            }
+            Some(HalfPlane {
+                normal,
+                distance: normal.dot(*a),
+            })
+        })
+        .collect()
+}
+
+/// Tolerance for points on the border of half-planes
+const HALF_PLANE_TOLERANCE: f64 = 1e-6;
+
+/// Returns the largest scale at which a shape with the given `corners`, which are relative to its
+/// center, fits into the intersection of the `half_planes`. The center of the shape can be moved
+/// freely.
+///
+/// This is a linear program in the center and the scale. Its optimum is a vertex which is the
+/// intersection of three constraints, therefore all combinations are tried.
+fn max_scale(half_planes: &[HalfPlane], corners: &[Vector2<f64>]) -> Option<f64> {
+    let constraints = half_planes
+        .iter()
+        .map(|half_plane| {
+            (
+                Vector3::new(
+                    half_plane.normal.x,
+                    half_plane.normal.y,
+                    half_plane.reach(corners),
+                ),
+                half_plane.distance,
+            )
+        })
+        .collect::<Vec<_>>();
+    let is_feasible = |solution: &Vector3<f64>| {
+        constraints
+            .iter()
+            .all(|(row, distance)| row.dot(*solution) <= distance + HALF_PLANE_TOLERANCE)
+    };
+
+    let mut best = 0.0;
+    for i in 0..constraints.len() {
+        for j in i + 1..constraints.len() {
+            for k in j + 1..constraints.len() {
+                let rows = Matrix3::from_cols(constraints[i].0, constraints[j].0, constraints[k].0)
+                    .transpose();
+                let solution = match rows.invert() {
+                    Some(inverted) => {
+                        inverted
+                            * Vector3::new(constraints[i].1, constraints[j].1, constraints[k].1)
+                    }
+                    None => continue,
+                };
+                if solution.z > best && is_feasible(&solution) {
+                    best = solution.z;
+                }
+            }
+        }
+    }
+
+    if best > 0.0 {
+        Some(best)
+    } else {
+        None
+    }
+}
+
+/// Returns the position of the center of a shape with the given `corners`, scaled by `scale`,
+/// which centers the shape within the intersection of the `half_planes`. Returns `None` if the
+/// shape does not fit.
+fn center_offset(
+    half_planes: &[HalfPlane],
+    corners: &[Vector2<f64>],
+    scale: f64,
+) -> Option<Vector2<f64>> {
+    let constraints = half_planes
+        .iter()
+        .map(|half_plane| half_plane.for_center(corners, scale))
+        .collect::<Vec<_>>();
+
+    // The feasible centers form a convex polygon. Its vertices are intersections of two
+    // constraints, and their mean is within the polygon.
+    let mut vertices = Vec::new();
+    for i in 0..constraints.len() {
+        for j in i + 1..constraints.len() {
+            let ((a, a_distance), (b, b_distance)) = (constraints[i], constraints[j]);
+            let determinant = a.x * b.y - a.y * b.x;
+            if determinant.abs() < 1e-12 {
+                continue;
+            }
+            let vertex = Vector2::new(
+                (a_distance * b.y - b_distance * a.y) / determinant,
+                (a.x * b_distance - b.x * a_distance) / determinant,
+            );
+            if constraints.iter().all(|(normal, distance)| {
+                normal.dot(vertex) <= distance + HALF_PLANE_TOLERANCE * scale.max(1.0)
+            }) {
+                vertices.push(vertex);
+            }
+        }
+    }
+
+    if vertices.is_empty() {
+        None
+    } else {
+        Some(vertices.iter().sum::<Vector2<f64>>() / vertices.len() as f64)
+    }
+}
+
+/// Statistics about the loading of tiles during the last frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameStatistics {
+    /// The number of messages which have been received from the workers
+    pub messages_received: usize,
+    /// The number of tessellated layers which have been added to the tile cache
+    pub layers_received: usize,
+    /// The estimated size of the received layers in bytes
+    pub bytes_received: usize,
+    /// The number of tile requests which have been finished
+    pub tiles_finished: usize,
+    /// The number of finished tile requests which are deferred to the next frame because the
+    /// request state was locked by a worker
+    pub tiles_deferred: usize,
+    /// The time which was spent receiving messages
+    pub drain_duration: Duration,
+    /// Whether receiving stopped because the time or byte budget was exhausted
+    pub budget_exhausted: bool,
+    /// The number of tiles in the tile cache
+    pub tiles_cached: usize,
+    /// The estimated memory of the tile cache in bytes
+    pub tile_cache_size: usize,
 }
 
 /// Stores the state of the map, dispatches tile fetching and caching, tessellation and drawing.
@@ -59,16 +804,30 @@ where
     view_state: ViewState,
 
     render_state: Option<RenderState>,
+    /// Used when the render state is initialized again.
+    render_settings: RenderSettings,
     scheduler: Scheduler<SM>,
     message_receiver: mpsc::Receiver<TessellateMessage>,
     shared_thread_state: SharedThreadState,
     tile_cache: TileCache,
+    io_settings: IoSettings,
+    /// Tile requests which have been tessellated but not yet marked as finished
+    finished_tile_requests: Vec<(TileRequestID, WorldTileCoords)>,
+    /// Tiles which failed to load and are retried after a backoff.
+    tile_errors: TileErrors,
+    tile_error_handler: Option<Box<TileErrorHandler>>,
+    frame_statistics: FrameStatistics,
 
     source_client: SourceClient<HC>,
 
     style: Style,
 
+    clock: Box<dyn Clock>,
+    pan_tracker: PanTracker,
+
     try_failed: bool,
+    /// Set if tiles could not be prefetched because of the bandwidth budget.
+    prefetch_pending: bool,
 }
 
 impl<MWC, SM, HC> MapState<MWC, SM, HC>
@@ -77,64 +836,58 @@ where
     SM: ScheduleMethod,
     HC: HTTPClient,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         map_window_config: MWC,
         window_size: WindowSize,
         render_state: Option<RenderState>,
         scheduler: Scheduler<SM>,
-        http_client: HC,
+        source_client: SourceClient<HC>,
         style: Style,
+        initial_camera: CameraOptions,
+        clock: Box<dyn Clock>,
+        render_settings: RenderSettings,
+        io_settings: IoSettings,
+        tile_error_handler: Option<Box<TileErrorHandler>>,
     ) -> Self {
-        let camera = camera::Camera::new(
-            (TILE_SIZE / 2.0, TILE_SIZE / 2.0, 150.0),
-            cgmath::Deg(-90.0),
-            cgmath::Deg(0.0),
-            window_size.width(),
-            window_size.height(),
-        );
-
-        let perspective = camera::Perspective::new(
-            window_size.width(),
-            window_size.height(),
-            cgmath::Deg(110.0),
-            100.0,
-            2000.0,
-        );
-
         let (message_sender, message_receiver) = mpsc::channel();
+        let max_tile_size = io_settings.max_tile_size;
 
         Self {
             map_window_config,
-            view_state: ViewState {
-                zoom: ChangeObserver::default(),
-                camera: ChangeObserver::new(camera),
-                perspective,
-            },
+            view_state: ViewState::new(window_size, &initial_camera),
 
             render_state,
             scheduler,
 
-            tile_cache: TileCache::new(),
+            tile_cache: TileCache::new(io_settings.tile_cache_budget),
+            io_settings,
+            finished_tile_requests: Vec::new(),
+            tile_errors: TileErrors::default(),
+            tile_error_handler,
+            frame_statistics: FrameStatistics::default(),
             message_receiver,
             shared_thread_state: SharedThreadState {
                 tile_request_state: Arc::new(Mutex::new(TileRequestState::new())),
                 message_sender,
                 geometry_index: Arc::new(Mutex::new(GeometryIndex::new())),
+                max_tile_size,
             },
 
             style,
 
+            clock,
+            pan_tracker: PanTracker::default(),
+
             try_failed: false,
-            source_client: SourceClient::Http(HttpSourceClient::new(http_client)),
+            prefetch_pending: false,
+            source_client,
+            render_settings,
         }
     }
 
     pub fn update_and_redraw(&mut self) -> Result<(), Error> {
-        // Get data from other threads
-        self.try_populate_cache();
-
-        // Update buffers
-        self.prepare_render();
+        self.update();
 
         // Render buffers
         self.render_state_mut().render()?;
@@ -145,9 +898,56 @@ where
         Ok(())
     }
 
+    /// Prepares the next frame without rendering it. This is used if the frame is rendered into a
+    /// target of the application which embeds the map.
+    pub fn update(&mut self) {
+        // Advance camera animations and apply camera constraints
+        let now = self.clock.now();
+        self.view_state.update(now);
+        self.pan_tracker
+            .update(self.view_state.center().into_mercator(), now);
+
+        // Get data from other threads
+        self.try_populate_cache();
+
+        // Update buffers
+        self.prepare_render();
+    }
+
+    /// Disables prefetching if the connection is metered and
+    /// [`crate::io::settings::PrefetchSettings::disable_on_metered_connection`] is set.
+    pub fn set_metered_connection(&mut self, metered_connection: bool) {
+        self.io_settings.metered_connection = metered_connection;
+    }
+
+    /// Returns statistics about the loading of tiles during the last frame.
+    pub fn frame_statistics(&self) -> &FrameStatistics {
+        &self.frame_statistics
+    }
+
+    /// Receives the results of the workers until the time or byte budget of the frame is
+    /// exhausted.
     #[tracing::instrument(skip_all)]
     fn try_populate_cache(&mut self) {
-        if let Ok(result) = self.message_receiver.try_recv() {
+        let start = self.clock.now();
+        let mut statistics = FrameStatistics::default();
+
+        loop {
+            // At least one message is received per frame, such that loading always progresses
+            if statistics.messages_received > 0
+                && (self.clock.now().duration_since(start) >= self.io_settings.drain_time_budget
+                    || statistics.bytes_received >= self.io_settings.drain_byte_budget)
+            {
+                statistics.budget_exhausted = true;
+                break;
+            }
+
+            let result = match self.message_receiver.try_recv() {
+                Ok(result) => result,
+                Err(_) => break,
+            };
+            statistics.messages_received += 1;
+
             match result {
                 TessellateMessage::Layer(layer_result) => {
                     tracing::trace!(
@@ -155,39 +955,272 @@ where
                         layer_result.layer_name(),
                         layer_result.get_coords()
                     );
+                    statistics.layers_received += 1;
+                    statistics.bytes_received += layer_result.size_bytes();
                     self.tile_cache.put_tessellated_layer(layer_result);
                 }
-                TessellateMessage::Tile(TileTessellateMessage { request_id, coords }) => loop {
-                    if let Ok(mut tile_request_state) =
-                        self.shared_thread_state.tile_request_state.try_lock()
-                    {
-                        tile_request_state.finish_tile_request(request_id);
-                        tracing::trace!("Tile at {} finished loading", coords);
-                        break;
+                TessellateMessage::Tile(TileTessellateMessage { request_id, coords }) => {
+                    self.tile_errors.record_success(&coords);
+                    self.finished_tile_requests.push((request_id, coords));
+                }
+                TessellateMessage::TileFailed(TileFailedMessage {
+                    request_id,
+                    coords,
+                    error,
+                }) => {
+                    let event = self.tile_errors.record_failure(
+                        &coords,
+                        error,
+                        self.clock.now(),
+                        &self.io_settings.retry,
+                    );
+                    if let Some(tile_error_handler) = self.tile_error_handler.as_mut() {
+                        tile_error_handler(&event);
+                    }
+                    self.finished_tile_requests.push((request_id, coords));
+                }
+            }
+        }
+
+        self.remove_evicted_tiles();
+
+        // Waiting for the workers to release the lock would block the frame. Instead the
+        // requests are finished in one of the next frames.
+        if !self.finished_tile_requests.is_empty() {
+            if let Ok(mut tile_request_state) =
+                self.shared_thread_state.tile_request_state.try_lock()
+            {
+                for (request_id, coords) in self.finished_tile_requests.drain(..) {
+                    tile_request_state.finish_tile_request(request_id);
+                    tracing::trace!("Tile at {} finished loading", coords);
+                    statistics.tiles_finished += 1;
+                }
+            }
+        }
+
+        statistics.tiles_deferred = self.finished_tile_requests.len();
+        statistics.drain_duration = self.clock.now().duration_since(start);
+        statistics.tiles_cached = self.tile_cache.len();
+        statistics.tile_cache_size = self.tile_cache.size();
+        self.frame_statistics = statistics;
+    }
+
+    /// Removes the tiles which have been evicted from the tile cache from the GPU buffers and the
+    /// geometry index.
+    fn remove_evicted_tiles(&mut self) {
+        let evicted = self.tile_cache.take_evicted();
+        if evicted.is_empty() {
+            return;
+        }
+
+        if let Some(render_state) = self.render_state.as_mut() {
+            for coords in &evicted {
+                render_state.evict_tile(coords);
+            }
+        }
+
+        if let Ok(mut geometry_index) = self.shared_thread_state.geometry_index.lock() {
+            for coords in &evicted {
+                geometry_index.remove_tile(coords);
+            }
+        }
+    }
+
+    /// Returns the tiles which provide the data for the view region. The parents of the tiles in
+    /// view come first, so that something is drawn while the tiles in view are loading. Within
+    /// both groups, tiles close to the center of the viewport come first.
+    fn tiles_to_load(&self, view_region: &ViewRegion) -> Vec<WorldTileCoords> {
+        let max_zooms = self.style.layer_max_zooms();
+        let in_view = view_region
+            .iter_sources(&max_zooms)
+            .filter(|coords| coords.build_quad_key().is_some())
+            .collect::<Vec<_>>();
+
+        let parent_levels = self.io_settings.fallback_parent_levels;
+        let mut parents = Vec::new();
+        if parent_levels > 0 {
+            for coords in &in_view {
+                if let Some(parent) = coords.ancestor_at(coords.z.saturating_sub(parent_levels)) {
+                    if parent != *coords && !parents.contains(&parent) {
+                        parents.push(parent);
                     }
-                },
+                }
+            }
+        }
+
+        let center = self.view_state.center_world();
+        let zoom = self.view_state.zoom();
+        let visible_level = self.view_state.visible_level();
+        let by_priority = |a: &WorldTileCoords, b: &WorldTileCoords| {
+            tile_request_priority(a, &center, zoom, visible_level)
+                .partial_cmp(&tile_request_priority(b, &center, zoom, visible_level))
+                .unwrap_or(Ordering::Equal)
+        };
+        parents.sort_by(by_priority);
+
+        let mut tiles = in_view;
+        tiles.sort_by(by_priority);
+        parents.extend(tiles);
+        parents
+    }
+
+    /// Returns the tiles which are likely to become visible soon. These are the tiles along the
+    /// path of a camera animation or pan, followed by a ring around the tiles in view. Tiles which
+    /// are already in `tiles` are skipped.
+    fn tiles_to_prefetch(
+        &self,
+        view_region: &ViewRegion,
+        tiles: &[WorldTileCoords],
+    ) -> Vec<WorldTileCoords> {
+        if !self.io_settings.is_prefetching() {
+            return Vec::new();
+        }
+
+        let settings = &self.io_settings.prefetch;
+        let max_zooms = self.style.layer_max_zooms();
+        let viewport_size = self.view_state.viewport_size();
+        let zoom = self.view_state.zoom();
+
+        let mut path = Vec::new();
+        if let Some(animation) = self.view_state.animation() {
+            for camera in animation.remaining_path(self.clock.now(), settings.path_samples) {
+                let center = camera
+                    .center
+                    .unwrap_or_else(|| self.view_state.center())
+                    .into_mercator();
+                path.extend(tiles_in_viewport(
+                    center,
+                    camera.zoom.unwrap_or(zoom),
+                    viewport_size,
+                ));
             }
         }
+        for center in self
+            .pan_tracker
+            .predict(settings.pan_lookahead, settings.path_samples)
+        {
+            path.extend(tiles_in_viewport(center, zoom, viewport_size));
+        }
+
+        let center = self.view_state.center_world();
+        let visible_level = self.view_state.visible_level();
+        let mut ring = view_region
+            .iter_ring(settings.ring_size)
+            .collect::<Vec<_>>();
+        ring.sort_by(|a, b| {
+            tile_request_priority(a, &center, zoom, visible_level)
+                .partial_cmp(&tile_request_priority(b, &center, zoom, visible_level))
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let mut returned = tiles.iter().copied().collect::<HashSet<_>>();
+        path.into_iter()
+            .chain(ring)
+            .filter(|coords| coords.build_quad_key().is_some())
+            .flat_map(|coords| {
+                max_zooms
+                    .iter()
+                    .map(move |max_zoom| coords.overzoom_source(*max_zoom))
+            })
+            .filter(|coords| returned.insert(*coords))
+            .collect()
     }
 
-    /// Request tiles which are currently in view.
+    /// Request tiles which are currently in view and their parents.
     #[tracing::instrument(skip_all)]
-    fn request_tiles_in_view(&mut self, view_region: &ViewRegion) -> bool {
-        let mut try_failed = false;
-        let source_layers: HashSet<String> = self
-            .style
-            .layers
+    fn request_tiles_in_view(
+        &mut self,
+        tiles: &[WorldTileCoords],
+        prefetch: &[WorldTileCoords],
+    ) -> bool {
+        // Layers are only loaded from tiles up to the max zoom of their source
+        let mut source_layers: HashMap<u8, HashSet<String>> = HashMap::new();
+        for coords in tiles.iter().chain(prefetch) {
+            source_layers
+                .entry(coords.z)
+                .or_insert_with(|| self.style.source_layers_at(coords.z));
+        }
+
+        let mut tile_request_state = match self.shared_thread_state.tile_request_state.try_lock() {
+            Ok(tile_request_state) => tile_request_state,
+            Err(_) => return true,
+        };
+
+        // Prefetched tiles are only cancelled if they are no longer predicted to become visible
+        let in_view = tiles
+            .iter()
+            .chain(prefetch)
+            .copied()
+            .collect::<HashSet<_>>();
+        let cancelled =
+            tile_request_state.retain_tile_requests(|request| in_view.contains(&request.coords));
+        if cancelled > 0 {
+            tracing::debug!(
+                "cancelled {} tile requests which are out of view",
+                cancelled
+            );
+        }
+
+        self.prefetch_pending = false;
+
+        // Failed tiles which leave the view are requested immediately when they come back
+        self.tile_errors.retain(|coords| in_view.contains(coords));
+        let now = self.clock.now();
+
+        for coords in tiles {
+            // TODO: Make tesselation depend on style?
+            if !self
+                .tile_cache
+                .is_layers_missing(coords, &source_layers[&coords.z])
+                || tile_request_state.is_tile_request_pending(coords)
+                || !self.tile_errors.can_request(coords, now)
+            {
+                continue;
+            }
+
+            // The remaining tiles are requested in a later frame
+            if tile_request_state.pending_tile_requests_count()
+                >= self.io_settings.max_concurrent_requests
+            {
+                self.prefetch_pending = !prefetch.is_empty();
+                return true;
+            }
+
+            self.request_tile(&mut tile_request_state, coords, &source_layers[&coords.z]);
+        }
+
+        // Prefetching must not evict tiles which are in view
+        if self.tile_cache.size() >= self.io_settings.prefetch.memory_budget {
+            return false;
+        }
+
+        let mut pending_prefetch_requests = prefetch
             .iter()
-            .filter_map(|layer| layer.source_layer.clone())
-            .collect();
+            .filter(|coords| tile_request_state.is_tile_request_pending(coords))
+            .count();
+        for coords in prefetch {
+            if !self
+                .tile_cache
+                .is_layers_missing(coords, &source_layers[&coords.z])
+                || tile_request_state.is_tile_request_pending(coords)
+                || !self.tile_errors.can_request(coords, now)
+            {
+                continue;
+            }
 
-        for coords in view_region.iter() {
-            if coords.build_quad_key().is_some() {
-                // TODO: Make tesselation depend on style?
-                try_failed = self.try_request_tile(&coords, &source_layers).unwrap();
+            if pending_prefetch_requests >= self.io_settings.prefetch.max_concurrent_requests
+                || tile_request_state.pending_tile_requests_count()
+                    >= self.io_settings.max_concurrent_requests
+            {
+                self.prefetch_pending = true;
+                break;
             }
+
+            self.request_tile(&mut tile_request_state, coords, &source_layers[&coords.z]);
+            pending_prefetch_requests += 1;
         }
-        try_failed
+
+        false
     }
 
     #[tracing::instrument(skip_all)]
@@ -199,25 +1232,50 @@ where
 
         let view_proj = self.view_state.view_projection();
 
-        let view_region = self
-            .view_state
-            .camera
-            .view_region_bounding_box(&view_proj.invert())
-            .map(|bounding_box| {
-                ViewRegion::new(bounding_box, 0, *self.view_state.zoom, visible_level)
+        let inverted_view_proj = view_proj.invert();
+        let camera = &self.view_state.camera;
+        let zoom = self.view_state.zoom();
+
+        // The polygon covers rotated views tightly. If the horizon is visible, then fall back to
+        // the bounding box.
+        let view_region = camera
+            .view_region_polygon(&inverted_view_proj)
+            .and_then(|polygon| ViewRegion::from_polygon(polygon, 0, zoom, visible_level))
+            .or_else(|| {
+                camera
+                    .view_region_bounding_box(&inverted_view_proj)
+                    .map(|bounding_box| ViewRegion::new(bounding_box, 0, zoom, visible_level))
             });
 
         drop(_guard);
 
+        let tiles = view_region
+            .as_ref()
+            .map(|view_region| self.tiles_to_load(view_region))
+            .unwrap_or_default();
+
         if let Some(view_region) = &view_region {
             self.render_state
                 .as_mut()
                 .expect("render state not yet initialized. Call reinitialize().")
-                .upload_tile_geometry(view_region, &self.style, &self.tile_cache);
+                .upload_tile_geometry(&tiles, &self.style, &self.tile_cache);
 
             let zoom = self.view_state.zoom();
-            self.render_state_mut()
-                .update_tile_view_pattern(view_region, &view_proj, zoom);
+            let now = self.clock.now();
+            self.render_state
+                .as_mut()
+                .expect("render state not yet initialized. Call reinitialize().")
+                .update_tile_view_pattern(view_region, &view_proj, zoom, &self.style, now);
+
+            // Visible tiles, their fallbacks and the parents which are loaded as fallbacks must
+            // stay in the cache
+            let render_state = self
+                .render_state
+                .as_ref()
+                .expect("render state not yet initialized. Call reinitialize().");
+            self.tile_cache
+                .pin_tiles(render_state.visible_tiles().chain(tiles.iter()));
+            self.remove_evicted_tiles();
 
             self.render_state_mut().update_metadata();
         }
@@ -228,10 +1286,12 @@ where
         if self.view_state.camera.did_change(0.05)
             || self.view_state.zoom.did_change(0.05)
             || self.try_failed
+            || self.prefetch_pending
+            || self.tile_errors.has_due_retries(self.clock.now())
         {
             if let Some(view_region) = &view_region {
-                // FIXME: We also need to request tiles from layers above if we are over the maximum zoom level
-                self.try_failed = self.request_tiles_in_view(view_region);
+                let prefetch = self.tiles_to_prefetch(view_region, &tiles);
+                self.try_failed = self.request_tiles_in_view(&tiles, &prefetch);
             }
 
             self.render_state()
@@ -242,57 +1302,60 @@ where
         self.view_state.zoom.update_reference();
     }
 
-    fn try_request_tile(
-        &mut self,
+    fn request_tile(
+        &self,
+        tile_request_state: &mut TileRequestState,
         coords: &WorldTileCoords,
         layers: &HashSet<String>,
-    ) -> Result<bool, Error> {
-        if !self.tile_cache.is_layers_missing(coords, layers) {
-            return Ok(false);
-        }
-
-        if let Ok(mut tile_request_state) = self.shared_thread_state.tile_request_state.try_lock() {
-            if let Some(request_id) = tile_request_state.start_tile_request(TileRequest {
+    ) {
+        if let Some((request_id, abort_registration)) =
+            tile_request_state.start_tile_request(TileRequest {
                 coords: *coords,
                 layers: layers.clone(),
-            }) {
-                tracing::info!("new tile request: {}", &coords);
-
-                // The following snippet can be added instead of the next code block to demonstrate
-                // an understanable approach of fetching
-                /*#[cfg(target_arch = "wasm32")]
-                if let Some(tile_coords) = coords.into_tile(TileAddressingScheme::TMS) {
-                    crate::platform::legacy_webworker_fetcher::request_tile(
-                        request_id,
-                        tile_coords,
-                    );
-                }*/
+            })
+        {
+            tracing::info!("new tile request: {}", &coords);
 
-                let client = self.source_client.clone();
-                let coords = *coords;
+            // The following snippet can be added instead of the next code block to demonstrate
+            // an understanable approach of fetching
+            /*#[cfg(target_arch = "wasm32")]
+            if let Some(tile_coords) = coords.into_tile(TileAddressingScheme::TMS) {
+                crate::platform::legacy_webworker_fetcher::request_tile(
+                    request_id,
+                    tile_coords,
+                );
+            }*/
 
-                self.scheduler
-                    .schedule_method()
-                    .schedule(
-                        self.shared_thread_state.clone(),
-                        move |state: SharedThreadState| async move {
+            let client = self.source_client.clone();
+            let coords = *coords;
+
+            self.scheduler
+                .schedule_method()
+                .schedule(
+                    self.shared_thread_state.clone(),
+                    move |state: SharedThreadState| async move {
+                        let request = async {
                             match client.fetch(&coords).await {
                                 Ok(data) => state
                                     .process_tile(request_id, data.into_boxed_slice())
                                     .unwrap(),
+                                // Transient errors are retried, other tiles stay empty
+                                Err(e) if e.is_transient() => {
+                                    state.tile_failed(&coords, request_id, e).unwrap()
+                                }
                                 Err(e) => {
-                                    log::error!("{:?}", &e);
+                                    log::info!("tile {} is unavailable: {:?}", &coords, &e);
                                     state.tile_unavailable(&coords, request_id).unwrap()
                                 }
                             }
-                        },
-                    )
-                    .unwrap();
-            }
+                        };
 
-            Ok(false)
-        } else {
-            Ok(true)
+                        if Abortable::new(request, abort_registration).await.is_err() {
+                            tracing::trace!("tile request {} was cancelled", &coords);
+                        }
+                    },
+                )
+                .unwrap();
         }
     }
 
@@ -333,7 +1396,28 @@ where
         &mut self.view_state
     }
 
-    pub fn recreate_surface(&mut self, window: &MWC::MapWindow) {
+    /// Returns true if all requested tiles have been loaded and are ready to be rendered.
+    pub fn is_idle(&self) -> bool {
+        !self.try_failed
+            && !self.view_state.is_animating()
+            && !self
+                .render_state
+                .as_ref()
+                .map(|render_state| render_state.is_fading())
+                .unwrap_or(false)
+            && self.finished_tile_requests.is_empty()
+            && self
+                .shared_thread_state
+                .tile_request_state
+                .try_lock()
+                .map(|tile_request_state| !tile_request_state.has_pending_tile_requests())
+                .unwrap_or(false)
+    }
+
+    pub fn recreate_surface(&mut self, window: &MWC::MapWindow)
+    where
+        MWC::MapWindow: HeadedMapWindow,
+    {
         self.render_state
             .as_mut()
             .expect("render state not yet initialized. Call reinitialize().")
@@ -344,11 +1428,12 @@ where
         self.render_state.is_some()
     }
 
-    pub async fn reinitialize(&mut self) {
+    pub async fn reinitialize(&mut self)
+    where
+        MWC::MapWindow: HeadedMapWindow,
+    {
         if self.render_state.is_none() {
-            let instance = wgpu::Instance::new(wgpu::Backends::all());
-            //let instance = wgpu::Instance::new(wgpu::Backends::GL);
-            //let instance = wgpu::Instance::new(wgpu::Backends::VULKAN);
+            let instance = wgpu::Instance::new(self.render_settings.backends);
 
             let window = MWC::MapWindow::create(&self.map_window_config);
             let window_size = window.size();
@@ -359,14 +1444,450 @@ where
                 format: crate::platform::COLOR_TEXTURE_FORMAT,
                 width: window_size.width(),
                 height: window_size.height(),
-                // present_mode: wgpu::PresentMode::Mailbox,
-                present_mode: wgpu::PresentMode::Fifo, // VSync
+                present_mode: self.render_settings.present_mode,
             };
             let _window_size = window.size();
-            let render_state = RenderState::initialize(instance, surface, surface_config)
-                .await
-                .unwrap();
+            let render_state = RenderState::initialize(
+                instance,
+                surface,
+                surface_config,
+                self.render_settings.clone(),
+            )
+            .await
+            .unwrap();
             self.render_state = Some(render_state)
         }
     }
 }
+
+/// The priority of a tile request, tiles with lower values are requested first. The priority
+/// grows with the distance in tiles from the center of the viewport. Tiles which are not on the
+/// visible zoom level are requested after the tiles which are close to the center.
+fn tile_request_priority(
+    coords: &WorldTileCoords,
+    center: &WorldCoords,
+    zoom: Zoom,
+    visible_level: u8,
+) -> f64 {
+    const ZOOM_LEVEL_PENALTY: f64 = 4.0;
+
+    let scale = zoom.scale_to_zoom_level(coords.z) / TILE_SIZE;
+    let delta_x = coords.x as f64 + 0.5 - center.x * scale;
+    let delta_y = coords.y as f64 + 0.5 - center.y * scale;
+    let level_delta = (coords.z as f64 - visible_level as f64).abs();
+
+    delta_x.hypot(delta_y) + level_delta * ZOOM_LEVEL_PENALTY
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::animation::Clock;
+    use crate::coords::{
+        LngLat, LngLatBounds, ViewRegion, WorldCoords, WorldTileCoords, Zoom, TILE_SIZE,
+    };
+    use crate::headless::HeadlessMapWindowConfig;
+    use crate::io::scheduler::Scheduler;
+    use crate::io::settings::IoSettings;
+    use crate::io::source_client::{HttpSourceClient, SourceClient};
+    use crate::io::{LayerTessellateMessage, TessellateMessage};
+    use crate::map_state::{
+        tile_request_priority, CameraOptions, EdgeInsets, MapState, ViewState, DEFAULT_MAX_PITCH,
+        DEFAULT_MAX_ZOOM, DEFAULT_MIN_PITCH,
+    };
+    use crate::platform::http_client::ReqwestHttpClient;
+    use crate::platform::schedule_method::TokioScheduleMethod;
+    use crate::render::settings::RenderSettings;
+    use crate::style::Style;
+    use crate::util::math::bounds_from_points;
+    use crate::window::WindowSize;
+    use cgmath::{Deg, Rad, Vector2, Vector3};
+    use instant::Instant;
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    /// A clock which advances by `step` each time it is read.
+    struct SteppingClock {
+        now: Cell<Instant>,
+        step: Duration,
+    }
+
+    impl Clock for SteppingClock {
+        fn now(&self) -> Instant {
+            let now = self.now.get();
+            self.now.set(now + self.step);
+            now
+        }
+    }
+
+    /// Creates a map without a render state whose clock advances by `step` on each read. The
+    /// workers have sent `messages` layers to the map.
+    fn map_with_messages(
+        step: Duration,
+        io_settings: IoSettings,
+        messages: i32,
+    ) -> MapState<HeadlessMapWindowConfig, TokioScheduleMethod, ReqwestHttpClient> {
+        let window_size = WindowSize::new(800, 600).unwrap();
+        let map_state = MapState::new(
+            HeadlessMapWindowConfig::new(window_size),
+            window_size,
+            None,
+            Scheduler::new(TokioScheduleMethod::new()),
+            SourceClient::Http(HttpSourceClient::new(ReqwestHttpClient::new(None))),
+            Style::default(),
+            CameraOptions::new(),
+            Box::new(SteppingClock {
+                now: Cell::new(Instant::now()),
+                step,
+            }),
+            RenderSettings::default(),
+            io_settings,
+            None,
+        );
+
+        for x in 0..messages {
+            map_state
+                .shared_thread_state
+                .message_sender
+                .send(TessellateMessage::Layer(
+                    LayerTessellateMessage::UnavailableLayer {
+                        coords: WorldTileCoords::from((x, 0, 5)),
+                        layer_name: "water".to_string(),
+                    },
+                ))
+                .unwrap();
+        }
+        map_state
+    }
+
+    fn assert_center(view_state: &ViewState, expected: LngLat) {
+        let center = view_state.center();
+        assert!(
+            (center.lng - expected.lng).abs() < 1e-6 && (center.lat - expected.lat).abs() < 1e-6,
+            "{} != {}",
+            center,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_initial_camera() {
+        let munich = LngLat::new(11.5583, 48.1402);
+        let view_state = ViewState::new(
+            WindowSize::new(800, 600).unwrap(),
+            &CameraOptions::new()
+                .with_center(munich)
+                .with_zoom(Zoom::new(12.0))
+                .with_bearing(30.0)
+                .with_pitch(20.0),
+        );
+
+        assert_center(&view_state, munich);
+        assert!((view_state.zoom().value() - 12.0).abs() < 1e-9);
+        assert!((view_state.bearing() - 30.0).abs() < 1e-9);
+        assert!((view_state.pitch() - 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_setters_keep_center() {
+        let munich = LngLat::new(11.5583, 48.1402);
+        let mut view_state = ViewState::new(
+            WindowSize::new(800, 600).unwrap(),
+            &CameraOptions::new()
+                .with_center(munich)
+                .with_zoom(Zoom::new(10.0)),
+        );
+
+        view_state.set_zoom(Zoom::new(14.5));
+        assert_center(&view_state, munich);
+
+        view_state.set_pitch(40.0);
+        assert_center(&view_state, munich);
+
+        view_state.set_bearing(-45.0);
+        assert_center(&view_state, munich);
+        assert!((view_state.bearing() - 315.0).abs() < 1e-9);
+
+        let berlin = LngLat::new(13.4050, 52.5200);
+        view_state.set_center(berlin);
+        assert_center(&view_state, berlin);
+
+        view_state.jump_to(
+            &CameraOptions::new()
+                .with_zoom(Zoom::new(3.0))
+                .with_bearing(0.0),
+        );
+        assert_center(&view_state, berlin);
+        assert_eq!(view_state.camera_options().zoom.unwrap().value(), 3.0);
+    }
+
+    #[test]
+    fn test_fit_bounds() {
+        // Bounds of Bavaria
+        let bounds = LngLatBounds::new(LngLat::new(8.97, 47.27), LngLat::new(13.84, 50.56));
+        let padding = EdgeInsets::new(20.0, 40.0, 100.0, 10.0);
+        let (padded_width, padded_height) = (750.0, 480.0);
+
+        for (bearing, pitch) in [(0.0, 0.0), (30.0, 0.0), (0.0, 40.0), (200.0, 30.0)] {
+            let mut view_state =
+                ViewState::new(WindowSize::new(800, 600).unwrap(), &CameraOptions::new());
+            view_state.fit_bounds(&bounds, &padding, Some(bearing), Some(pitch), None);
+
+            assert!((view_state.bearing() - bearing).abs() < 1e-9);
+            assert!((view_state.pitch() - pitch).abs() < 1e-9);
+
+            let view_proj = view_state.view_projection();
+            let corners = [
+                bounds.north_west(),
+                bounds.ne,
+                bounds.south_east(),
+                bounds.sw,
+            ]
+            .map(|corner| {
+                let world = corner.into_world(view_state.zoom());
+                view_state
+                    .camera
+                    .world_to_window(&Vector3::new(world.x, world.y, 0.0), &view_proj)
+                    .unwrap()
+            });
+
+            for corner in &corners {
+                assert!((0.0..=1.0).contains(&corner.z));
+                assert!(corner.x >= padding.left - 1e-3);
+                assert!(corner.x <= 800.0 - padding.right + 1e-3);
+                assert!(corner.y >= padding.top - 1e-3);
+                assert!(corner.y <= 600.0 - padding.bottom + 1e-3);
+            }
+
+            // The bounds fill the padded viewport along at least one axis. With a pitched camera
+            // the near plane clips the bottom of the viewport.
+            let fill = if pitch == 0.0 { 0.99 } else { 0.85 };
+            let (min, max) =
+                bounds_from_points(corners.iter().map(|corner| [corner.x, corner.y])).unwrap();
+            assert!(
+                max[0] - min[0] > padded_width * fill || max[1] - min[1] > padded_height * fill,
+                "bounds do not fill the viewport with bearing {} and pitch {}",
+                bearing,
+                pitch
+            );
+        }
+    }
+
+    #[test]
+    fn test_zoom_and_pitch_limits() {
+        let mut view_state = ViewState::new(
+            WindowSize::new(800, 600).unwrap(),
+            &CameraOptions::new()
+                .with_zoom(Zoom::new(30.0))
+                .with_pitch(80.0),
+        );
+        assert_eq!(view_state.zoom().value(), DEFAULT_MAX_ZOOM);
+        assert!((view_state.pitch() - DEFAULT_MAX_PITCH).abs() < 1e-9);
+
+        view_state.set_max_zoom(Zoom::new(10.0));
+        assert_eq!(view_state.zoom().value(), 10.0);
+        view_state.update_zoom(Zoom::new(12.0));
+        assert_eq!(view_state.zoom().value(), 10.0);
+
+        view_state.set_min_zoom(Zoom::new(4.0));
+        view_state.set_zoom(Zoom::new(2.0));
+        assert_eq!(view_state.zoom().value(), 4.0);
+
+        view_state.set_max_pitch(30.0);
+        assert!((view_state.pitch() - 30.0).abs() < 1e-9);
+        view_state.set_pitch(-10.0);
+        assert!((view_state.pitch() - DEFAULT_MIN_PITCH).abs() < 1e-9);
+
+        // Direct changes to the camera are clamped during the next update
+        view_state.camera.pitch = Rad::from(Deg(50.0));
+        view_state.update(Instant::now());
+        assert!((view_state.pitch() - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_max_bounds() {
+        let munich = LngLat::new(11.5583, 48.1402);
+        let bounds = LngLatBounds::new(LngLat::new(11.0, 48.0), LngLat::new(12.0, 48.5));
+        let mut view_state = ViewState::new(
+            WindowSize::new(800, 600).unwrap(),
+            &CameraOptions::new()
+                .with_center(munich)
+                .with_zoom(Zoom::new(10.0)),
+        );
+        view_state.set_max_bounds(Some(bounds));
+
+        view_state.set_center(LngLat::new(14.0, 48.2));
+        assert_center(&view_state, LngLat::new(12.0, 48.2));
+
+        // Panning outside of the bounds is eased back instead of snapping
+        view_state.camera.position.x += 300.0;
+        let outside = view_state.center();
+        assert!(!bounds.contains(&outside));
+
+        let start = Instant::now();
+        view_state.update(start);
+        view_state.update(start + Duration::from_millis(16));
+        let eased = view_state.center();
+        assert!(eased.lng < outside.lng && eased.lng > bounds.east());
+
+        view_state.update(start + Duration::from_secs(2));
+        assert!((view_state.center().lng - bounds.east()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_rotation() {
+        let munich = LngLat::new(11.5583, 48.1402);
+        let mut view_state = ViewState::new(
+            WindowSize::new(800, 600).unwrap(),
+            &CameraOptions::new()
+                .with_center(munich)
+                .with_zoom(Zoom::new(10.0)),
+        );
+
+        let window_to_lng_lat = |view_state: &ViewState, x: f64, y: f64| {
+            let world = view_state
+                .camera
+                .window_to_world_at_ground(
+                    &Vector2::new(x, y),
+                    &view_state.view_projection().invert(),
+                )
+                .unwrap();
+            WorldCoords::at_ground(world.x, world.y).into_lng_lat(view_state.zoom())
+        };
+
+        // Without rotation the right edge of the window is east of the center
+        let right = window_to_lng_lat(&view_state, 800.0, 300.0);
+        assert!(right.lng > munich.lng && (right.lat - munich.lat).abs() < 1e-6);
+
+        // With a bearing of 90 degrees east is up
+        view_state.set_bearing(90.0);
+        assert_center(&view_state, munich);
+        let top = window_to_lng_lat(&view_state, 400.0, 0.0);
+        assert!(top.lng > munich.lng && (top.lat - munich.lat).abs() < 1e-6);
+        let right = window_to_lng_lat(&view_state, 800.0, 300.0);
+        assert!(right.lat < munich.lat && (right.lng - munich.lng).abs() < 1e-6);
+
+        // Rotating around a window position keeps the position in place
+        let anchor = Vector2::new(100.0, 500.0);
+        let before = window_to_lng_lat(&view_state, anchor.x, anchor.y);
+        view_state.rotate_around(-30.0, &anchor);
+        assert!((view_state.bearing() - 60.0).abs() < 1e-9);
+        let after = window_to_lng_lat(&view_state, anchor.x, anchor.y);
+        assert!((before.lng - after.lng).abs() < 1e-9 && (before.lat - after.lat).abs() < 1e-9);
+
+        view_state.reset_north(None);
+        assert!(view_state.bearing().abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_rotated_view_region() {
+        let mut view_state = ViewState::new(
+            WindowSize::new(800, 600).unwrap(),
+            &CameraOptions::new()
+                .with_center(LngLat::new(11.5583, 48.1402))
+                .with_zoom(Zoom::new(10.0)),
+        );
+        view_state.set_bearing(45.0);
+
+        let inverted_view_proj = view_state.view_projection().invert();
+        let polygon = view_state
+            .camera
+            .view_region_polygon(&inverted_view_proj)
+            .unwrap();
+        let bounding_box = view_state
+            .camera
+            .view_region_bounding_box(&inverted_view_proj)
+            .unwrap();
+
+        let from_polygon = ViewRegion::from_polygon(polygon, 0, view_state.zoom(), 12).unwrap();
+        let from_bounding_box = ViewRegion::new(bounding_box, 0, view_state.zoom(), 12);
+
+        let polygon_tiles = from_polygon.iter().collect::<Vec<_>>();
+        let bounding_box_tiles = from_bounding_box.iter().collect::<Vec<_>>();
+
+        // The polygon covers fewer tiles, but all of them are also covered by the bounding box
+        assert!(polygon_tiles.len() < bounding_box_tiles.len());
+        assert!(polygon_tiles
+            .iter()
+            .all(|tile| bounding_box_tiles.contains(tile) && from_polygon.is_in_view(tile)));
+
+        // The tile in the center of the view is covered
+        let center = view_state.center().into_world_tile(12);
+        assert!(polygon_tiles.contains(&center));
+    }
+
+    #[test]
+    fn test_tile_request_priority() {
+        let zoom = Zoom::new(2.0);
+        // The center of the tile (1, 1, 2)
+        let center = WorldCoords::at_ground(TILE_SIZE * 1.5, TILE_SIZE * 1.5);
+
+        let mut coords = vec![
+            WorldTileCoords::from((3, 3, 2)),
+            WorldTileCoords::from((0, 0, 1)),
+            WorldTileCoords::from((2, 1, 2)),
+            WorldTileCoords::from((1, 1, 2)),
+        ];
+        coords.sort_by(|a, b| {
+            tile_request_priority(a, &center, zoom, 2)
+                .partial_cmp(&tile_request_priority(b, &center, zoom, 2))
+                .unwrap()
+        });
+
+        assert_eq!(
+            coords,
+            vec![
+                WorldTileCoords::from((1, 1, 2)),
+                WorldTileCoords::from((2, 1, 2)),
+                WorldTileCoords::from((3, 3, 2)),
+                WorldTileCoords::from((0, 0, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_drain_time_budget() {
+        let io_settings = IoSettings {
+            drain_time_budget: Duration::from_millis(3),
+            ..IoSettings::default()
+        };
+        let mut map_state = map_with_messages(Duration::from_millis(1), io_settings, 10);
+
+        // The clock advances by one millisecond before each further message
+        map_state.try_populate_cache();
+        let statistics = map_state.frame_statistics();
+        assert_eq!(statistics.messages_received, 3);
+        assert!(statistics.budget_exhausted);
+        assert!(statistics.drain_duration >= Duration::from_millis(3));
+
+        map_state.try_populate_cache();
+        assert_eq!(map_state.frame_statistics().messages_received, 3);
+        map_state.try_populate_cache();
+        map_state.try_populate_cache();
+        let statistics = map_state.frame_statistics();
+        assert_eq!(statistics.messages_received, 1);
+        assert!(!statistics.budget_exhausted);
+    }
+
+    #[test]
+    fn test_drain_byte_budget() {
+        let io_settings = IoSettings {
+            drain_byte_budget: 1,
+            ..IoSettings::default()
+        };
+        let mut map_state = map_with_messages(Duration::ZERO, io_settings, 2);
+
+        // At least one message is received per frame even if it exceeds the budget
+        map_state.try_populate_cache();
+        let statistics = map_state.frame_statistics();
+        assert_eq!(statistics.messages_received, 1);
+        assert_eq!(statistics.layers_received, 1);
+        assert!(statistics.budget_exhausted);
+
+        map_state.try_populate_cache();
+        assert_eq!(map_state.frame_statistics().messages_received, 1);
+        map_state.try_populate_cache();
+        let statistics = map_state.frame_statistics();
+        assert_eq!(statistics.messages_received, 0);
+        assert!(!statistics.budget_exhausted);
+    }
+}
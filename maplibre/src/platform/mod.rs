@@ -29,6 +29,12 @@ pub const COLOR_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8
 #[cfg(not(target_arch = "wasm32"))]
 mod noweb;
 
+/// Disk cache for HTTP responses for non-web targets.
+pub mod http_cache {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub use super::noweb::http_cache::*;
+}
+
 /// Http client for non-web targets.
 pub mod http_client {
     #[cfg(not(target_arch = "wasm32"))]
@@ -0,0 +1,380 @@
+//! Disk cache for HTTP responses which is backed by SQLite.
+
+use crate::error::Error;
+use crate::io::http_cache::{CachedResponse, HttpCache};
+use async_trait::async_trait;
+use flate2::Crc;
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+fn checksum(data: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+fn is_corruption(error: &rusqlite::Error) -> bool {
+    match error {
+        rusqlite::Error::SqliteFailure(error, _) => matches!(
+            error.code,
+            ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase
+        ),
+        _ => false,
+    }
+}
+
+/// Caches HTTP responses in a SQLite database. If the size of the cached responses exceeds
+/// `max_size`, then the least recently used responses are evicted.
+///
+/// Entries which fail their checksum are removed. If the database itself is corrupted, then it is
+/// deleted and recreated.
+pub struct SqliteHttpCache {
+    state: Arc<Mutex<CacheState>>,
+}
+
+struct CacheState {
+    path: Option<PathBuf>,
+    connection: Connection,
+    max_size: u64,
+    /// The size of all cached responses in bytes. It is tracked such that the size does not need
+    /// to be summed up on every write.
+    size: u64,
+}
+
+impl SqliteHttpCache {
+    /// Opens the cache at `path`. The database is recreated if it can not be read.
+    pub fn open<P: AsRef<Path>>(path: P, max_size: u64) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let connection = match CacheState::connect(Some(&path)) {
+            Ok(connection) => connection,
+            Err(e) if is_corruption(&e) => {
+                log::warn!("HTTP cache {:?} is corrupted and is recreated", path);
+                std::fs::remove_file(&path).map_err(|e| Error::Network(e.to_string()))?;
+                CacheState::connect(Some(&path))?
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        Self::with_connection(Some(path), connection, max_size)
+    }
+
+    pub fn open_in_memory(max_size: u64) -> Result<Self, Error> {
+        Self::with_connection(None, CacheState::connect(None)?, max_size)
+    }
+
+    fn with_connection(
+        path: Option<PathBuf>,
+        connection: Connection,
+        max_size: u64,
+    ) -> Result<Self, Error> {
+        let size = CacheState::stored_size(&connection)?;
+        Ok(Self {
+            state: Arc::new(Mutex::new(CacheState {
+                path,
+                connection,
+                max_size,
+                size,
+            })),
+        })
+    }
+
+    /// Runs `f` with the state of the cache. SQLite blocks, therefore `f` must not run on the
+    /// threads of the async runtime. If the cache is unusable, because a previous access
+    /// panicked, then `None` is returned and the cache is treated as a miss.
+    async fn run<T, F>(&self, f: F) -> Option<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut CacheState) -> Option<T> + Send + 'static,
+    {
+        let state = self.state.clone();
+        let result = tokio::task::spawn_blocking(move || match state.lock() {
+            Ok(mut state) => f(&mut state),
+            Err(_) => {
+                log::warn!("HTTP cache is poisoned and is not used");
+                None
+            }
+        })
+        .await;
+
+        match result {
+            Ok(result) => result,
+            Err(e) => {
+                log::warn!("HTTP cache failed: {}", e);
+                None
+            }
+        }
+    }
+
+    /// The size of all cached responses in bytes.
+    pub fn size(&self) -> u64 {
+        match self.state.lock() {
+            Ok(state) => state.size,
+            Err(_) => 0,
+        }
+    }
+}
+
+impl CacheState {
+    fn connect(path: Option<&Path>) -> Result<Connection, rusqlite::Error> {
+        let connection = match path {
+            Some(path) => Connection::open(path)?,
+            None => Connection::open_in_memory()?,
+        };
+        // language=SQL
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS responses (
+                url TEXT PRIMARY KEY,
+                data BLOB NOT NULL,
+                checksum INTEGER NOT NULL,
+                size INTEGER NOT NULL,
+                etag TEXT,
+                last_modified TEXT,
+                expires INTEGER NOT NULL,
+                last_access INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS responses_last_access ON responses (last_access);",
+        )?;
+        Ok(connection)
+    }
+
+    fn stored_size(connection: &Connection) -> Result<u64, rusqlite::Error> {
+        // language=SQL
+        let size: i64 =
+            connection.query_row("SELECT COALESCE(SUM(size), 0) FROM responses;", [], |row| {
+                row.get(0)
+            })?;
+        Ok(size as u64)
+    }
+
+    /// Logs `error` and recreates the database if it is corrupted.
+    fn recover(&mut self, error: rusqlite::Error) {
+        log::warn!("HTTP cache failed: {}", error);
+        if is_corruption(&error) {
+            if let Some(path) = &self.path {
+                // Release the file before it is deleted
+                if let Ok(in_memory) = Connection::open_in_memory() {
+                    drop(std::mem::replace(&mut self.connection, in_memory));
+                }
+                let _ = std::fs::remove_file(path);
+            }
+            match Self::connect(self.path.as_deref()) {
+                Ok(fresh) => self.connection = fresh,
+                Err(e) => log::error!("recreating the HTTP cache failed: {}", e),
+            }
+        }
+
+        // A failed write can leave the tracked size behind the stored responses
+        self.size = Self::stored_size(&self.connection).unwrap_or(0);
+    }
+
+    fn read(&mut self, url: &str) -> Result<Option<CachedResponse>, rusqlite::Error> {
+        // language=SQL
+        let row = self
+            .connection
+            .query_row(
+                "SELECT data, checksum, etag, last_modified, expires FROM responses WHERE url = ?1;",
+                params![url],
+                |row| {
+                    Ok((
+                        row.get::<_, Vec<u8>>(0)?,
+                        row.get::<_, u32>(1)?,
+                        CachedResponse {
+                            data: Vec::new(),
+                            etag: row.get(2)?,
+                            last_modified: row.get(3)?,
+                            expires: row.get::<_, i64>(4)? as u64,
+                        },
+                    ))
+                },
+            )
+            .optional()?;
+
+        let (data, expected, response) = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        if checksum(&data) != expected {
+            log::warn!("cached response for {} is corrupted and was removed", url);
+            self.delete(url)?;
+            return Ok(None);
+        }
+
+        // language=SQL
+        self.connection.execute(
+            "UPDATE responses SET last_access = (SELECT MAX(last_access) + 1 FROM responses)
+                WHERE url = ?1;",
+            params![url],
+        )?;
+
+        Ok(Some(CachedResponse { data, ..response }))
+    }
+
+    fn write(&mut self, url: &str, response: &CachedResponse) -> Result<(), rusqlite::Error> {
+        self.delete(url)?;
+        // language=SQL
+        self.connection.execute(
+            "INSERT INTO responses
+                (url, data, checksum, size, etag, last_modified, expires, last_access)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7,
+                    (SELECT COALESCE(MAX(last_access), 0) + 1 FROM responses));",
+            params![
+                url,
+                response.data,
+                checksum(&response.data),
+                response.data.len() as i64,
+                response.etag,
+                response.last_modified,
+                response.expires as i64
+            ],
+        )?;
+        self.size += response.data.len() as u64;
+
+        while self.size > self.max_size {
+            // language=SQL
+            let least_recently_used: Option<String> = self
+                .connection
+                .query_row(
+                    "SELECT url FROM responses ORDER BY last_access LIMIT 1;",
+                    [],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            match least_recently_used {
+                Some(url) => self.delete(&url)?,
+                None => self.size = 0,
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes the response for `url` and subtracts its size.
+    fn delete(&mut self, url: &str) -> Result<(), rusqlite::Error> {
+        // language=SQL
+        let size: Option<i64> = self
+            .connection
+            .query_row(
+                "SELECT size FROM responses WHERE url = ?1;",
+                params![url],
+                |row| row.get(0),
+            )
+            .optional()?;
+        // language=SQL
+        self.connection
+            .execute("DELETE FROM responses WHERE url = ?1;", params![url])?;
+        self.size = self.size.saturating_sub(size.unwrap_or(0) as u64);
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "no-thread-safe-futures", async_trait(?Send))]
+#[cfg_attr(not(feature = "no-thread-safe-futures"), async_trait)]
+impl HttpCache for SqliteHttpCache {
+    async fn get(&self, url: &str) -> Option<CachedResponse> {
+        let url = url.to_string();
+        self.run(move |state| match state.read(&url) {
+            Ok(response) => response,
+            Err(e) => {
+                state.recover(e);
+                None
+            }
+        })
+        .await
+    }
+
+    async fn put(&self, url: &str, response: CachedResponse) {
+        let url = url.to_string();
+        self.run(move |state| {
+            if response.data.len() as u64 > state.max_size {
+                return None;
+            }
+
+            if let Err(e) = state.write(&url, &response) {
+                state.recover(e);
+            }
+            Some(())
+        })
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SqliteHttpCache;
+    use crate::io::http_cache::{CachedResponse, HttpCache};
+    use rusqlite::params;
+
+    fn response(data: Vec<u8>) -> CachedResponse {
+        CachedResponse {
+            data,
+            etag: Some("\"etag\"".to_string()),
+            last_modified: None,
+            expires: 10,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lru_eviction() {
+        let cache = SqliteHttpCache::open_in_memory(10).unwrap();
+        cache.put("a", response(vec![0; 4])).await;
+        cache.put("b", response(vec![1; 4])).await;
+        assert_eq!(cache.get("a").await, Some(response(vec![0; 4])));
+
+        // "b" is the least recently used response
+        cache.put("c", response(vec![2; 4])).await;
+        assert_eq!(cache.size(), 8);
+        assert!(cache.get("a").await.is_some());
+        assert!(cache.get("b").await.is_none());
+        assert!(cache.get("c").await.is_some());
+
+        // Responses which exceed the cache are not stored
+        cache.put("d", response(vec![3; 11])).await;
+        assert!(cache.get("d").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_corruption() {
+        let cache = SqliteHttpCache::open_in_memory(100).unwrap();
+        cache.put("a", response(vec![0; 4])).await;
+        cache
+            .state
+            .lock()
+            .unwrap()
+            .connection
+            .execute(
+                "UPDATE responses SET data = ?1 WHERE url = 'a';",
+                params![vec![1u8; 4]],
+            )
+            .unwrap();
+        assert!(cache.get("a").await.is_none());
+        assert_eq!(cache.size(), 0);
+
+        let path =
+            std::env::temp_dir().join(format!("maplibre-test-{}.http-cache", std::process::id()));
+        std::fs::write(&path, b"this is not a database, this is not a database").unwrap();
+        let cache = SqliteHttpCache::open(&path, 100).unwrap();
+        cache.put("a", response(vec![0; 4])).await;
+        assert!(cache.get("a").await.is_some());
+        drop(cache);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_poisoned() {
+        let cache = SqliteHttpCache::open_in_memory(100).unwrap();
+        cache.put("a", response(vec![0; 4])).await;
+
+        let state = cache.state.clone();
+        let _ = std::thread::spawn(move || {
+            let _state = state.lock().unwrap();
+            panic!("poison the cache");
+        })
+        .join();
+
+        // The cache is treated as a miss
+        assert!(cache.get("a").await.is_none());
+        cache.put("b", response(vec![0; 4])).await;
+        assert!(cache.get("b").await.is_none());
+    }
+}
@@ -1,14 +1,25 @@
 use crate::error::Error;
+use crate::io::http_cache::{CachePolicy, CachedResponse, HttpCache};
+use crate::io::source_client::{Request, ResourceKind, TransformRequest};
+use crate::platform::http_cache::SqliteHttpCache;
 use crate::HTTPClient;
 use async_trait::async_trait;
-use reqwest::{Client, StatusCode};
-use reqwest_middleware::ClientWithMiddleware;
-use reqwest_middleware_cache::managers::CACacheManager;
-use reqwest_middleware_cache::{Cache, CacheMode};
+use reqwest::header::{
+    HeaderMap, CACHE_CONTROL, ETAG, EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The default maximum size of the HTTP cache in bytes.
+const DEFAULT_CACHE_SIZE: u64 = 1024 * 1024 * 256;
 
 #[derive(Clone)]
 pub struct ReqwestHttpClient {
-    client: ClientWithMiddleware,
+    client: Client,
+    cache: Option<Arc<dyn HttpCache>>,
+    transform_request: Option<Arc<TransformRequest>>,
 }
 impl From<reqwest::Error> for Error {
     fn from(err: reqwest::Error) -> Self {
@@ -16,44 +27,335 @@ impl From<reqwest::Error> for Error {
     }
 }
 
-impl From<reqwest_middleware::Error> for Error {
-    fn from(err: reqwest_middleware::Error) -> Self {
-        Error::Network(err.to_string())
+/// Maps a failed response to an error by its status, see [`Error::from_status`].
+fn status_error(e: reqwest::Error) -> Error {
+    match e.status() {
+        Some(status) => Error::from_status(status.as_u16(), e.to_string()),
+        None => Error::Network(e.to_string()),
+    }
+}
+
+/// Returns the response if it is successful. A `204 No Content` response means that the
+/// resource is empty, for example a tile without data, and is reported as [`Error::NotFound`].
+fn check_status(response: Response) -> Result<Response, Error> {
+    if response.status() == StatusCode::NO_CONTENT {
+        return Err(Error::NotFound(format!(
+            "{} has no content",
+            response.url()
+        )));
     }
+    response.error_for_status().map_err(status_error)
+}
+
+fn unix_time_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn header_value(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+fn cache_policy(headers: &HeaderMap, now: u64) -> CachePolicy {
+    let expires = header_value(headers, EXPIRES)
+        .and_then(|expires| httpdate::parse_http_date(&expires).ok())
+        .and_then(|expires| expires.duration_since(UNIX_EPOCH).ok())
+        .map(|expires| expires.as_secs());
+    CachePolicy::from_headers(
+        header_value(headers, CACHE_CONTROL).as_deref(),
+        expires,
+        now,
+    )
 }
 
 impl ReqwestHttpClient {
-    /// cache_path: Under which path should we cache requests.
+    /// cache_path: Under which path should we cache requests. The cache is limited to 256MiB.
     pub fn new(cache_path: Option<String>) -> Self {
-        let mut builder = reqwest_middleware::ClientBuilder::new(Client::new());
-
-        if let Some(cache_path) = cache_path {
-            builder = builder.with(Cache {
-                mode: CacheMode::Default,
-                cache_manager: CACacheManager { path: cache_path },
-            });
-        }
+        let cache = cache_path.and_then(|cache_path| {
+            if let Err(e) = std::fs::create_dir_all(&cache_path) {
+                log::error!("failed to create the HTTP cache directory: {}", e);
+                return None;
+            }
+            match SqliteHttpCache::open(
+                Path::new(&cache_path).join("http-cache.sqlite"),
+                DEFAULT_CACHE_SIZE,
+            ) {
+                Ok(cache) => Some(Arc::new(cache) as Arc<dyn HttpCache>),
+                Err(e) => {
+                    log::error!("failed to open the HTTP cache: {:?}", e);
+                    None
+                }
+            }
+        });
 
         Self {
-            client: builder.build(),
+            client: Client::new(),
+            cache,
+            transform_request: None,
         }
     }
+
+    /// Caches responses in `cache` instead of the default cache.
+    pub fn with_cache<C: HttpCache>(mut self, cache: C) -> Self {
+        self.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Builds the request for `url`, which is transformed by the [`TransformRequest`] if set.
+    /// The credentials of the request are ignored, because there are no cookies outside of the
+    /// browser.
+    fn request(&self, url: &str, kind: ResourceKind) -> RequestBuilder {
+        let request = match &self.transform_request {
+            Some(transform_request) => transform_request(url, kind),
+            None => Request::new(url),
+        };
+
+        request
+            .headers
+            .iter()
+            .fold(self.client.get(&request.url), |builder, (name, value)| {
+                builder.header(name.as_str(), value.as_str())
+            })
+    }
+
+    async fn fetch_uncached(&self, url: &str, kind: ResourceKind) -> Result<Vec<u8>, Error> {
+        let response = check_status(self.request(url, kind).send().await?)?;
+        let body = response.bytes().await?;
+        Ok(Vec::from(body.as_ref()))
+    }
 }
 
 #[async_trait]
 impl HTTPClient for ReqwestHttpClient {
-    async fn fetch(&self, url: &str) -> Result<Vec<u8>, Error> {
-        let response = self.client.get(url).send().await?;
-        match response.error_for_status() {
-            Ok(response) => {
-                if response.status() == StatusCode::NOT_MODIFIED {
-                    log::info!("Using data from cache");
+    async fn fetch(&self, url: &str, kind: ResourceKind) -> Result<Vec<u8>, Error> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return self.fetch_uncached(url, kind).await,
+        };
+
+        let now = unix_time_now();
+        let cached = cache.get(url).await;
+        if let Some(cached) = &cached {
+            if cached.is_fresh(now) {
+                return Ok(cached.data.clone());
+            }
+        }
+
+        let mut request = self.request(url, kind);
+        if let Some(cached) = &cached {
+            if let Some(etag) = &cached.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                // Stale data is better than no data, for example if the device is offline
+                return match cached {
+                    Some(cached) => {
+                        log::warn!("using stale cached response for {}: {}", url, e);
+                        Ok(cached.data)
+                    }
+                    None => Err(e.into()),
+                };
+            }
+        };
+
+        if response.status().is_server_error() {
+            if let Some(cached) = cached {
+                log::warn!(
+                    "using stale cached response for {}: {}",
+                    url,
+                    response.status()
+                );
+                return Ok(cached.data);
+            }
+        }
+
+        let policy = cache_policy(response.headers(), now);
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(cached) = cached {
+                log::info!("Using data from cache");
+                let data = cached.data.clone();
+                if policy.storable {
+                    cache
+                        .put(
+                            url,
+                            CachedResponse {
+                                expires: policy.expires,
+                                ..cached
+                            },
+                        )
+                        .await;
                 }
+                return Ok(data);
+            }
+        }
+
+        let response = check_status(response)?;
+        let etag = header_value(response.headers(), ETAG);
+        let last_modified = header_value(response.headers(), LAST_MODIFIED);
+        let data = Vec::from(response.bytes().await?.as_ref());
+        if policy.storable {
+            cache
+                .put(
+                    url,
+                    CachedResponse {
+                        data: data.clone(),
+                        etag,
+                        last_modified,
+                        expires: policy.expires,
+                    },
+                )
+                .await;
+        }
+        Ok(data)
+    }
+
+    fn with_transform_request(mut self, transform_request: Arc<TransformRequest>) -> Self {
+        self.transform_request = Some(transform_request);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReqwestHttpClient;
+    use crate::error::Error;
+    use crate::io::source_client::{HTTPClient, ResourceKind};
+    use crate::platform::http_cache::SqliteHttpCache;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
 
-                let body = response.bytes().await?;
-                Ok(Vec::from(body.as_ref()))
+    /// Answers the requests to the returned URL with `responses` in order. The received requests
+    /// are recorded.
+    fn serve(responses: Vec<&'static str>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/tile.pbf", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let read = stream.read(&mut buffer).unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                received
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(&request).to_lowercase());
+                stream.write_all(response.as_bytes()).unwrap();
             }
-            Err(e) => Err(Error::Network(e.to_string())),
+        });
+        (url, requests)
+    }
+
+    const CACHED: &str = "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nCache-Control: no-cache\r\n\
+        Content-Length: 4\r\nConnection: close\r\n\r\ntile";
+
+    fn client() -> ReqwestHttpClient {
+        ReqwestHttpClient::new(None).with_cache(SqliteHttpCache::open_in_memory(1024).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_revalidation() {
+        let (url, requests) = serve(vec![
+            CACHED,
+            "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n",
+        ]);
+        let client = client();
+
+        assert_eq!(
+            client.fetch(&url, ResourceKind::Tile).await.unwrap(),
+            b"tile"
+        );
+        assert_eq!(
+            client.fetch(&url, ResourceKind::Tile).await.unwrap(),
+            b"tile"
+        );
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].contains("if-none-match: \"v1\""));
+    }
+
+    #[tokio::test]
+    async fn test_not_found() {
+        let (url, _) = serve(vec![
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n",
+        ]);
+        let client = ReqwestHttpClient::new(None);
+
+        assert!(matches!(
+            client.fetch(&url, ResourceKind::Tile).await,
+            Err(Error::NotFound(_))
+        ));
+        // An empty tile is not retried
+        assert!(matches!(
+            client.fetch(&url, ResourceKind::Tile).await,
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stale_on_error() {
+        let unavailable =
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        let (url, _) = serve(vec![CACHED, unavailable, unavailable]);
+        let client = client();
+
+        assert_eq!(
+            client.fetch(&url, ResourceKind::Tile).await.unwrap(),
+            b"tile"
+        );
+        assert_eq!(
+            client.fetch(&url, ResourceKind::Tile).await.unwrap(),
+            b"tile"
+        );
+
+        // Without a cached response the error is reported
+        assert!(matches!(
+            ReqwestHttpClient::new(None)
+                .fetch(&url, ResourceKind::Tile)
+                .await,
+            Err(Error::Network(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_status_errors() {
+        let (url, _) = serve(vec![
+            "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 429 Too Many Requests\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 408 Request Timeout\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let client = ReqwestHttpClient::new(None);
+
+        let forbidden = client.fetch(&url, ResourceKind::Tile).await.unwrap_err();
+        assert!(matches!(forbidden, Error::Rejected(_)));
+        assert!(!forbidden.is_transient());
+        for _ in 0..2 {
+            assert!(client
+                .fetch(&url, ResourceKind::Tile)
+                .await
+                .unwrap_err()
+                .is_transient());
         }
     }
 }
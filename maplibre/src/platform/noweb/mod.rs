@@ -2,6 +2,7 @@
 
 use std::future::Future;
 
+pub mod http_cache;
 pub mod http_client;
 pub mod schedule_method;
 
@@ -3,6 +3,7 @@ use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::mem::size_of;
 use std::ops::Range;
+use std::{cmp, fmt};
 
 use crate::style::layer::StyleLayer;
 use wgpu::BufferAddress;
@@ -21,8 +22,76 @@ impl Queue<wgpu::Buffer> for wgpu::Queue {
     }
 }
 
+/// Creates backing buffers and copies between them. This is required for growing and compacting
+/// the buffers of a [`BufferPool`].
+pub trait Device<Q, B> {
+    /// Creates a buffer for the given type of data. The buffer must be usable as source and
+    /// destination of copies.
+    fn create_backing_buffer(&self, typ: BackingBufferType, size: wgpu::BufferAddress) -> B;
+
+    /// Copies ranges of bytes from `source` to `destination`.
+    fn copy_buffer(&self, queue: &Q, source: &B, destination: &B, copies: &[BufferCopy]);
+}
+
+impl Device<wgpu::Queue, wgpu::Buffer> for wgpu::Device {
+    fn create_backing_buffer(
+        &self,
+        typ: BackingBufferType,
+        size: wgpu::BufferAddress,
+    ) -> wgpu::Buffer {
+        let usage = match typ {
+            BackingBufferType::Indices => wgpu::BufferUsages::INDEX,
+            BackingBufferType::Vertices
+            | BackingBufferType::Metadata
+            | BackingBufferType::FeatureMetadata => wgpu::BufferUsages::VERTEX,
+        };
+
+        self.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: usage | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn copy_buffer(
+        &self,
+        queue: &wgpu::Queue,
+        source: &wgpu::Buffer,
+        destination: &wgpu::Buffer,
+        copies: &[BufferCopy],
+    ) {
+        let mut encoder = self.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Buffer pool copy encoder"),
+        });
+
+        for copy in copies {
+            encoder.copy_buffer_to_buffer(
+                source,
+                copy.source.start,
+                destination,
+                copy.destination,
+                copy.source.end - copy.source.start,
+            );
+        }
+
+        queue.submit(Some(encoder.finish()));
+    }
+}
+
+/// Describes a copy of the bytes in `source` to the `destination` offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BufferCopy {
+    pub source: Range<wgpu::BufferAddress>,
+    pub destination: wgpu::BufferAddress,
+}
+
 /// This is inspired by the memory pool in Vulkan documented
 /// [here](https://gpuopen-librariesandsdks.github.io/VulkanMemoryAllocator/html/custom_memory_pools.html).
+///
+/// Each backing buffer manages its space with a free list. If a layer does not fit, then the
+/// backing buffer is compacted, grown up to its maximum size or the least recently visible tiles
+/// are evicted. Tiles which are visible in the current frame are never evicted.
 #[derive(Debug)]
 pub struct BufferPool<Q, B, V, I, M, FM> {
     vertices: BackingBuffer<B>,
@@ -30,7 +99,17 @@ pub struct BufferPool<Q, B, V, I, M, FM> {
     layer_metadata: BackingBuffer<B>,
     feature_metadata: BackingBuffer<B>,
 
-    index: RingIndex,
+    index: PoolIndex,
+
+    /// Incremented whenever the visible tiles are marked
+    frame: u64,
+    /// The last frame in which a tile has been visible
+    last_visible: BTreeMap<Quadkey, u64>,
+
+    evictions: u64,
+    growths: u64,
+    compactions: u64,
+
     phantom_v: PhantomData<V>,
     phantom_i: PhantomData<I>,
     phantom_q: PhantomData<Q>,
@@ -38,14 +117,41 @@ pub struct BufferPool<Q, B, V, I, M, FM> {
     phantom_fm: PhantomData<FM>,
 }
 
-#[derive(Debug)]
-enum BackingBufferType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackingBufferType {
     Vertices,
     Indices,
     Metadata,
     FeatureMetadata,
 }
 
+/// Reasons why a layer can not be allocated in a [`BufferPool`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum AllocationError {
+    /// The layer is larger than the maximum size of a backing buffer.
+    TooLarge {
+        typ: BackingBufferType,
+        bytes: wgpu::BufferAddress,
+    },
+    /// The remaining space is occupied by visible tiles.
+    OutOfMemory { typ: BackingBufferType },
+}
+
+impl fmt::Display for AllocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AllocationError::TooLarge { typ, bytes } => write!(
+                f,
+                "{} bytes exceed the maximum size of the {:?} buffer",
+                bytes, typ
+            ),
+            AllocationError::OutOfMemory { typ } => {
+                write!(f, "the {:?} buffer is full of visible tiles", typ)
+            }
+        }
+    }
+}
+
 impl<Q: Queue<B>, B, V: bytemuck::Pod, I: bytemuck::Pod, TM: bytemuck::Pod, FM: bytemuck::Pod>
     BufferPool<Q, B, V, I, TM, FM>
 {
@@ -56,27 +162,19 @@ impl<Q: Queue<B>, B, V: bytemuck::Pod, I: bytemuck::Pod, TM: bytemuck::Pod, FM:
         feature_metadata: BackingBufferDescriptor<B>,
     ) -> Self {
         Self {
-            vertices: BackingBuffer::new(
-                vertices.buffer,
-                vertices.inner_size,
-                BackingBufferType::Vertices,
-            ),
-            indices: BackingBuffer::new(
-                indices.buffer,
-                indices.inner_size,
-                BackingBufferType::Indices,
-            ),
-            layer_metadata: BackingBuffer::new(
-                layer_metadata.buffer,
-                layer_metadata.inner_size,
-                BackingBufferType::Metadata,
-            ),
+            vertices: BackingBuffer::new(vertices, BackingBufferType::Vertices),
+            indices: BackingBuffer::new(indices, BackingBufferType::Indices),
+            layer_metadata: BackingBuffer::new(layer_metadata, BackingBufferType::Metadata),
             feature_metadata: BackingBuffer::new(
-                feature_metadata.buffer,
-                feature_metadata.inner_size,
+                feature_metadata,
                 BackingBufferType::FeatureMetadata,
             ),
-            index: RingIndex::new(),
+            index: PoolIndex::new(),
+            frame: 0,
+            last_visible: BTreeMap::new(),
+            evictions: 0,
+            growths: 0,
+            compactions: 0,
             phantom_v: Default::default(),
             phantom_i: Default::default(),
             phantom_q: Default::default(),
@@ -87,15 +185,7 @@ impl<Q: Queue<B>, B, V: bytemuck::Pod, I: bytemuck::Pod, TM: bytemuck::Pod, FM:
 
     #[cfg(test)]
     fn available_space(&self, typ: BackingBufferType) -> wgpu::BufferAddress {
-        let gap = match typ {
-            BackingBufferType::Vertices => &self.vertices,
-            BackingBufferType::Indices => &self.indices,
-            BackingBufferType::Metadata => &self.layer_metadata,
-            BackingBufferType::FeatureMetadata => &self.feature_metadata,
-        }
-        .find_largest_gap(&self.index);
-
-        gap.end - gap.start
+        self.backing_buffer(typ).free_list.largest_free_block()
     }
 
     pub fn vertices(&self) -> &B {
@@ -114,6 +204,24 @@ impl<Q: Queue<B>, B, V: bytemuck::Pod, I: bytemuck::Pod, TM: bytemuck::Pod, FM:
         &self.feature_metadata.inner
     }
 
+    fn backing_buffer(&self, typ: BackingBufferType) -> &BackingBuffer<B> {
+        match typ {
+            BackingBufferType::Vertices => &self.vertices,
+            BackingBufferType::Indices => &self.indices,
+            BackingBufferType::Metadata => &self.layer_metadata,
+            BackingBufferType::FeatureMetadata => &self.feature_metadata,
+        }
+    }
+
+    fn backing_buffer_mut(&mut self, typ: BackingBufferType) -> &mut BackingBuffer<B> {
+        match typ {
+            BackingBufferType::Vertices => &mut self.vertices,
+            BackingBufferType::Indices => &mut self.indices,
+            BackingBufferType::Metadata => &mut self.layer_metadata,
+            BackingBufferType::FeatureMetadata => &mut self.feature_metadata,
+        }
+    }
+
     /// The VertexBuffers can contain padding elements. Not everything from a VertexBuffers is useable.
     /// The function returns the `bytes` and `aligned_bytes`. See [`OverAlignedVertexBuffer`].
     fn align(
@@ -142,21 +250,34 @@ impl<Q: Queue<B>, B, V: bytemuck::Pod, I: bytemuck::Pod, TM: bytemuck::Pod, FM:
         })
     }
 
+    /// Marks the tiles which are visible in the current frame. Visible tiles are never evicted
+    /// until other tiles are marked as visible.
+    pub fn mark_visible<'a>(&mut self, coords: impl Iterator<Item = &'a WorldTileCoords>) {
+        self.frame += 1;
+        for coords in coords {
+            if let Some(key) = coords.build_quad_key() {
+                self.last_visible.insert(key, self.frame);
+            }
+        }
+    }
+
     /// Allocates
     /// * `geometry`
     /// * `layer_metadata` and
-    /// * `feature_metadata` for a layer. This function is able to dynamically evict layers if there
-    /// is not enough space available.
+    /// * `feature_metadata` for a layer. This function is able to dynamically compact and grow
+    /// the backing buffers and to evict layers if there is not enough space available.
     #[tracing::instrument(skip_all)]
-    pub fn allocate_layer_geometry(
+    #[allow(clippy::too_many_arguments)]
+    pub fn allocate_layer_geometry<D: Device<Q, B>>(
         &mut self,
+        device: &D,
         queue: &Q,
         coords: WorldTileCoords,
         style_layer: StyleLayer,
         geometry: &OverAlignedVertexBuffer<V, I>,
         layer_metadata: TM,
         feature_metadata: &[FM],
-    ) {
+    ) -> Result<(), AllocationError> {
         let vertices_stride = size_of::<V>() as wgpu::BufferAddress;
         let indices_stride = size_of::<I>() as wgpu::BufferAddress;
         let layer_metadata_stride = size_of::<TM>() as wgpu::BufferAddress;
@@ -188,18 +309,25 @@ impl<Q: Queue<B>, B, V: bytemuck::Pod, I: bytemuck::Pod, TM: bytemuck::Pod, FM:
             )
         }
 
+        let requests = [
+            (BackingBufferType::Vertices, vertices_bytes),
+            (BackingBufferType::Indices, indices_bytes),
+            (BackingBufferType::Metadata, layer_metadata_bytes),
+            (BackingBufferType::FeatureMetadata, feature_metadata_bytes),
+        ];
+
+        for (typ, bytes) in requests {
+            self.make_room(device, queue, typ, bytes)?;
+        }
+
         let maybe_entry = IndexEntry {
             coords,
             style_layer,
-            buffer_vertices: self.vertices.make_room(vertices_bytes, &mut self.index),
-            buffer_indices: self.indices.make_room(indices_bytes, &mut self.index),
+            buffer_vertices: self.vertices.allocate(vertices_bytes),
+            buffer_indices: self.indices.allocate(indices_bytes),
             usable_indices: geometry.usable_indices as u32,
-            buffer_layer_metadata: self
-                .layer_metadata
-                .make_room(layer_metadata_bytes, &mut self.index),
-            buffer_feature_metadata: self
-                .feature_metadata
-                .make_room(feature_metadata_bytes, &mut self.index),
+            buffer_layer_metadata: self.layer_metadata.allocate(layer_metadata_bytes),
+            buffer_feature_metadata: self.feature_metadata.allocate(feature_metadata_bytes),
         };
 
         // write_buffer() is the preferred method for WASM: https://toji.github.io/webgpu-best-practices/buffer-uploads.html#when-in-doubt-writebuffer
@@ -227,7 +355,139 @@ impl<Q: Queue<B>, B, V: bytemuck::Pod, I: bytemuck::Pod, TM: bytemuck::Pod, FM:
             &bytemuck::cast_slice(feature_metadata)[0..aligned_feature_metadata_bytes as usize],
         );
 
+        // Tiles which have just been loaded are treated as visible
+        if let Some(key) = coords.build_quad_key() {
+            self.last_visible.entry(key).or_insert(self.frame);
+        }
+
         self.index.push_back(maybe_entry);
+
+        Ok(())
+    }
+
+    /// Ensures that the backing buffer of type `typ` has a contiguous block of at least `bytes`.
+    fn make_room<D: Device<Q, B>>(
+        &mut self,
+        device: &D,
+        queue: &Q,
+        typ: BackingBufferType,
+        bytes: wgpu::BufferAddress,
+    ) -> Result<(), AllocationError> {
+        let bytes = align_to_copy(bytes);
+
+        if bytes > self.backing_buffer(typ).max_size {
+            return Err(AllocationError::TooLarge { typ, bytes });
+        }
+
+        loop {
+            let buffer = self.backing_buffer(typ);
+            let free_list = &buffer.free_list;
+
+            if free_list.largest_free_block() >= bytes {
+                return Ok(());
+            }
+
+            let used = buffer.inner_size - free_list.free_bytes();
+
+            if free_list.free_bytes() >= bytes {
+                // Enough space is available, but it is fragmented
+                let size = buffer.inner_size;
+                self.reallocate(device, queue, typ, size);
+                self.compactions += 1;
+            } else if buffer.max_size - used >= bytes {
+                let size = cmp::min(
+                    buffer.max_size,
+                    cmp::max(buffer.inner_size * 2, used + bytes),
+                );
+                self.reallocate(device, queue, typ, size);
+                self.growths += 1;
+            } else if !self.evict_least_recently_visible() {
+                return Err(AllocationError::OutOfMemory { typ });
+            }
+        }
+    }
+
+    /// Moves all allocations of a backing buffer to the start of a new buffer with `size` bytes.
+    fn reallocate<D: Device<Q, B>>(
+        &mut self,
+        device: &D,
+        queue: &Q,
+        typ: BackingBufferType,
+        size: wgpu::BufferAddress,
+    ) {
+        let mut entries = self
+            .index
+            .tree_index
+            .values_mut()
+            .flat_map(|entries| entries.iter_mut())
+            .map(|entry| entry.range_mut(typ))
+            .filter(|range| range.end > range.start)
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|range| range.start);
+
+        let mut copies = Vec::with_capacity(entries.len());
+        let mut offset = 0;
+        for range in entries {
+            let bytes = range.end - range.start;
+            copies.push(BufferCopy {
+                source: range.start..range.start + align_to_copy(bytes),
+                destination: offset,
+            });
+            *range = offset..offset + bytes;
+            offset += align_to_copy(bytes);
+        }
+
+        let buffer = self.backing_buffer_mut(typ);
+        let new_inner = device.create_backing_buffer(typ, size);
+        device.copy_buffer(queue, &buffer.inner, &new_inner, &copies);
+
+        buffer.inner = new_inner;
+        buffer.inner_size = size;
+        buffer.free_list = FreeList::new(size);
+        buffer.free_list.reserve(0..offset);
+    }
+
+    /// Evicts all layers of the tile which has not been visible for the longest time. Returns
+    /// false if all tiles are visible.
+    fn evict_least_recently_visible(&mut self) -> bool {
+        let frame = self.frame;
+        let last_visible = &self.last_visible;
+        let candidate = self
+            .index
+            .tree_index
+            .keys()
+            .map(|key| (*key, last_visible.get(key).copied().unwrap_or(0)))
+            .filter(|(_, visible)| *visible < frame)
+            .min_by_key(|(_, visible)| *visible);
+
+        let key = match candidate {
+            Some((key, _)) => key,
+            None => return false,
+        };
+
+        self.remove_key(&key);
+        self.evictions += 1;
+
+        true
+    }
+
+    /// Frees all layers of a tile, for example because it has been evicted from the tile cache.
+    pub fn remove_tile(&mut self, coords: &WorldTileCoords) {
+        if let Some(key) = coords.build_quad_key() {
+            self.remove_key(&key);
+        }
+    }
+
+    fn remove_key(&mut self, key: &Quadkey) {
+        if let Some(entries) = self.index.tree_index.remove(key) {
+            for entry in entries {
+                self.vertices.free(&entry.buffer_vertices);
+                self.indices.free(&entry.buffer_indices);
+                self.layer_metadata.free(&entry.buffer_layer_metadata);
+                self.feature_metadata.free(&entry.buffer_feature_metadata);
+            }
+        }
+        self.last_visible.remove(key);
     }
 
     #[tracing::instrument(skip_all)]
@@ -279,9 +539,66 @@ impl<Q: Queue<B>, B, V: bytemuck::Pod, I: bytemuck::Pod, TM: bytemuck::Pod, FM:
         );
     }
 
-    pub fn index(&self) -> &RingIndex {
+    pub fn index(&self) -> &PoolIndex {
         &self.index
     }
+
+    pub fn statistics(&self) -> BufferPoolStatistics {
+        BufferPoolStatistics {
+            vertices: self.vertices.statistics(),
+            indices: self.indices.statistics(),
+            layer_metadata: self.layer_metadata.statistics(),
+            feature_metadata: self.feature_metadata.statistics(),
+            tiles: self.index.tree_index.len(),
+            layers: self
+                .index
+                .tree_index
+                .values()
+                .map(|entries| entries.len())
+                .sum(),
+            evictions: self.evictions,
+            growths: self.growths,
+            compactions: self.compactions,
+        }
+    }
+}
+
+/// Statistics about the allocations in a [`BufferPool`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferPoolStatistics {
+    pub vertices: BackingBufferStatistics,
+    pub indices: BackingBufferStatistics,
+    pub layer_metadata: BackingBufferStatistics,
+    pub feature_metadata: BackingBufferStatistics,
+    /// The number of tiles of which at least one layer is allocated
+    pub tiles: usize,
+    /// The number of allocated layers
+    pub layers: usize,
+    pub evictions: u64,
+    pub growths: u64,
+    pub compactions: u64,
+}
+
+/// Statistics about the space in a single backing buffer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackingBufferStatistics {
+    pub size: wgpu::BufferAddress,
+    pub max_size: wgpu::BufferAddress,
+    pub used: wgpu::BufferAddress,
+    pub free: wgpu::BufferAddress,
+    pub largest_free_block: wgpu::BufferAddress,
+}
+
+impl BackingBufferStatistics {
+    /// The share of free space which is not part of the largest free block. A value of 0 means
+    /// that the free space is not fragmented.
+    pub fn fragmentation(&self) -> f64 {
+        if self.free == 0 {
+            0.0
+        } else {
+            1.0 - self.largest_free_block as f64 / self.free as f64
+        }
+    }
 }
 
 pub struct BackingBufferDescriptor<B> {
@@ -289,12 +606,31 @@ pub struct BackingBufferDescriptor<B> {
     pub(crate) buffer: B,
     /// The size of buffer
     pub(crate) inner_size: wgpu::BufferAddress,
+    /// The size up to which the buffer can grow
+    pub(crate) max_size: wgpu::BufferAddress,
 }
 
 impl<B> BackingBufferDescriptor<B> {
     pub fn new(buffer: B, inner_size: wgpu::BufferAddress) -> Self {
-        Self { buffer, inner_size }
+        Self {
+            buffer,
+            inner_size,
+            max_size: inner_size,
+        }
     }
+
+    /// Allows the buffer to grow up to `max_size`. By default buffers do not grow.
+    pub fn with_max_size(mut self, max_size: wgpu::BufferAddress) -> Self {
+        self.max_size = cmp::max(max_size, self.inner_size);
+        self
+    }
+}
+
+/// Rounds `bytes` up to a multiple of [`wgpu::COPY_BUFFER_ALIGNMENT`]. Allocations are aligned
+/// such that they can be written and copied.
+fn align_to_copy(bytes: wgpu::BufferAddress) -> wgpu::BufferAddress {
+    let align = wgpu::COPY_BUFFER_ALIGNMENT;
+    bytes + (align - bytes % align) % align
 }
 
 #[derive(Debug)]
@@ -303,80 +639,146 @@ struct BackingBuffer<B> {
     inner: B,
     /// The size of the `inner` buffer
     inner_size: wgpu::BufferAddress,
+    /// The size up to which the `inner` buffer can grow
+    max_size: wgpu::BufferAddress,
+    free_list: FreeList,
     typ: BackingBufferType,
 }
 
 impl<B> BackingBuffer<B> {
-    fn new(inner: B, inner_size: wgpu::BufferAddress, typ: BackingBufferType) -> Self {
+    fn new(descriptor: BackingBufferDescriptor<B>, typ: BackingBufferType) -> Self {
         Self {
-            inner,
-            inner_size,
+            inner: descriptor.buffer,
+            inner_size: descriptor.inner_size,
+            max_size: descriptor.max_size,
+            free_list: FreeList::new(descriptor.inner_size),
             typ,
         }
     }
 
-    fn make_room(
-        &mut self,
-        new_data: wgpu::BufferAddress,
-        index: &mut RingIndex,
-    ) -> Range<wgpu::BufferAddress> {
-        if new_data > self.inner_size {
-            panic!("can not allocate because backing buffers are too small")
+    /// Allocates `bytes`. Room has to be made before.
+    fn allocate(&mut self, bytes: wgpu::BufferAddress) -> Range<wgpu::BufferAddress> {
+        let block = self
+            .free_list
+            .allocate(align_to_copy(bytes))
+            .unwrap_or_else(|| panic!("no room made in the {:?} buffer", self.typ));
+        block.start..block.start + bytes
+    }
+
+    fn free(&mut self, range: &Range<wgpu::BufferAddress>) {
+        self.free_list
+            .free(range.start..range.start + align_to_copy(range.end - range.start));
+    }
+
+    fn statistics(&self) -> BackingBufferStatistics {
+        BackingBufferStatistics {
+            size: self.inner_size,
+            max_size: self.max_size,
+            used: self.inner_size - self.free_list.free_bytes(),
+            free: self.free_list.free_bytes(),
+            largest_free_block: self.free_list.largest_free_block(),
         }
+    }
+}
 
-        let mut available_gap = self.find_largest_gap(index);
+/// Keeps track of the free blocks within a buffer. Adjacent free blocks are merged.
+#[derive(Debug)]
+struct FreeList {
+    /// Maps the start of each free block to its end
+    blocks: BTreeMap<wgpu::BufferAddress, wgpu::BufferAddress>,
+}
 
-        while new_data > available_gap.end - available_gap.start {
-            // no more space, we need to evict items
-            if index.pop_front().is_some() {
-                available_gap = self.find_largest_gap(index);
-            } else {
-                panic!("evicted even though index is empty")
-            }
+impl FreeList {
+    fn new(size: wgpu::BufferAddress) -> Self {
+        let mut blocks = BTreeMap::new();
+        if size > 0 {
+            blocks.insert(0, size);
         }
+        Self { blocks }
+    }
 
-        available_gap.start..available_gap.start + new_data
+    fn free_bytes(&self) -> wgpu::BufferAddress {
+        self.blocks.iter().map(|(start, end)| end - start).sum()
     }
 
-    fn find_largest_gap(&self, index: &RingIndex) -> Range<wgpu::BufferAddress> {
-        let start = index.front().map(|first| match self.typ {
-            BackingBufferType::Vertices => first.buffer_vertices.start,
-            BackingBufferType::Indices => first.buffer_indices.start,
-            BackingBufferType::Metadata => first.buffer_layer_metadata.start,
-            BackingBufferType::FeatureMetadata => first.buffer_feature_metadata.start,
-        });
-        let end = index.back().map(|first| match self.typ {
-            BackingBufferType::Vertices => first.buffer_vertices.end,
-            BackingBufferType::Indices => first.buffer_indices.end,
-            BackingBufferType::Metadata => first.buffer_layer_metadata.end,
-            BackingBufferType::FeatureMetadata => first.buffer_feature_metadata.end,
-        });
+    fn largest_free_block(&self) -> wgpu::BufferAddress {
+        self.blocks
+            .iter()
+            .map(|(start, end)| end - start)
+            .max()
+            .unwrap_or(0)
+    }
 
-        if let Some(start) = start {
-            if let Some(end) = end {
-                if end > start {
-                    // we haven't wrapped yet in the ring buffer
-
-                    let gap_from_start = 0..start; // gap from beginning to first entry
-                    let gap_to_end = end..self.inner_size;
-
-                    if gap_to_end.end - gap_to_end.start > gap_from_start.end - gap_from_start.start
-                    {
-                        gap_to_end
-                    } else {
-                        gap_from_start
-                    }
-                } else {
-                    // we already wrapped in the ring buffer
-                    // we choose the gab between the two
-                    end..start
-                }
-            } else {
-                unreachable!()
+    /// Allocates from the smallest free block which is large enough.
+    fn allocate(&mut self, bytes: wgpu::BufferAddress) -> Option<Range<wgpu::BufferAddress>> {
+        if bytes == 0 {
+            return Some(0..0);
+        }
+
+        let (start, end) = self
+            .blocks
+            .iter()
+            .filter(|(start, end)| *end - *start >= bytes)
+            .min_by_key(|(start, end)| *end - *start)
+            .map(|(start, end)| (*start, *end))?;
+
+        self.blocks.remove(&start);
+        if start + bytes < end {
+            self.blocks.insert(start + bytes, end);
+        }
+
+        Some(start..start + bytes)
+    }
+
+    /// Removes `range` from the free blocks. The range must be part of a single free block.
+    fn reserve(&mut self, range: Range<wgpu::BufferAddress>) {
+        if range.end <= range.start {
+            return;
+        }
+
+        let (start, end) = self
+            .blocks
+            .range(..=range.start)
+            .next_back()
+            .map(|(start, end)| (*start, *end))
+            .filter(|(_, end)| *end >= range.end)
+            .expect("reserved range is not free");
+
+        self.blocks.remove(&start);
+        if start < range.start {
+            self.blocks.insert(start, range.start);
+        }
+        if range.end < end {
+            self.blocks.insert(range.end, end);
+        }
+    }
+
+    fn free(&mut self, range: Range<wgpu::BufferAddress>) {
+        if range.end <= range.start {
+            return;
+        }
+
+        let mut start = range.start;
+        let mut end = range.end;
+
+        if let Some((previous_start, previous_end)) = self
+            .blocks
+            .range(..start)
+            .next_back()
+            .map(|(start, end)| (*start, *end))
+        {
+            if previous_end == start {
+                self.blocks.remove(&previous_start);
+                start = previous_start;
             }
-        } else {
-            0..self.inner_size
         }
+
+        if let Some(next_end) = self.blocks.get(&end).copied() {
+            self.blocks.remove(&end);
+            end = next_end;
+        }
+
+        self.blocks.insert(start, end);
     }
 }
 
@@ -417,34 +819,30 @@ impl IndexEntry {
     pub fn feature_metadata_buffer_range(&self) -> Range<wgpu::BufferAddress> {
         self.buffer_feature_metadata.clone()
     }
+
+    fn range_mut(&mut self, typ: BackingBufferType) -> &mut Range<wgpu::BufferAddress> {
+        match typ {
+            BackingBufferType::Vertices => &mut self.buffer_vertices,
+            BackingBufferType::Indices => &mut self.buffer_indices,
+            BackingBufferType::Metadata => &mut self.buffer_layer_metadata,
+            BackingBufferType::FeatureMetadata => &mut self.buffer_feature_metadata,
+        }
+    }
 }
 
+/// Stores the allocated layers of each tile.
 #[derive(Debug)]
-pub struct RingIndex {
+pub struct PoolIndex {
     tree_index: BTreeMap<Quadkey, VecDeque<IndexEntry>>,
-    linear_index: VecDeque<Quadkey>,
 }
 
-impl RingIndex {
+impl PoolIndex {
     pub fn new() -> Self {
         Self {
             tree_index: Default::default(),
-            linear_index: Default::default(),
         }
     }
 
-    pub fn front(&self) -> Option<&IndexEntry> {
-        self.linear_index
-            .front()
-            .and_then(|key| self.tree_index.get(key).and_then(|entries| entries.front()))
-    }
-
-    pub fn back(&self) -> Option<&IndexEntry> {
-        self.linear_index
-            .back()
-            .and_then(|key| self.tree_index.get(key).and_then(|entries| entries.back()))
-    }
-
     pub fn get_layers(&self, coords: &WorldTileCoords) -> Option<&VecDeque<IndexEntry>> {
         coords
             .build_quad_key()
@@ -471,35 +869,8 @@ impl RingIndex {
             .is_some()
     }
 
-    pub fn get_tile_coords_fallback(&self, coords: &WorldTileCoords) -> Option<WorldTileCoords> {
-        let mut current = *coords;
-        loop {
-            if self.has_tile(&current) {
-                return Some(current);
-            } else if let Some(parent) = current.get_parent() {
-                current = parent
-            } else {
-                return None;
-            }
-        }
-    }
-
     pub fn iter(&self) -> impl Iterator<Item = impl Iterator<Item = &IndexEntry>> + '_ {
-        self.linear_index
-            .iter()
-            .flat_map(|key| self.tree_index.get(key).map(|entries| entries.iter()))
-    }
-
-    fn pop_front(&mut self) -> Option<IndexEntry> {
-        if let Some(entries) = self
-            .linear_index
-            .pop_front()
-            .and_then(|key| self.tree_index.get_mut(&key))
-        {
-            entries.pop_front()
-        } else {
-            None
-        }
+        self.tree_index.values().map(|entries| entries.iter())
     }
 
     fn push_back(&mut self, entry: IndexEntry) {
@@ -512,8 +883,6 @@ impl RingIndex {
                     index_entry.get_mut().push_back(entry);
                 }
             }
-
-            self.linear_index.push_back(key)
         } else {
             unreachable!() // TODO handle
         }
@@ -522,12 +891,16 @@ impl RingIndex {
 
 #[cfg(test)]
 mod tests {
+    use crate::coords::WorldTileCoords;
     use crate::style::layer::StyleLayer;
+    use crate::tessellation::OverAlignedVertexBuffer;
     use lyon::tessellation::VertexBuffers;
+    use std::cell::Cell;
     use wgpu::BufferAddress;
 
     use crate::render::buffer_pool::{
-        BackingBufferDescriptor, BackingBufferType, BufferPool, Queue,
+        AllocationError, BackingBufferDescriptor, BackingBufferType, BufferCopy, BufferPool,
+        Device, Queue,
     };
 
     #[derive(Debug)]
@@ -544,115 +917,175 @@ mod tests {
         }
     }
 
+    #[derive(Default)]
+    struct TestDevice {
+        copies: Cell<usize>,
+    }
+
+    impl Device<TestQueue, TestBuffer> for TestDevice {
+        fn create_backing_buffer(
+            &self,
+            _typ: BackingBufferType,
+            size: BufferAddress,
+        ) -> TestBuffer {
+            TestBuffer { size }
+        }
+
+        fn copy_buffer(
+            &self,
+            _queue: &TestQueue,
+            source: &TestBuffer,
+            destination: &TestBuffer,
+            copies: &[BufferCopy],
+        ) {
+            for copy in copies {
+                let bytes = copy.source.end - copy.source.start;
+                if copy.source.end > source.size || copy.destination + bytes > destination.size {
+                    panic!("copy out of bounds");
+                }
+            }
+            self.copies.set(self.copies.get() + 1);
+        }
+    }
+
     #[repr(C)]
     #[derive(Default, Copy, Clone, bytemuck_derive::Pod, bytemuck_derive::Zeroable)]
     struct TestVertex {
         data: [u8; 24],
     }
 
-    fn create_48byte() -> Vec<TestVertex> {
-        vec![TestVertex::default(), TestVertex::default()]
+    type TestPool = BufferPool<TestQueue, TestBuffer, TestVertex, u32, u32, u32>;
+
+    fn create_pool(size: BufferAddress, max_size: BufferAddress) -> TestPool {
+        BufferPool::new(
+            BackingBufferDescriptor::new(TestBuffer { size }, size).with_max_size(max_size),
+            BackingBufferDescriptor::new(TestBuffer { size: 128 }, 128),
+            BackingBufferDescriptor::new(TestBuffer { size: 128 }, 128),
+            BackingBufferDescriptor::new(TestBuffer { size: 128 }, 128),
+        )
     }
 
-    fn create_24byte() -> Vec<TestVertex> {
-        vec![TestVertex::default()]
+    fn create_geometry(vertices: usize) -> OverAlignedVertexBuffer<TestVertex, u32> {
+        let mut buffer = VertexBuffers::new();
+        buffer
+            .vertices
+            .append(&mut vec![TestVertex::default(); vertices]);
+        buffer.indices.append(&mut vec![1, 2, 3, 4]);
+        buffer.into()
+    }
+
+    fn allocate(
+        pool: &mut TestPool,
+        device: &TestDevice,
+        coords: WorldTileCoords,
+        vertices: usize,
+    ) -> Result<(), AllocationError> {
+        pool.allocate_layer_geometry(
+            device,
+            &TestQueue {},
+            coords,
+            StyleLayer::default(),
+            &create_geometry(vertices),
+            2,
+            &[],
+        )
     }
 
     #[test]
     fn test_allocate() {
-        let mut pool: BufferPool<TestQueue, TestBuffer, TestVertex, u32, u32, u32> =
-            BufferPool::new(
-                BackingBufferDescriptor::new(TestBuffer { size: 128 }, 128),
-                BackingBufferDescriptor::new(TestBuffer { size: 128 }, 128),
-                BackingBufferDescriptor::new(TestBuffer { size: 128 }, 128),
-                BackingBufferDescriptor::new(TestBuffer { size: 128 }, 128),
-            );
-
-        let queue = TestQueue {};
-        let style_layer = StyleLayer::default();
-
-        let mut data48bytes = VertexBuffers::new();
-        data48bytes.vertices.append(&mut create_48byte());
-        data48bytes.indices.append(&mut vec![1, 2, 3, 4]);
-        let data48bytes_aligned = data48bytes.into();
-
-        let mut data24bytes = VertexBuffers::new();
-        data24bytes.vertices.append(&mut create_24byte());
-        data24bytes.indices.append(&mut vec![1, 2, 3, 4]);
-        let data24bytes_aligned = data24bytes.into();
+        let mut pool = create_pool(128, 128);
+        let device = TestDevice::default();
 
         for _ in 0..2 {
-            pool.allocate_layer_geometry(
-                &queue,
-                (0, 0, 0).into(),
-                style_layer.clone(),
-                &data48bytes_aligned,
-                2,
-                &[],
-            );
+            allocate(&mut pool, &device, (0, 0, 0).into(), 2).unwrap();
         }
         assert_eq!(
             128 - 2 * 48,
             pool.available_space(BackingBufferType::Vertices)
         );
 
-        pool.allocate_layer_geometry(
-            &queue,
-            (0, 0, 0).into(),
-            style_layer.clone(),
-            &data24bytes_aligned,
-            2,
-            &[],
-        );
+        allocate(&mut pool, &device, (0, 0, 0).into(), 1).unwrap();
         assert_eq!(
             128 - 2 * 48 - 24,
             pool.available_space(BackingBufferType::Vertices)
         );
-        println!("{:?}", &pool.index);
 
-        pool.allocate_layer_geometry(
-            &queue,
-            (0, 0, 0).into(),
-            style_layer.clone(),
-            &data24bytes_aligned,
-            2,
-            &[],
-        );
-        // appended now at the beginning
-        println!("{:?}", &pool.index);
-        assert_eq!(24, pool.available_space(BackingBufferType::Vertices));
+        let statistics = pool.statistics();
+        assert_eq!(statistics.vertices.used, 2 * 48 + 24);
+        assert_eq!(statistics.tiles, 1);
+        assert_eq!(statistics.layers, 3);
 
-        pool.allocate_layer_geometry(
-            &queue,
-            (0, 0, 0).into(),
-            style_layer.clone(),
-            &data24bytes_aligned,
-            2,
-            &[],
+        // The tile is visible, so it can not be evicted
+        assert_eq!(
+            allocate(&mut pool, &device, (1, 0, 1).into(), 1),
+            Err(AllocationError::OutOfMemory {
+                typ: BackingBufferType::Vertices
+            })
         );
-        println!("{:?}", &pool.index);
-        assert_eq!(0, pool.available_space(BackingBufferType::Vertices));
 
-        pool.allocate_layer_geometry(
-            &queue,
-            (0, 0, 0).into(),
-            style_layer.clone(),
-            &data24bytes_aligned,
-            2,
-            &[],
+        assert_eq!(
+            allocate(&mut pool, &device, (1, 0, 1).into(), 6),
+            Err(AllocationError::TooLarge {
+                typ: BackingBufferType::Vertices,
+                bytes: 144
+            })
         );
-        println!("{:?}", &pool.index);
-        assert_eq!(24, pool.available_space(BackingBufferType::Vertices));
+    }
 
-        pool.allocate_layer_geometry(
-            &queue,
-            (0, 0, 0).into(),
-            style_layer,
-            &data24bytes_aligned,
-            2,
-            &[],
-        );
-        println!("{:?}", &pool.index);
-        assert_eq!(0, pool.available_space(BackingBufferType::Vertices));
+    #[test]
+    fn test_evict_least_recently_visible() {
+        let mut pool = create_pool(96, 96);
+        let device = TestDevice::default();
+
+        let first: WorldTileCoords = (0, 0, 1).into();
+        let second: WorldTileCoords = (1, 0, 1).into();
+        let third: WorldTileCoords = (0, 1, 1).into();
+
+        allocate(&mut pool, &device, first, 2).unwrap();
+        pool.mark_visible([first].iter());
+        allocate(&mut pool, &device, second, 2).unwrap();
+        pool.mark_visible([second].iter());
+        pool.mark_visible([first].iter());
+
+        // The second tile has not been visible for the longest time
+        allocate(&mut pool, &device, third, 2).unwrap();
+        assert!(pool.index().has_tile(&first));
+        assert!(!pool.index().has_tile(&second));
+        assert!(pool.index().has_tile(&third));
+        assert_eq!(pool.statistics().evictions, 1);
+    }
+
+    #[test]
+    fn test_grow_and_compact() {
+        let mut pool = create_pool(96, 192);
+        let device = TestDevice::default();
+
+        let first: WorldTileCoords = (0, 0, 1).into();
+        let second: WorldTileCoords = (1, 0, 1).into();
+        let third: WorldTileCoords = (0, 1, 1).into();
+
+        allocate(&mut pool, &device, first, 1).unwrap();
+        allocate(&mut pool, &device, second, 2).unwrap();
+        allocate(&mut pool, &device, third, 2).unwrap();
+
+        // The pool grew instead of evicting tiles
+        let statistics = pool.statistics();
+        assert_eq!(statistics.growths, 1);
+        assert_eq!(statistics.evictions, 0);
+        assert_eq!(statistics.vertices.size, 192);
+        assert_eq!(statistics.vertices.used, 120);
+
+        // Evicting the second tile leaves a gap between the first and the third tile
+        pool.mark_visible([first, third].iter());
+        allocate(&mut pool, &device, (1, 1, 1).into(), 4).unwrap();
+        assert!(!pool.index().has_tile(&second));
+
+        let statistics = pool.statistics();
+        assert_eq!(statistics.evictions, 1);
+        assert_eq!(statistics.compactions, 1);
+        assert_eq!(statistics.vertices.used, 168);
+        assert_eq!(statistics.vertices.free, 24);
+        assert_eq!(statistics.vertices.fragmentation(), 0.0);
+        assert_eq!(device.copies.get(), 2);
     }
 }
@@ -63,6 +63,9 @@ impl ModelViewProjection {
     }
 }
 
+/// The yaw at which the camera faces north.
+const NORTH_YAW: cgmath::Rad<f64> = cgmath::Rad(-std::f64::consts::FRAC_PI_2);
+
 #[derive(Debug, Clone)]
 pub struct Camera {
     pub position: cgmath::Point3<f64>,
@@ -109,11 +112,24 @@ impl Camera {
         self.height = height as f64;
     }
 
+    /// The bearing of the camera. A bearing of zero means that north is up. The bearing increases
+    /// clockwise.
+    pub fn bearing(&self) -> cgmath::Rad<f64> {
+        (self.yaw - NORTH_YAW).normalize()
+    }
+
+    pub fn set_bearing<B: Into<cgmath::Rad<f64>>>(&mut self, bearing: B) {
+        self.yaw = NORTH_YAW + bearing.into().normalize();
+    }
+
     fn calc_matrix(&self) -> cgmath::Matrix4<f64> {
+        // The camera looks straight down at the `z=0` plane if the pitch is zero. A positive pitch
+        // tilts the camera towards the top of the screen such that the horizon becomes visible.
+        let rotation = cgmath::Matrix3::from_angle_z(self.bearing());
         cgmath::Matrix4::look_to_rh(
             self.position,
-            cgmath::Vector3::new(self.yaw.cos(), self.pitch.sin(), self.yaw.sin()).normalize(),
-            cgmath::Vector3::unit_y(),
+            rotation * cgmath::Vector3::new(0.0, -self.pitch.sin(), -self.pitch.cos()),
+            rotation * cgmath::Vector3::unit_y(),
         )
     }
 
@@ -186,6 +202,21 @@ impl Camera {
         Vector3::new(px / 2.0 * xd + ox, py / 2.0 * yd + oy, pz * zd + oz)
     }
 
+    /// Projects `world` coordinates to window coordinates. The `z` component of the result is the
+    /// depth within `[0, 1]` if the point is between the near and the far plane.
+    /// Returns `None` if the point is behind the camera.
+    pub fn world_to_window(
+        &self,
+        world: &Vector3<f64>,
+        view_proj: &ViewProjection,
+    ) -> Option<Vector3<f64>> {
+        let clip = view_proj.project(world.extend(1.0));
+        if clip.w <= 0.0 {
+            return None;
+        }
+        Some(self.clip_to_window(&clip).truncate())
+    }
+
     /// Order of transformations reversed: https://computergraphics.stackexchange.com/questions/6087/screen-space-coordinates-to-eye-space-conversion/6093
     /// `w` is lost.
     ///
@@ -259,6 +290,31 @@ impl Camera {
         }
     }
 
+    /// Calculates the polygon which is formed by the intersection between the viewing frustum and
+    /// the `z=0` plane. The vertices are the intersections of the rays through the corners of the
+    /// window, in the order top-left, top-right, bottom-right, bottom-left. Unlike an axis-aligned
+    /// bounding box, the polygon describes the visible area tightly if the camera is rotated.
+    ///
+    /// *Note:* Returns `None` if a corner of the window does not intersect the `z=0` plane, for
+    /// example because the horizon is visible.
+    pub fn view_region_polygon(
+        &self,
+        inverted_view_proj: &InvertedViewProjection,
+    ) -> Option<Vec<Point2<f64>>> {
+        [
+            Vector2::new(0.0, 0.0),
+            Vector2::new(self.width, 0.0),
+            Vector2::new(self.width, self.height),
+            Vector2::new(0.0, self.height),
+        ]
+        .iter()
+        .map(|point| {
+            self.window_to_world_at_ground(point, inverted_view_proj)
+                .map(|world| Point2::new(world.x, world.y))
+        })
+        .collect()
+    }
+
     /// Calculates an [`Aabb2`] bounding box which contains at least the visible area on the `z=0`
     /// plane. One can think of it as being the bounding box of the geometry which forms the
     /// intersection between the viewing frustum and the `z=0` plane.
@@ -290,6 +346,62 @@ impl Camera {
 
         Some(Aabb2::new(Point2::from(min), Point2::from(max)))
     }
+
+    /// Calculates the polygon which is formed by the intersection between the `z=0` plane and the
+    /// part of the viewing frustum which is seen through the `window` area. Unlike
+    /// [`Camera::view_region_bounding_box`], the frustum is clipped by the near and the far plane,
+    /// therefore the polygon also exists if the horizon is visible or if the near plane cuts off
+    /// the ground at the bottom of the window. The polygon is convex and its vertices are sorted
+    /// by their angle around the centroid.
+    ///
+    /// *Note:* Returns `None` if the `z=0` plane is not visible within the `window` area.
+    pub fn view_region_within(
+        &self,
+        window: &Aabb2<f64>,
+        inverted_view_proj: &InvertedViewProjection,
+    ) -> Option<Vec<Point2<f64>>> {
+        // The corners of the clipped frustum on the near and on the far plane
+        let corners = [
+            Vector2::new(window.min.x, window.min.y),
+            Vector2::new(window.max.x, window.min.y),
+            Vector2::new(window.max.x, window.max.y),
+            Vector2::new(window.min.x, window.max.y),
+        ]
+        .map(|corner| {
+            [0.0, 1.0].map(|depth| self.window_to_world(&corner.extend(depth), inverted_view_proj))
+        });
+
+        let mut edges = Vec::with_capacity(12);
+        for i in 0..4 {
+            let next = (i + 1) % 4;
+            edges.push((corners[i][0], corners[next][0]));
+            edges.push((corners[i][1], corners[next][1]));
+            edges.push((corners[i][0], corners[i][1]));
+        }
+
+        let mut polygon: Vec<Point2<f64>> = edges
+            .iter()
+            .filter(|(a, b)| (a.z <= 0.0) != (b.z <= 0.0))
+            .map(|(a, b)| {
+                let u = -a.z / (b.z - a.z);
+                let point = a + u * (b - a);
+                Point2::new(point.x, point.y)
+            })
+            .collect();
+        if polygon.len() < 3 {
+            return None;
+        }
+
+        let centroid = Point2::centroid(&polygon);
+        polygon.sort_by(|a, b| {
+            let angle = |point: &Point2<f64>| (point.y - centroid.y).atan2(point.x - centroid.x);
+            angle(a)
+                .partial_cmp(&angle(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        Some(polygon)
+    }
+
     /// An alternative implementation for `view_bounding_box`.
     ///
     /// This implementation works in the NDC space. We are creating a plane in the world 3D space.
@@ -349,6 +461,7 @@ impl Camera {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct Perspective {
     fovy: cgmath::Rad<f64>,
     znear: f64,
@@ -2,7 +2,6 @@
 //! communication with the GPU.
 
 mod buffer_pool;
-mod options;
 mod piplines;
 mod shaders;
 mod texture;
@@ -10,6 +9,9 @@ mod tile_view_pattern;
 
 pub mod camera;
 pub mod render_state;
+pub mod settings;
 
 // These are created during tessellation and must be public
 pub use shaders::ShaderVertex;
+
+pub use buffer_pool::{BackingBufferStatistics, BufferPoolStatistics};
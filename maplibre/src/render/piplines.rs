@@ -1,4 +1,4 @@
-use crate::render::options::{DEBUG_STENCIL_PATTERN, DEBUG_WIREFRAME};
+use crate::render::settings::RenderSettings;
 use wgpu::{FragmentState, PipelineLayout, RenderPipelineDescriptor, VertexState};
 
 use super::texture::DEPTH_TEXTURE_FORMAT;
@@ -10,14 +10,15 @@ use super::texture::DEPTH_TEXTURE_FORMAT;
 ///
 /// * `update_stencil`: Fragments passing through the pipeline will be able to update the stencil
 ///                     buffer. This is used for masking
+/// * `settings`: Configures multisampling and the debug modes
 ///
 /// returns: RenderPipelineDescriptor
 pub fn create_map_render_pipeline_description<'a>(
     pipeline_layout: &'a PipelineLayout,
     vertex_state: VertexState<'a>,
     fragment_state: FragmentState<'a>,
-    sample_count: u32,
     update_stencil: bool,
+    settings: &RenderSettings,
 ) -> RenderPipelineDescriptor<'a> {
     let stencil_state = if update_stencil {
         wgpu::StencilFaceState {
@@ -28,7 +29,7 @@ pub fn create_map_render_pipeline_description<'a>(
         }
     } else {
         wgpu::StencilFaceState {
-            compare: if DEBUG_STENCIL_PATTERN {
+            compare: if settings.debug_stencil_pattern {
                 wgpu::CompareFunction::Always
             } else {
                 wgpu::CompareFunction::Equal
@@ -48,7 +49,7 @@ pub fn create_map_render_pipeline_description<'a>(
             topology: wgpu::PrimitiveTopology::TriangleList,
             polygon_mode: if update_stencil {
                 wgpu::PolygonMode::Fill
-            } else if DEBUG_WIREFRAME {
+            } else if settings.debug_wireframe {
                 wgpu::PolygonMode::Line
             } else {
                 wgpu::PolygonMode::Fill
@@ -72,7 +73,7 @@ pub fn create_map_render_pipeline_description<'a>(
             bias: wgpu::DepthBiasState::default(),
         }),
         multisample: wgpu::MultisampleState {
-            count: sample_count,
+            count: settings.msaa.samples,
             mask: !0,
             alpha_to_coverage_enabled: false,
         },
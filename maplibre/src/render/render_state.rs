@@ -1,48 +1,146 @@
 use std::default::Default;
 
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::time::Duration;
 use std::{cmp, iter};
 
+use instant::Instant;
 use tracing;
 use wgpu::{Buffer, Limits, Queue};
 
 use crate::style::Style;
 
-use crate::coords::{ViewRegion, Zoom};
+use crate::coords::{ViewRegion, WorldTileCoords, Zoom};
+use crate::error::{Error, RenderError};
+use crate::headless::RgbaImage;
 
 use crate::io::tile_cache::TileCache;
 use crate::io::LayerTessellateMessage;
 use crate::platform::MIN_BUFFER_SIZE;
-use crate::render::buffer_pool::{BackingBufferDescriptor, BufferPool, IndexEntry};
+use crate::render::buffer_pool::{
+    BackingBufferDescriptor, BackingBufferType, BufferPool, BufferPoolStatistics, Device,
+    IndexEntry,
+};
 
 use crate::render::camera::{Camera, ViewProjection};
-use crate::render::options::{
-    DEBUG_WIREFRAME, FEATURE_METADATA_BUFFER_SIZE, INDEX_FORMAT, INDICES_BUFFER_SIZE,
-    LAYER_METADATA_BUFFER_SIZE, TILE_VIEW_BUFFER_SIZE, VERTEX_BUFFER_SIZE,
-};
-use crate::render::tile_view_pattern::{TileInView, TileViewPattern};
+use crate::render::settings::{RenderSettings, INDEX_FORMAT};
+use crate::render::tile_view_pattern::{TileShape, TileViewPattern};
 use crate::tessellation::IndexDataType;
 use crate::util::FPSMeter;
-use crate::MapWindow;
+use crate::window::HeadedMapWindow;
 
 use super::piplines::*;
 use super::shaders;
 use super::shaders::*;
 use super::texture::Texture;
 
+/// Dimensions of a buffer to which a texture is copied. The rows of the buffer are padded to
+/// [`wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct BufferDimensions {
+    width: u32,
+    height: u32,
+    unpadded_bytes_per_row: u32,
+    padded_bytes_per_row: u32,
+}
+
+impl BufferDimensions {
+    fn new(width: u32, height: u32) -> Self {
+        let bytes_per_pixel = std::mem::size_of::<u32>() as u32;
+        let unpadded_bytes_per_row = width * bytes_per_pixel;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row_padding = (align - unpadded_bytes_per_row % align) % align;
+        let padded_bytes_per_row = unpadded_bytes_per_row + padded_bytes_per_row_padding;
+        Self {
+            width,
+            height,
+            unpadded_bytes_per_row,
+            padded_bytes_per_row,
+        }
+    }
+
+    fn buffer_size(&self) -> wgpu::BufferAddress {
+        self.padded_bytes_per_row as wgpu::BufferAddress * self.height as wgpu::BufferAddress
+    }
+
+    /// Removes the padding of the rows and converts the pixels of the given `format` to RGBA.
+    fn unpad_to_rgba(&self, padded: &[u8], format: wgpu::TextureFormat) -> Vec<u8> {
+        let swap_red_blue = matches!(
+            format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        );
+
+        let mut data = Vec::with_capacity((self.unpadded_bytes_per_row * self.height) as usize);
+        for row in padded.chunks(self.padded_bytes_per_row as usize) {
+            let row = &row[..self.unpadded_bytes_per_row as usize];
+            if swap_red_blue {
+                for pixel in row.chunks(4) {
+                    data.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+                }
+            } else {
+                data.extend_from_slice(row);
+            }
+        }
+        data
+    }
+}
+
+/// An offscreen texture to which frames are rendered when running headless.
+struct HeadlessTarget {
+    texture: Texture,
+    /// The rendered texture is copied to this buffer after each frame such that it can be read.
+    output_buffer: wgpu::Buffer,
+    dimensions: BufferDimensions,
+}
+
+impl HeadlessTarget {
+    fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let dimensions = BufferDimensions::new(config.width, config.height);
+        Self {
+            texture: Texture::create_headless_texture(device, config),
+            output_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Headless output buffer"),
+                size: dimensions.buffer_size(),
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            dimensions,
+        }
+    }
+}
+
+/// The target to which frames are rendered.
+enum RenderTarget {
+    /// The surface of a window. Frames are presented on the window.
+    Surface(wgpu::Surface),
+    Headless(HeadlessTarget),
+    /// Texture views which are supplied by the application which owns the device. The
+    /// application also presents the frames.
+    External,
+}
+
 pub struct RenderState {
-    instance: wgpu::Instance,
+    /// The instance is only known if the device was requested by the render state itself.
+    instance: Option<wgpu::Instance>,
 
-    device: wgpu::Device,
-    queue: wgpu::Queue,
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
 
     fps_meter: FPSMeter,
 
-    surface: wgpu::Surface,
+    render_target: RenderTarget,
+    /// The configuration of the render target. If rendering headless, then this is not applied
+    /// to a surface but only describes the size and format of the offscreen texture.
     surface_config: wgpu::SurfaceConfiguration,
     suspended: bool,
 
     render_pipeline: wgpu::RenderPipeline,
+    /// Blends tiles which fade in over their fallbacks.
+    fade_pipeline: wgpu::RenderPipeline,
     mask_pipeline: wgpu::RenderPipeline,
+    fade_duration: Duration,
+    color_load_op: wgpu::LoadOp<wgpu::Color>,
     bind_group: wgpu::BindGroup,
 
     sample_count: u32,
@@ -69,18 +167,112 @@ impl RenderState {
         instance: wgpu::Instance,
         surface: wgpu::Surface,
         surface_config: wgpu::SurfaceConfiguration,
-    ) -> Option<Self> {
-        let sample_count = 4;
-
+        settings: RenderSettings,
+    ) -> Result<Self, RenderError> {
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::LowPower,
+                power_preference: settings.power_preference,
                 compatible_surface: Some(&surface),
                 force_fallback_adapter: false,
             })
             .await
-            .unwrap();
+            .ok_or(RenderError::NoAdapter)?;
+
+        let (device, queue) = Self::request_device(&adapter, &settings).await?;
+
+        Self::from_device(
+            Some(instance),
+            Arc::new(device),
+            Arc::new(queue),
+            RenderTarget::Surface(surface),
+            surface_config,
+            settings,
+        )
+    }
+
+    /// Initializes the rendering to an offscreen texture of the given size. No window is
+    /// required. The rendered frames can be read with [`RenderState::read_frame`].
+    ///
+    /// If `force_fallback_adapter` is set, then a software adapter is used. This allows rendering
+    /// on machines without a GPU.
+    ///
+    /// Fails if no suitable adapter or device is available.
+    pub async fn initialize_headless(
+        instance: wgpu::Instance,
+        width: u32,
+        height: u32,
+        force_fallback_adapter: bool,
+        settings: RenderSettings,
+    ) -> Result<Self, RenderError> {
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: settings.power_preference,
+                compatible_surface: None,
+                force_fallback_adapter,
+            })
+            .await
+            .ok_or(RenderError::NoAdapter)?;
+
+        let (device, queue) = Self::request_device(&adapter, &settings).await?;
+
+        // There is no surface, therefore the frames are rendered in the format of the images. The
+        // channel order of copied BGRA textures differs between backends.
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: settings.present_mode,
+        };
+
+        let headless_target = HeadlessTarget::new(&device, &surface_config);
+
+        Self::from_device(
+            Some(instance),
+            Arc::new(device),
+            Arc::new(queue),
+            RenderTarget::Headless(headless_target),
+            surface_config,
+            settings,
+        )
+    }
+
+    /// Initializes the rendering with a device and queue which are owned by the application which
+    /// embeds the map. Frames are rendered into texture views of the given `format` and size via
+    /// [`RenderState::render_to`] or [`RenderState::encode_render`]. Presenting the frames is left
+    /// to the application.
+    ///
+    /// Fails if the device does not support the given settings.
+    pub fn initialize_with_device(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        settings: RenderSettings,
+    ) -> Result<Self, RenderError> {
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width,
+            height,
+            present_mode: settings.present_mode,
+        };
 
+        Self::from_device(
+            None,
+            device,
+            queue,
+            RenderTarget::External,
+            surface_config,
+            settings,
+        )
+    }
+
+    async fn request_device(
+        adapter: &wgpu::Adapter,
+        settings: &RenderSettings,
+    ) -> Result<(wgpu::Device, wgpu::Queue), RenderError> {
         let limits = if cfg!(feature = "web-webgl") {
             Limits {
                 max_texture_dimension_2d: 4096,
@@ -103,14 +295,11 @@ impl RenderState {
             }
         };
 
-        // create a device and a queue
-        let features = if DEBUG_WIREFRAME {
-            wgpu::Features::default() | wgpu::Features::POLYGON_MODE_LINE
-        } else {
-            wgpu::Features::default()
-        };
+        // create a device and a queue. Features which the adapter does not support are reported
+        // when validating the settings against the device.
+        let features = settings.required_features() & adapter.features();
 
-        let (device, queue) = adapter
+        adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
@@ -120,34 +309,46 @@ impl RenderState {
                 None,
             )
             .await
-            .ok()?;
+            .map_err(RenderError::RequestDevice)
+    }
 
-        surface.configure(&device, &surface_config);
+    fn from_device(
+        instance: Option<wgpu::Instance>,
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        render_target: RenderTarget,
+        surface_config: wgpu::SurfaceConfiguration,
+        settings: RenderSettings,
+    ) -> Result<Self, RenderError> {
+        settings
+            .validate(
+                device.features(),
+                &device.limits(),
+                surface_config.width,
+                surface_config.height,
+            )
+            .map_err(RenderError::Settings)?;
 
-        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: VERTEX_BUFFER_SIZE,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        if let RenderTarget::Surface(surface) = &render_target {
+            surface.configure(&device, &surface_config);
+        }
 
-        let feature_metadata_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: FEATURE_METADATA_BUFFER_SIZE,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let sample_count = settings.msaa.samples;
+        let budgets = settings.buffer_budgets;
 
-        let indices_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: INDICES_BUFFER_SIZE,
-            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        // The backing buffers of the pool need to be copyable in order to grow
+        let vertex_buffer =
+            device.create_backing_buffer(BackingBufferType::Vertices, budgets.vertices.initial);
+        let feature_metadata_buffer = device.create_backing_buffer(
+            BackingBufferType::FeatureMetadata,
+            budgets.feature_metadata.initial,
+        );
+        let indices_buffer =
+            device.create_backing_buffer(BackingBufferType::Indices, budgets.indices.initial);
 
         let tile_view_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            size: TILE_VIEW_BUFFER_SIZE,
+            size: budgets.tile_view,
             usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -155,14 +356,11 @@ impl RenderState {
         let globals_buffer_byte_size =
             cmp::max(MIN_BUFFER_SIZE, std::mem::size_of::<ShaderGlobals>() as u64);
 
-        let layer_metadata_buffer_size =
-            std::mem::size_of::<ShaderLayerMetadata>() as u64 * LAYER_METADATA_BUFFER_SIZE;
-        let layer_metadata_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Layer Metadata ubo"),
-            size: layer_metadata_buffer_size,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let layer_metadata_stride = std::mem::size_of::<ShaderLayerMetadata>() as u64;
+        let layer_metadata_buffer = device.create_backing_buffer(
+            BackingBufferType::Metadata,
+            layer_metadata_stride * budgets.layer_metadata.initial,
+        );
 
         let globals_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Globals ubo"),
@@ -208,28 +406,50 @@ impl RenderState {
         let render_pipeline_descriptor = create_map_render_pipeline_description(
             &pipeline_layout,
             vertex_shader.create_vertex_state(&device),
-            fragment_shader.create_fragment_state(&device),
-            sample_count,
+            fragment_shader.create_fragment_state(&device, surface_config.format),
             false,
+            &settings,
         );
 
+        let mut fade_vertex_shader = shaders::tile::VERTEX;
+        let mut fade_fragment_shader = shaders::tile::FADE_FRAGMENT;
+
+        let mut fade_pipeline_descriptor = create_map_render_pipeline_description(
+            &pipeline_layout,
+            fade_vertex_shader.create_vertex_state(&device),
+            fade_fragment_shader.create_fragment_state(&device, surface_config.format),
+            false,
+            &settings,
+        );
+        // Tiles which fade in are drawn over the layers of their fallbacks in the order of the
+        // layers, which is why the depth test is disabled
+        if let Some(depth_stencil) = &mut fade_pipeline_descriptor.depth_stencil {
+            depth_stencil.depth_compare = wgpu::CompareFunction::Always;
+            depth_stencil.depth_write_enabled = false;
+        }
+
         let mut vertex_shader = shaders::tile_mask::VERTEX;
-        let mut fragment_shader = shaders::tile_mask::FRAGMENT;
+        let mut fragment_shader = if settings.debug_stencil_pattern {
+            shaders::tile_mask::DEBUG_FRAGMENT
+        } else {
+            shaders::tile_mask::FRAGMENT
+        };
 
         let mask_pipeline_descriptor = create_map_render_pipeline_description(
             &pipeline_layout,
             vertex_shader.create_vertex_state(&device),
-            fragment_shader.create_fragment_state(&device),
-            sample_count,
+            fragment_shader.create_fragment_state(&device, surface_config.format),
             true,
+            &settings,
         );
 
         let render_pipeline = device.create_render_pipeline(&render_pipeline_descriptor);
+        let fade_pipeline = device.create_render_pipeline(&fade_pipeline_descriptor);
         let mask_pipeline = device.create_render_pipeline(&mask_pipeline_descriptor);
 
         let depth_texture = Texture::create_depth_texture(&device, &surface_config, sample_count);
 
-        let multisampling_texture = if sample_count > 1 {
+        let multisampling_texture = if settings.msaa.is_multisampling() {
             Some(Texture::create_multisampling_texture(
                 &device,
                 &surface_config,
@@ -239,14 +459,17 @@ impl RenderState {
             None
         };
 
-        Some(Self {
+        Ok(Self {
             instance,
-            surface,
+            render_target,
             device,
             queue,
             surface_config,
             render_pipeline,
+            fade_pipeline,
             mask_pipeline,
+            fade_duration: settings.fade_duration,
+            color_load_op: settings.color_load_op,
             bind_group,
             multisampling_texture,
             depth_texture,
@@ -255,25 +478,35 @@ impl RenderState {
             fps_meter: FPSMeter::new(),
             suspended: false, // Initially rendering is not suspended
             buffer_pool: BufferPool::new(
-                BackingBufferDescriptor::new(vertex_buffer, VERTEX_BUFFER_SIZE),
-                BackingBufferDescriptor::new(indices_buffer, INDICES_BUFFER_SIZE),
-                BackingBufferDescriptor::new(layer_metadata_buffer, layer_metadata_buffer_size),
-                BackingBufferDescriptor::new(feature_metadata_buffer, FEATURE_METADATA_BUFFER_SIZE),
+                BackingBufferDescriptor::new(vertex_buffer, budgets.vertices.initial)
+                    .with_max_size(budgets.vertices.max),
+                BackingBufferDescriptor::new(indices_buffer, budgets.indices.initial)
+                    .with_max_size(budgets.indices.max),
+                BackingBufferDescriptor::new(
+                    layer_metadata_buffer,
+                    layer_metadata_stride * budgets.layer_metadata.initial,
+                )
+                .with_max_size(layer_metadata_stride * budgets.layer_metadata.max),
+                BackingBufferDescriptor::new(
+                    feature_metadata_buffer,
+                    budgets.feature_metadata.initial,
+                )
+                .with_max_size(budgets.feature_metadata.max),
             ),
             tile_view_pattern: TileViewPattern::new(BackingBufferDescriptor::new(
                 tile_view_buffer,
-                TILE_VIEW_BUFFER_SIZE,
+                budgets.tile_view,
             )),
         })
     }
 
-    pub fn recreate_surface<W: MapWindow>(&mut self, window: &W) {
+    pub fn recreate_surface<W: HeadedMapWindow>(&mut self, window: &W) {
         // We only create a new surface if we are currently suspended. On Android (and probably iOS)
         // the surface gets invalid after the app has been suspended.
-        if self.suspended {
-            let surface = unsafe { self.instance.create_surface(window.inner()) };
+        if let (true, Some(instance)) = (self.suspended, &self.instance) {
+            let surface = unsafe { instance.create_surface(window.inner()) };
             surface.configure(&self.device, &self.surface_config);
-            self.surface = surface;
+            self.render_target = RenderTarget::Surface(surface);
         }
     }
 
@@ -286,7 +519,13 @@ impl RenderState {
         self.surface_config.width = width;
         self.surface_config.height = height;
 
-        self.surface.configure(&self.device, &self.surface_config);
+        match &mut self.render_target {
+            RenderTarget::Surface(surface) => surface.configure(&self.device, &self.surface_config),
+            RenderTarget::Headless(headless_target) => {
+                *headless_target = HeadlessTarget::new(&self.device, &self.surface_config)
+            }
+            RenderTarget::External => {}
+        }
 
         // Re-configure depth buffer
         self.depth_texture =
@@ -390,15 +629,46 @@ impl RenderState {
         }*/
     }
 
+    /// Returns the coordinates of the tiles which are drawn in the current frame, including the
+    /// tiles which are drawn as fallback.
+    pub fn visible_tiles(&self) -> impl Iterator<Item = &WorldTileCoords> + '_ {
+        self.tile_view_pattern.visible_coords()
+    }
+
+    /// Returns true if tiles are fading in over their fallbacks.
+    pub fn is_fading(&self) -> bool {
+        self.tile_view_pattern.is_fading()
+    }
+
+    /// Frees the geometry of a tile which is no longer cached.
+    pub fn evict_tile(&mut self, coords: &WorldTileCoords) {
+        self.buffer_pool.remove_tile(coords);
+    }
+
+    /// Returns statistics about the allocations of tile geometry on the GPU.
+    pub fn buffer_pool_statistics(&self) -> BufferPoolStatistics {
+        self.buffer_pool.statistics()
+    }
+
     #[tracing::instrument(skip_all)]
     pub fn update_tile_view_pattern(
         &mut self,
         view_region: &ViewRegion,
         view_proj: &ViewProjection,
         zoom: Zoom,
+        style: &Style,
+        now: Instant,
     ) {
-        self.tile_view_pattern
-            .update_pattern(view_region, &self.buffer_pool, zoom);
+        self.tile_view_pattern.update_pattern(
+            view_region,
+            &self.buffer_pool,
+            zoom,
+            style,
+            now,
+            self.fade_duration,
+        );
+        self.buffer_pool
+            .mark_visible(self.tile_view_pattern.visible_coords());
         self.tile_view_pattern
             .upload_pattern(&self.queue, view_proj);
     }
@@ -406,23 +676,24 @@ impl RenderState {
     #[tracing::instrument(skip_all)]
     pub fn upload_tile_geometry(
         &mut self,
-        view_region: &ViewRegion,
+        tiles: &[WorldTileCoords],
         style: &Style,
         tile_cache: &TileCache,
     ) {
-        // Upload all tessellated layers which are in view
-        for world_coords in view_region.iter() {
+        // Upload all tessellated layers of the tiles which are in view or are their fallbacks
+        for world_coords in tiles {
             let loaded_layers = self
                 .buffer_pool
-                .get_loaded_layers_at(&world_coords)
+                .get_loaded_layers_at(world_coords)
                 .unwrap_or_default();
-            if let Some(available_layers) = tile_cache
-                .iter_tessellated_layers_at(&world_coords)
-                .map(|layers| {
-                    layers
-                        .filter(|result| !loaded_layers.contains(&result.layer_name()))
-                        .collect::<Vec<_>>()
-                })
+            if let Some(available_layers) =
+                tile_cache
+                    .iter_tessellated_layers_at(world_coords)
+                    .map(|layers| {
+                        layers
+                            .filter(|result| !loaded_layers.contains(&result.layer_name()))
+                            .collect::<Vec<_>>()
+                    })
             {
                 for style_layer in &style.layers {
                     let source_layer = style_layer.source_layer.as_ref().unwrap();
@@ -468,14 +739,24 @@ impl RenderState {
                                 drop(guard);
 
                                 tracing::trace!("Allocating geometry at {}", &coords);
-                                self.buffer_pool.allocate_layer_geometry(
+                                if let Err(e) = self.buffer_pool.allocate_layer_geometry(
+                                    self.device.as_ref(),
                                     &self.queue,
                                     *coords,
                                     style_layer.clone(),
                                     buffer,
-                                    ShaderLayerMetadata::new(style_layer.index as f32),
+                                    // The depth is cleared to zero and only greater depths pass,
+                                    // therefore the first layer has a z-index of one
+                                    ShaderLayerMetadata::new(style_layer.index as f32 + 1.0),
                                     &feature_metadata,
-                                );
+                                ) {
+                                    tracing::warn!(
+                                        "Layer {} at {} not uploaded: {}",
+                                        source_layer,
+                                        coords,
+                                        e
+                                    );
+                                }
                             }
                         }
                     }
@@ -489,10 +770,24 @@ impl RenderState {
         let render_setup_span = tracing::span!(tracing::Level::TRACE, "render prepare");
         let _guard = render_setup_span.enter();
 
-        let frame = self.surface.get_current_texture()?;
-        let frame_view = frame
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let (frame, frame_view) = match &self.render_target {
+            RenderTarget::Surface(surface) => {
+                let frame = surface.get_current_texture()?;
+                let frame_view = frame
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                (Some(frame), frame_view)
+            }
+            RenderTarget::Headless(headless_target) => (
+                None,
+                headless_target
+                    .texture
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default()),
+            ),
+            // The application renders with render_to or encode_render instead
+            RenderTarget::External => return Ok(()),
+        };
 
         let mut encoder = self
             .device
@@ -502,6 +797,68 @@ impl RenderState {
 
         drop(_guard);
 
+        self.encode_render(&mut encoder, &frame_view);
+
+        if let RenderTarget::Headless(headless_target) = &self.render_target {
+            // Copy the frame such that it can be read by the CPU
+            encoder.copy_texture_to_buffer(
+                headless_target.texture.texture.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &headless_target.output_buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: NonZeroU32::new(
+                            headless_target.dimensions.padded_bytes_per_row,
+                        ),
+                        rows_per_image: None,
+                    },
+                },
+                wgpu::Extent3d {
+                    width: headless_target.dimensions.width,
+                    height: headless_target.dimensions.height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        {
+            let _span = tracing::span!(tracing::Level::TRACE, "render finish").entered();
+            tracing::trace!("Finished drawing");
+
+            self.queue.submit(Some(encoder.finish()));
+            tracing::trace!("Submitted queue");
+
+            if let Some(frame) = frame {
+                frame.present();
+                tracing::trace!("Presented frame");
+            }
+        }
+
+        self.fps_meter.update_and_print();
+        Ok(())
+    }
+
+    /// Renders a frame into the given view and submits it to the queue. The view must match the
+    /// format and size of the render state.
+    #[tracing::instrument(skip_all)]
+    pub fn render_to(&mut self, view: &wgpu::TextureView) {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Encoder"),
+            });
+
+        self.encode_render(&mut encoder, view);
+
+        self.queue.submit(Some(encoder.finish()));
+        self.fps_meter.update_and_print();
+    }
+
+    /// Records the render pass of a frame into the given encoder. The view must match the format
+    /// and size of the render state. Submitting the encoder is left to the caller. The view is
+    /// cleared or loaded depending on [`RenderSettings::color_load_op`].
+    #[tracing::instrument(skip_all)]
+    pub fn encode_render(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
         {
             let _span_ = tracing::span!(tracing::Level::TRACE, "render pass").entered();
             {
@@ -510,16 +867,16 @@ impl RenderState {
                         wgpu::RenderPassColorAttachment {
                             view: &multisampling_target.view,
                             ops: wgpu::Operations {
-                                load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                                load: self.color_load_op,
                                 store: true,
                             },
-                            resolve_target: Some(&frame_view),
+                            resolve_target: Some(view),
                         }
                     } else {
                         wgpu::RenderPassColorAttachment {
-                            view: &frame_view,
+                            view,
                             ops: wgpu::Operations {
-                                load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                                load: self.color_load_op,
                                 store: true,
                             },
                             resolve_target: None,
@@ -544,104 +901,159 @@ impl RenderState {
 
                 pass.set_bind_group(0, &self.bind_group, &[]);
 
-                {
-                    let index = self.buffer_pool.index();
-
-                    for TileInView { shape, fallback } in self.tile_view_pattern.iter() {
-                        let coords = shape.coords;
-                        tracing::trace!("Drawing tile at {coords}");
-
-                        let shape_to_render = fallback.as_ref().unwrap_or(shape);
-
-                        let reference = self
-                            .tile_view_pattern
-                            .stencil_reference_value(&shape_to_render.coords)
-                            as u32;
-
-                        // Draw mask
-                        {
-                            tracing::trace!("Drawing mask {}", &coords);
-
-                            pass.set_pipeline(&self.mask_pipeline);
-                            pass.set_stencil_reference(reference);
-                            pass.set_vertex_buffer(
-                                0,
-                                self.tile_view_pattern
-                                    .buffer()
-                                    .slice(shape.buffer_range.clone()),
-                            );
-                            pass.draw(0..6, 0..1);
-                        }
+                for tile in self.tile_view_pattern.iter() {
+                    let shape = &tile.shape;
+                    let coords = shape.coords;
+                    tracing::trace!("Drawing tile at {coords}");
 
-                        if let Some(entries) = index.get_layers(&shape_to_render.coords) {
-                            let mut layers_to_render: Vec<&IndexEntry> = Vec::from_iter(entries);
-                            layers_to_render.sort_by_key(|entry| entry.style_layer.index);
-
-                            for entry in layers_to_render {
-                                // Draw tile
-                                {
-                                    tracing::trace!(
-                                        "Drawing layer {:?} at {}",
-                                        entry.style_layer.source_layer,
-                                        &entry.coords
-                                    );
+                    // Everything which is drawn for this tile uses its reference, which clips
+                    // overzoomed sources and fallbacks to the extent of the tile.
+                    let reference = self.tile_view_pattern.stencil_reference_value(&coords) as u32;
 
-                                    pass.set_pipeline(&self.render_pipeline);
-                                    pass.set_stencil_reference(reference);
-                                    pass.set_index_buffer(
-                                        self.buffer_pool
-                                            .indices()
-                                            .slice(entry.indices_buffer_range()),
-                                        INDEX_FORMAT,
-                                    );
-                                    pass.set_vertex_buffer(
-                                        0,
-                                        self.buffer_pool
-                                            .vertices()
-                                            .slice(entry.vertices_buffer_range()),
-                                    );
-                                    pass.set_vertex_buffer(
-                                        1,
-                                        self.tile_view_pattern
-                                            .buffer()
-                                            .slice(shape_to_render.buffer_range.clone()),
-                                    );
-                                    pass.set_vertex_buffer(
-                                        2,
-                                        self.buffer_pool
-                                            .metadata()
-                                            .slice(entry.layer_metadata_buffer_range()),
-                                    );
-                                    pass.set_vertex_buffer(
-                                        3,
-                                        self.buffer_pool
-                                            .feature_metadata()
-                                            .slice(entry.feature_metadata_buffer_range()),
-                                    );
-                                    pass.draw_indexed(entry.indices_range(), 0, 0..1);
-                                }
-                            }
-                        } else {
-                            tracing::trace!("No layers found at {}", &shape_to_render.coords);
-                        }
+                    // Draw mask
+                    {
+                        tracing::trace!("Drawing mask {}", &coords);
+
+                        pass.set_pipeline(&self.mask_pipeline);
+                        pass.set_stencil_reference(reference);
+                        pass.set_vertex_buffer(
+                            0,
+                            self.tile_view_pattern
+                                .buffer()
+                                .slice(shape.buffer_range.clone()),
+                        );
+                        pass.draw(0..6, 0..1);
+                    }
+
+                    for fallback in &tile.fallbacks {
+                        let entries = self.layers_at(fallback, |_| true);
+                        self.draw_layers(&mut pass, entries, reference, &self.render_pipeline);
                     }
+
+                    let pipeline = if tile.is_fading() {
+                        &self.fade_pipeline
+                    } else {
+                        &self.render_pipeline
+                    };
+                    // Each layer is drawn from the tile at the max zoom of its source
+                    let entries = tile
+                        .data_shapes()
+                        .flat_map(|data_shape| {
+                            self.layers_at(data_shape, |entry| {
+                                let max_zoom =
+                                    self.tile_view_pattern.layer_max_zoom(&entry.style_layer.id);
+                                tile.source_shape(max_zoom).coords == data_shape.coords
+                            })
+                        })
+                        .collect();
+                    self.draw_layers(&mut pass, entries, reference, pipeline);
                 }
             }
         }
+    }
 
-        {
-            let _span = tracing::span!(tracing::Level::TRACE, "render finish").entered();
-            tracing::trace!("Finished drawing");
+    /// Returns the layers which are loaded for the tile of `shape` and match the `filter`,
+    /// together with the shape.
+    fn layers_at<'a, F>(
+        &'a self,
+        shape: &'a TileShape,
+        filter: F,
+    ) -> Vec<(&'a IndexEntry, &'a TileShape)>
+    where
+        F: Fn(&IndexEntry) -> bool,
+    {
+        match self.buffer_pool.index().get_layers(&shape.coords) {
+            Some(entries) => entries
+                .iter()
+                .filter(|entry| filter(entry))
+                .map(|entry| (entry, shape))
+                .collect(),
+            None => {
+                tracing::trace!("No layers found at {}", &shape.coords);
+                Vec::new()
+            }
+        }
+    }
 
-            self.queue.submit(Some(encoder.finish()));
-            tracing::trace!("Submitted queue");
+    /// Draws the layers with the shapes of their tiles in the order of the style.
+    fn draw_layers<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        mut layers_to_render: Vec<(&'a IndexEntry, &'a TileShape)>,
+        reference: u32,
+        pipeline: &'a wgpu::RenderPipeline,
+    ) {
+        layers_to_render.sort_by_key(|(entry, _)| entry.style_layer.index);
+
+        for (entry, shape) in layers_to_render {
+            // Draw tile
+            tracing::trace!(
+                "Drawing layer {:?} at {}",
+                entry.style_layer.source_layer,
+                &entry.coords
+            );
+
+            pass.set_pipeline(pipeline);
+            pass.set_stencil_reference(reference);
+            pass.set_index_buffer(
+                self.buffer_pool
+                    .indices()
+                    .slice(entry.indices_buffer_range()),
+                INDEX_FORMAT,
+            );
+            pass.set_vertex_buffer(
+                0,
+                self.buffer_pool
+                    .vertices()
+                    .slice(entry.vertices_buffer_range()),
+            );
+            pass.set_vertex_buffer(
+                1,
+                self.tile_view_pattern
+                    .buffer()
+                    .slice(shape.buffer_range.clone()),
+            );
+            pass.set_vertex_buffer(
+                2,
+                self.buffer_pool
+                    .metadata()
+                    .slice(entry.layer_metadata_buffer_range()),
+            );
+            pass.set_vertex_buffer(
+                3,
+                self.buffer_pool
+                    .feature_metadata()
+                    .slice(entry.feature_metadata_buffer_range()),
+            );
+            pass.draw_indexed(entry.indices_range(), 0, 0..1);
+        }
+    }
 
-            frame.present();
-            tracing::trace!("Presented frame");
+    /// Reads the pixels of the last rendered frame. This is only possible when rendering
+    /// headless, otherwise `None` is returned.
+    pub async fn read_frame(&self) -> Option<Result<RgbaImage, Error>> {
+        let headless_target = match &self.render_target {
+            RenderTarget::Headless(headless_target) => headless_target,
+            RenderTarget::Surface(_) | RenderTarget::External => return None,
+        };
+
+        let buffer_slice = headless_target.output_buffer.slice(..);
+        let mapping = buffer_slice.map_async(wgpu::MapMode::Read);
+        self.device.poll(wgpu::Maintain::Wait);
+        if let Err(e) = mapping.await {
+            return Some(Err(Error::Render(RenderError::Readback(e))));
         }
 
-        self.fps_meter.update_and_print();
-        Ok(())
+        let dimensions = &headless_target.dimensions;
+        let data =
+            dimensions.unpad_to_rgba(&buffer_slice.get_mapped_range(), self.surface_config.format);
+        headless_target.output_buffer.unmap();
+
+        Some(Ok(RgbaImage::new(
+            dimensions.width,
+            dimensions.height,
+            data,
+        )))
     }
 
     pub fn suspend(&mut self) {
@@ -652,3 +1064,33 @@ impl RenderState {
         self.suspended = false;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::render::render_state::BufferDimensions;
+
+    #[test]
+    fn test_buffer_dimensions() {
+        let dimensions = BufferDimensions::new(10, 2);
+        assert_eq!(dimensions.unpadded_bytes_per_row, 40);
+        assert_eq!(dimensions.padded_bytes_per_row, 256);
+        assert_eq!(dimensions.buffer_size(), 512);
+
+        let dimensions = BufferDimensions::new(64, 1);
+        assert_eq!(dimensions.padded_bytes_per_row, 256);
+
+        let mut padded = vec![0u8; 512];
+        padded[0..4].copy_from_slice(&[1, 2, 3, 4]);
+        padded[256..260].copy_from_slice(&[5, 6, 7, 8]);
+
+        let dimensions = BufferDimensions::new(10, 2);
+        let rgba = dimensions.unpad_to_rgba(&padded, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(rgba.len(), 80);
+        assert_eq!(&rgba[0..4], &[1, 2, 3, 4]);
+        assert_eq!(&rgba[40..44], &[5, 6, 7, 8]);
+
+        let rgba = dimensions.unpad_to_rgba(&padded, wgpu::TextureFormat::Bgra8UnormSrgb);
+        assert_eq!(&rgba[0..4], &[3, 2, 1, 4]);
+        assert_eq!(&rgba[40..44], &[7, 6, 5, 8]);
+    }
+}
@@ -0,0 +1,315 @@
+//! Settings which configure the renderer at runtime.
+
+use std::fmt;
+use std::fmt::Formatter;
+use std::time::Duration;
+use wgpu::BufferAddress;
+
+/// The format of the index buffer. This is not a setting, because it must match the
+/// `IndexDataType` of the tessellator, which is fixed at compile time.
+pub const INDEX_FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint32;
+
+/// Sample counts which are supported by WebGPU.
+const SUPPORTED_SAMPLE_COUNTS: [u32; 2] = [1, 4];
+
+/// Multisample anti-aliasing of the rendered frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Msaa {
+    /// The number of samples per pixel. A count of 1 disables anti-aliasing.
+    pub samples: u32,
+}
+
+impl Msaa {
+    pub fn is_multisampling(&self) -> bool {
+        self.samples > 1
+    }
+}
+
+impl Default for Msaa {
+    fn default() -> Self {
+        Self { samples: 4 }
+    }
+}
+
+/// The initial size of a buffer and the size up to which it grows when it is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferBudget {
+    pub initial: BufferAddress,
+    pub max: BufferAddress,
+}
+
+impl BufferBudget {
+    pub fn new(initial: BufferAddress, max: BufferAddress) -> Self {
+        Self { initial, max }
+    }
+}
+
+/// Sizes of the GPU buffers which store the tessellated tiles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferBudgets {
+    /// Size of the vertex buffer in bytes.
+    pub vertices: BufferBudget,
+    /// Size of the index buffer in bytes.
+    pub indices: BufferBudget,
+    /// Size of the buffer for the styles of features in bytes.
+    pub feature_metadata: BufferBudget,
+    /// Size of the buffer for the metadata of layers, for example their z-index, in bytes.
+    pub layer_metadata: BufferBudget,
+    /// Size of the buffer for the transformations of visible tiles in bytes.
+    pub tile_view: BufferAddress,
+}
+
+impl Default for BufferBudgets {
+    fn default() -> Self {
+        Self {
+            vertices: BufferBudget::new(1024 * 1024 * 32, 1024 * 1024 * 128),
+            indices: BufferBudget::new(1024 * 1024 * 32, 1024 * 1024 * 128),
+            feature_metadata: BufferBudget::new(1024 * 1024 * 32, 1024 * 1024 * 128),
+            layer_metadata: BufferBudget::new(1024 * 24, 1024 * 96),
+            tile_view: 1024 * 64,
+        }
+    }
+}
+
+/// Configures how maps are rendered.
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    /// The backends from which an adapter is chosen.
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    pub present_mode: wgpu::PresentMode,
+    pub msaa: Msaa,
+    pub buffer_budgets: BufferBudgets,
+    /// Draws the outlines of triangles instead of filling them. Requires
+    /// [`wgpu::Features::POLYGON_MODE_LINE`].
+    pub debug_wireframe: bool,
+    /// Draws the stencil masks of tiles and ignores them when drawing features.
+    pub debug_stencil_pattern: bool,
+    /// The duration over which tiles fade in over their fallbacks once they are loaded. A zero
+    /// duration disables fading.
+    pub fade_duration: Duration,
+    /// How the color target is initialized at the start of a frame. [`wgpu::LoadOp::Load`] keeps
+    /// the contents of the target, such that an application which embeds the map can draw the map
+    /// over its own frame. Loading requires that multisampling is disabled, because the map is
+    /// drawn into a separate multisampled texture otherwise.
+    pub color_load_op: wgpu::LoadOp<wgpu::Color>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::LowPower,
+            present_mode: wgpu::PresentMode::Fifo, // VSync
+            msaa: Msaa::default(),
+            buffer_budgets: BufferBudgets::default(),
+            debug_wireframe: false,
+            debug_stencil_pattern: false,
+            fade_duration: Duration::from_millis(300),
+            color_load_op: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+        }
+    }
+}
+
+impl RenderSettings {
+    /// The features a device needs in order to render with these settings.
+    pub fn required_features(&self) -> wgpu::Features {
+        if self.debug_wireframe {
+            wgpu::Features::POLYGON_MODE_LINE
+        } else {
+            wgpu::Features::empty()
+        }
+    }
+
+    /// Checks whether a device with the given features and limits can render frames of the given
+    /// size with these settings.
+    pub fn validate(
+        &self,
+        features: wgpu::Features,
+        limits: &wgpu::Limits,
+        width: u32,
+        height: u32,
+    ) -> Result<(), SettingsError> {
+        if !SUPPORTED_SAMPLE_COUNTS.contains(&self.msaa.samples) {
+            return Err(SettingsError::UnsupportedSampleCount(self.msaa.samples));
+        }
+
+        if self.msaa.is_multisampling() && self.color_load_op == wgpu::LoadOp::Load {
+            return Err(SettingsError::LoadWithMultisampling);
+        }
+
+        let missing_features = self.required_features() - features;
+        if !missing_features.is_empty() {
+            return Err(SettingsError::MissingFeatures(missing_features));
+        }
+
+        let max_dimension = limits.max_texture_dimension_2d;
+        if width > max_dimension || height > max_dimension {
+            return Err(SettingsError::SizeExceedsLimit {
+                width,
+                height,
+                max_dimension,
+            });
+        }
+
+        let budgets = &self.buffer_budgets;
+        for (name, budget) in [
+            ("vertices", budgets.vertices),
+            ("indices", budgets.indices),
+            ("feature metadata", budgets.feature_metadata),
+            ("layer metadata", budgets.layer_metadata),
+            (
+                "tile view",
+                BufferBudget::new(budgets.tile_view, budgets.tile_view),
+            ),
+        ] {
+            for size in [budget.initial, budget.max] {
+                if size == 0 || size % wgpu::COPY_BUFFER_ALIGNMENT != 0 {
+                    return Err(SettingsError::InvalidBufferSize { name, size });
+                }
+            }
+            if budget.max < budget.initial {
+                return Err(SettingsError::InvalidBufferSize {
+                    name,
+                    size: budget.max,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reasons why [`RenderSettings`] can not be used with a device.
+#[derive(Debug, PartialEq)]
+pub enum SettingsError {
+    UnsupportedSampleCount(u32),
+    MissingFeatures(wgpu::Features),
+    SizeExceedsLimit {
+        width: u32,
+        height: u32,
+        max_dimension: u32,
+    },
+    /// Buffer sizes must not be zero and must be a multiple of [`wgpu::COPY_BUFFER_ALIGNMENT`].
+    /// The maximum size must not be smaller than the initial size.
+    InvalidBufferSize {
+        name: &'static str,
+        size: BufferAddress,
+    },
+    /// The contents of the target can not be loaded if multisampling is enabled.
+    LoadWithMultisampling,
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::UnsupportedSampleCount(samples) => {
+                write!(f, "unsupported MSAA sample count {}", samples)
+            }
+            SettingsError::MissingFeatures(features) => {
+                write!(f, "device does not support the features {:?}", features)
+            }
+            SettingsError::SizeExceedsLimit {
+                width,
+                height,
+                max_dimension,
+            } => write!(
+                f,
+                "size {}x{} exceeds the maximum texture dimension {}",
+                width, height, max_dimension
+            ),
+            SettingsError::InvalidBufferSize { name, size } => {
+                write!(f, "invalid size {} of the {} buffer", size, name)
+            }
+            SettingsError::LoadWithMultisampling => {
+                write!(
+                    f,
+                    "loading the color target is not supported with multisampling"
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::render::settings::{BufferBudget, Msaa, RenderSettings, SettingsError};
+
+    #[test]
+    fn test_validate() {
+        let limits = wgpu::Limits::downlevel_webgl2_defaults();
+        let settings = RenderSettings::default();
+        assert_eq!(
+            settings.validate(wgpu::Features::empty(), &limits, 800, 600),
+            Ok(())
+        );
+        assert_eq!(
+            settings.validate(wgpu::Features::empty(), &limits, 4096, 600),
+            Err(SettingsError::SizeExceedsLimit {
+                width: 4096,
+                height: 600,
+                max_dimension: 2048
+            })
+        );
+
+        let settings = RenderSettings {
+            msaa: Msaa { samples: 2 },
+            ..RenderSettings::default()
+        };
+        assert_eq!(
+            settings.validate(wgpu::Features::empty(), &limits, 800, 600),
+            Err(SettingsError::UnsupportedSampleCount(2))
+        );
+
+        let settings = RenderSettings {
+            debug_wireframe: true,
+            ..RenderSettings::default()
+        };
+        assert_eq!(
+            settings.validate(wgpu::Features::empty(), &limits, 800, 600),
+            Err(SettingsError::MissingFeatures(
+                wgpu::Features::POLYGON_MODE_LINE
+            ))
+        );
+        assert_eq!(
+            settings.validate(wgpu::Features::POLYGON_MODE_LINE, &limits, 800, 600),
+            Ok(())
+        );
+
+        let settings = RenderSettings {
+            color_load_op: wgpu::LoadOp::Load,
+            ..RenderSettings::default()
+        };
+        assert_eq!(
+            settings.validate(wgpu::Features::empty(), &limits, 800, 600),
+            Err(SettingsError::LoadWithMultisampling)
+        );
+        let settings = RenderSettings {
+            msaa: Msaa { samples: 1 },
+            ..settings
+        };
+        assert_eq!(
+            settings.validate(wgpu::Features::empty(), &limits, 800, 600),
+            Ok(())
+        );
+
+        let mut settings = RenderSettings::default();
+        settings.buffer_budgets.indices = BufferBudget::new(3, 4);
+        assert_eq!(
+            settings.validate(wgpu::Features::empty(), &limits, 800, 600),
+            Err(SettingsError::InvalidBufferSize {
+                name: "indices",
+                size: 3
+            })
+        );
+
+        settings.buffer_budgets.indices = BufferBudget::new(8, 4);
+        assert_eq!(
+            settings.validate(wgpu::Features::empty(), &limits, 800, 600),
+            Err(SettingsError::InvalidBufferSize {
+                name: "indices",
+                size: 4
+            })
+        );
+    }
+}
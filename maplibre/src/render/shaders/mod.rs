@@ -1,6 +1,7 @@
 #![allow(clippy::identity_op)]
 use wgpu::{
-    ColorTargetState, Device, FragmentState, ShaderModule, VertexBufferLayout, VertexState,
+    ColorTargetState, Device, FragmentState, ShaderModule, TextureFormat, VertexBufferLayout,
+    VertexState,
 };
 
 use crate::coords::WorldCoords;
@@ -21,6 +22,8 @@ impl From<WorldCoords> for Vec3f32 {
 pub struct FragmentShaderState {
     source: &'static str,
     targets: &'static [ColorTargetState],
+    /// The targets with the format of the render target.
+    resolved_targets: Vec<ColorTargetState>,
     module: Option<ShaderModule>,
 }
 
@@ -35,20 +38,35 @@ impl FragmentShaderState {
         Self {
             source,
             targets,
+            resolved_targets: Vec::new(),
             module: None,
         }
     }
 
-    pub fn create_fragment_state(&mut self, device: &Device) -> FragmentState {
+    /// Creates the fragment state for render targets of the given `format`.
+    pub fn create_fragment_state(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+    ) -> FragmentState {
         self.module = Some(device.create_shader_module(&wgpu::ShaderModuleDescriptor {
             label: Some("fragment shader"),
             source: wgpu::ShaderSource::Wgsl(self.source.into()),
         }));
 
+        self.resolved_targets = self
+            .targets
+            .iter()
+            .map(|target| ColorTargetState {
+                format,
+                ..target.clone()
+            })
+            .collect();
+
         wgpu::FragmentState {
             module: self.module.as_ref().unwrap(),
             entry_point: "main",
-            targets: self.targets,
+            targets: &self.resolved_targets,
         }
     }
 }
@@ -140,6 +158,13 @@ pub mod tile {
                         format: wgpu::VertexFormat::Float32,
                         shader_location: 9,
                     },
+                    // opacity
+                    wgpu::VertexAttribute {
+                        offset: 4 * wgpu::VertexFormat::Float32x4.size()
+                            + wgpu::VertexFormat::Float32.size(),
+                        format: wgpu::VertexFormat::Float32,
+                        shader_location: 11,
+                    },
                 ],
             },
             // layer metadata
@@ -191,11 +216,20 @@ pub mod tile {
             write_mask: wgpu::ColorWrites::ALL,
         }],
     );
+
+    /// The fragment shader which blends tiles over their fallbacks while they fade in.
+    pub const FADE_FRAGMENT: FragmentShaderState = FragmentShaderState::new(
+        include_str!("tile.fragment.wgsl"),
+        &[wgpu::ColorTargetState {
+            format: COLOR_TEXTURE_FORMAT,
+            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+            write_mask: wgpu::ColorWrites::ALL,
+        }],
+    );
 }
 
 pub mod tile_mask {
     use crate::platform::COLOR_TEXTURE_FORMAT;
-    use crate::render::options::DEBUG_STENCIL_PATTERN;
     use crate::render::shaders::ShaderTileMetadata;
     use wgpu::ColorWrites;
 
@@ -237,17 +271,19 @@ pub mod tile_mask {
         &[wgpu::ColorTargetState {
             format: COLOR_TEXTURE_FORMAT,
             blend: None,
-            write_mask: mask_write_mask(),
+            write_mask: ColorWrites::empty(),
         }],
     );
 
-    pub const fn mask_write_mask() -> ColorWrites {
-        if DEBUG_STENCIL_PATTERN {
-            wgpu::ColorWrites::ALL
-        } else {
-            wgpu::ColorWrites::empty()
-        }
-    }
+    /// The fragment shader which draws the stencil masks of tiles in order to debug them.
+    pub const DEBUG_FRAGMENT: FragmentShaderState = FragmentShaderState::new(
+        include_str!("tile_mask.fragment.wgsl"),
+        &[wgpu::ColorTargetState {
+            format: COLOR_TEXTURE_FORMAT,
+            blend: None,
+            write_mask: ColorWrites::ALL,
+        }],
+    );
 }
 
 #[repr(C)]
@@ -331,13 +367,16 @@ impl ShaderLayerMetadata {
 pub struct ShaderTileMetadata {
     pub transform: Mat4x4f32,
     pub zoom_factor: f32,
+    /// Multiplied with the alpha of the features when tiles fade in.
+    pub opacity: f32,
 }
 
 impl ShaderTileMetadata {
-    pub fn new(transform: Mat4x4f32, zoom_factor: f32) -> Self {
+    pub fn new(transform: Mat4x4f32, zoom_factor: f32, opacity: f32) -> Self {
         Self {
             transform,
             zoom_factor,
+            opacity,
         }
     }
 }
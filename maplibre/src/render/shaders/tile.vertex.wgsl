@@ -25,6 +25,7 @@ fn main(
     [[location(8)]] color: vec4<f32>,
     [[location(9)]] zoom_factor: f32,
     [[location(10)]] z_index: f32,
+    [[location(11)]] opacity: f32,
     [[builtin(instance_index)]] instance_idx: u32 // instance_index is used when we have multiple instances of the same "object"
 ) -> VertexOutput {
     let z = 0.0;
@@ -39,5 +40,5 @@ fn main(
     // FIXME: how to fix z-fighting?
     position.z = z_index;
 
-    return VertexOutput(color, position);
+    return VertexOutput(vec4<f32>(color.rgb, color.a * opacity), position);
 }
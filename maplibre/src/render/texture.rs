@@ -32,6 +32,29 @@ impl Texture {
         }
     }
 
+    /// Creates a texture which fits the given configuration and can be copied to a buffer. This
+    /// is used instead of a surface when rendering headless.
+    pub fn create_headless_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> Texture {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Headless frame texture"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view }
+    }
+
     /// Creates a texture that uses MSAA and fits a given swap chain.
     pub fn create_multisampling_texture(
         device: &wgpu::Device,
@@ -6,16 +6,27 @@ use crate::render::shaders::{ShaderFeatureStyle, ShaderLayerMetadata, ShaderTile
 use cgmath::Matrix4;
 
 use crate::render::ShaderVertex;
+use crate::style::Style;
 use crate::tessellation::IndexDataType;
+use instant::Instant;
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
 use std::mem::size_of;
 use std::ops::Range;
+use std::time::Duration;
 use wgpu::Buffer;
 
 /// The tile mask pattern assigns each tile a value which can be used for stencil testing.
 pub struct TileViewPattern<Q, B> {
     in_view: Vec<TileInView>,
     buffer: BackingBuffer<B>,
+    /// Tiles in view whose data was missing during the last update.
+    missing: HashSet<WorldTileCoords>,
+    /// The time at which the data of a tile in view arrived. The tile fades in over its
+    /// fallbacks.
+    fade_start: HashMap<WorldTileCoords, Instant>,
+    /// The max zoom of the source of each style layer, by the id of the layer.
+    layer_max_zooms: HashMap<String, Option<u8>>,
     phantom_q: PhantomData<Q>,
 }
 
@@ -30,7 +41,6 @@ pub struct TileShape {
 
 impl TileShape {
     fn new(coords: WorldTileCoords, zoom: Zoom, index: u64) -> Self {
-        const STRIDE: u64 = size_of::<ShaderTileMetadata>() as u64;
         Self {
             coords,
             zoom_factor: zoom.scale_to_tile(&coords),
@@ -43,9 +53,40 @@ impl TileShape {
 pub struct TileInView {
     pub shape: TileShape,
 
-    pub fallback: Option<TileShape>,
+    /// The ancestors at the max zooms of the sources for which the tile is overzoomed. Their data
+    /// is clipped to the extent of `shape`.
+    pub sources: Vec<TileShape>,
+
+    /// Loaded tiles which are drawn within the extent of `shape` while its data is loading or
+    /// fading in. This is either an ancestor or up to four children.
+    pub fallbacks: Vec<TileShape>,
+
+    /// The opacity with which the data of the tile is drawn over its fallbacks.
+    pub opacity: f32,
+}
+
+impl TileInView {
+    /// The shape with which the data of a source with the given `max_zoom` is drawn.
+    pub fn source_shape(&self, max_zoom: Option<u8>) -> &TileShape {
+        let coords = self.shape.coords.overzoom_source(max_zoom);
+        self.sources
+            .iter()
+            .find(|source| source.coords == coords)
+            .unwrap_or(&self.shape)
+    }
+
+    /// The shapes with which the data of this tile is drawn.
+    pub fn data_shapes(&self) -> impl Iterator<Item = &TileShape> + '_ {
+        std::iter::once(&self.shape).chain(&self.sources)
+    }
+
+    pub fn is_fading(&self) -> bool {
+        self.opacity < 1.0
+    }
 }
 
+const STRIDE: u64 = size_of::<ShaderTileMetadata>() as u64;
+
 #[derive(Debug)]
 struct BackingBuffer<B> {
     /// The internal structure which is used for storage
@@ -65,10 +106,16 @@ impl<Q: Queue<B>, B> TileViewPattern<Q, B> {
         Self {
             in_view: Vec::with_capacity(64),
             buffer: BackingBuffer::new(buffer.buffer, buffer.inner_size),
+            missing: Default::default(),
+            fade_start: Default::default(),
+            layer_max_zooms: Default::default(),
             phantom_q: Default::default(),
         }
     }
 
+    /// Assigns the tiles in view their shapes and fallbacks. Each layer of the `style` is drawn
+    /// from the tile at the max zoom of its source. Tiles whose data arrived within the last
+    /// `fade_duration` fade in over their fallbacks.
     #[tracing::instrument(skip_all)]
     pub fn update_pattern(
         &mut self,
@@ -82,49 +129,145 @@ impl<Q: Queue<B>, B> TileViewPattern<Q, B> {
             ShaderFeatureStyle,
         >,
         zoom: Zoom,
+        style: &Style,
+        now: Instant,
+        fade_duration: Duration,
     ) {
         self.in_view.clear();
 
         let mut index = 0;
+        let capacity = self.buffer.inner_size / STRIDE;
+        let mut skipped = 0;
 
         let pool_index = buffer_pool.index();
+        let mut missing = HashSet::new();
+
+        self.layer_max_zooms = style
+            .layers
+            .iter()
+            .map(|layer| (layer.id.clone(), style.layer_max_zoom(layer)))
+            .collect();
+        let max_zooms = style.layer_max_zooms();
 
         for coords in view_region.iter() {
             if coords.build_quad_key().is_none() {
                 continue;
             }
 
-            let shape = TileShape::new(coords, zoom, index);
+            let mut source_coords = source_coords(&coords, &max_zooms);
+            let has_data = source_coords
+                .iter()
+                .all(|source_coords| pool_index.has_tile(source_coords));
 
-            index += 1;
+            let mut opacity = 1.0;
+            if !has_data {
+                missing.insert(coords);
+                self.fade_start.remove(&coords);
+            } else if self.missing.contains(&coords) && fade_duration > Duration::ZERO {
+                self.fade_start.insert(coords, now);
+            }
 
-            let fallback = {
-                if !pool_index.has_tile(&coords) {
-                    if let Some(fallback_coords) = pool_index.get_tile_coords_fallback(&coords) {
-                        tracing::trace!(
-                            "Could not find data at {coords}. Falling back to {fallback_coords}"
-                        );
-
-                        let shape = TileShape::new(fallback_coords, zoom, index);
-
-                        index += 1;
-                        Some(shape)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
+            if let Some(start) = self.fade_start.get(&coords) {
+                opacity = fade_opacity(*start, now, fade_duration);
+                if opacity >= 1.0 {
+                    self.fade_start.remove(&coords);
                 }
+            }
+
+            // The sources with the highest max zoom provide the most detailed fallbacks
+            let fallback_coords = if !has_data || opacity < 1.0 {
+                let deepest = source_coords.last().copied().unwrap_or(coords);
+                find_fallbacks(&deepest, |coords| pool_index.has_tile(coords))
+            } else {
+                Vec::new()
             };
 
-            self.in_view.push(TileInView { shape, fallback });
+            if has_data && fallback_coords.is_empty() {
+                // There is nothing to fade from
+                opacity = 1.0;
+                self.fade_start.remove(&coords);
+            }
+
+            source_coords.retain(|source_coords| *source_coords != coords);
+            let needed = 1 + source_coords.len() as u64 + fallback_coords.len() as u64;
+            if index + needed > capacity {
+                skipped += 1;
+                continue;
+            }
+
+            let shape = TileShape::new(coords, zoom, index);
+            index += 1;
+
+            let sources = source_coords
+                .into_iter()
+                .map(|source_coords| {
+                    let shape = TileShape::new(source_coords, zoom, index);
+                    index += 1;
+                    shape
+                })
+                .collect();
+
+            let fallbacks = fallback_coords
+                .into_iter()
+                .map(|fallback_coords| {
+                    tracing::trace!(
+                        "Could not find data at {coords}. Falling back to {fallback_coords}"
+                    );
+
+                    let shape = TileShape::new(fallback_coords, zoom, index);
+                    index += 1;
+                    shape
+                })
+                .collect();
+
+            self.in_view.push(TileInView {
+                shape,
+                sources,
+                fallbacks,
+                opacity,
+            });
+        }
+
+        if skipped > 0 {
+            tracing::warn!(
+                "{} tiles are not drawn because the tile view buffer is full",
+                skipped
+            );
         }
+
+        self.missing = missing;
+        let in_view = &self.in_view;
+        self.fade_start.retain(|coords, _| {
+            in_view
+                .iter()
+                .any(|tile_in_view| tile_in_view.shape.coords == *coords)
+        });
+    }
+
+    /// Returns true if tiles are fading in.
+    pub fn is_fading(&self) -> bool {
+        !self.fade_start.is_empty()
     }
 
     pub fn iter(&self) -> impl Iterator<Item = &TileInView> + '_ {
         self.in_view.iter()
     }
 
+    /// Returns the coordinates of all tiles in the pattern, including the overzoomed sources and
+    /// the fallbacks.
+    pub fn visible_coords(&self) -> impl Iterator<Item = &WorldTileCoords> + '_ {
+        self.in_view.iter().flat_map(|tile| {
+            std::iter::once(&tile.shape.coords)
+                .chain(tile.sources.iter().map(|source| &source.coords))
+                .chain(tile.fallbacks.iter().map(|fallback| &fallback.coords))
+        })
+    }
+
+    /// The max zoom of the source of the style layer with the given id.
+    pub fn layer_max_zoom(&self, layer_id: &str) -> Option<u8> {
+        self.layer_max_zooms.get(layer_id).copied().flatten()
+    }
+
     pub fn buffer(&self) -> &B {
         &self.buffer.inner
     }
@@ -133,27 +276,27 @@ impl<Q: Queue<B>, B> TileViewPattern<Q, B> {
     pub fn upload_pattern(&self, queue: &Q, view_proj: &ViewProjection) {
         let mut buffer = Vec::with_capacity(self.in_view.len());
 
-        for tile in &self.in_view {
-            buffer.push(ShaderTileMetadata {
+        let metadata = |shape: &TileShape, opacity: f32| {
+            ShaderTileMetadata::new(
                 // We are casting here from 64bit to 32bit, because 32bit is more performant and is
                 // better supported.
-                transform: view_proj
-                    .to_model_view_projection(tile.shape.transform)
+                view_proj
+                    .to_model_view_projection(shape.transform)
                     .downcast()
                     .into(),
-                zoom_factor: tile.shape.zoom_factor as f32,
-            });
+                shape.zoom_factor as f32,
+                opacity,
+            )
+        };
 
-            if let Some(fallback_shape) = &tile.fallback {
-                buffer.push(ShaderTileMetadata {
-                    // We are casting here from 64bit to 32bit, because 32bit is more performant and is
-                    // better supported.
-                    transform: view_proj
-                        .to_model_view_projection(fallback_shape.transform)
-                        .downcast()
-                        .into(),
-                    zoom_factor: fallback_shape.zoom_factor as f32,
-                });
+        // The order must match the indices which are assigned in `update_pattern`
+        for tile in &self.in_view {
+            for data_shape in tile.data_shapes() {
+                buffer.push(metadata(data_shape, tile.opacity));
+            }
+
+            for fallback_shape in &tile.fallbacks {
+                buffer.push(metadata(fallback_shape, 1.0));
             }
         }
 
@@ -175,3 +318,117 @@ impl<Q: Queue<B>, B> TileViewPattern<Q, B> {
             }
     }
 }
+
+/// Returns the tiles which provide the data of the tile at `coords` for sources with the given
+/// `max_zooms`, ordered by their zoom level.
+fn source_coords(coords: &WorldTileCoords, max_zooms: &[Option<u8>]) -> Vec<WorldTileCoords> {
+    let mut source_coords = max_zooms
+        .iter()
+        .map(|max_zoom| coords.overzoom_source(*max_zoom))
+        .collect::<Vec<_>>();
+    source_coords.sort_unstable_by_key(|source_coords| source_coords.z);
+    source_coords.dedup();
+    source_coords
+}
+
+/// Returns loaded tiles which cover the extent of `coords`. The closest loaded ancestor is
+/// preferred. If no ancestor is loaded, then the loaded children are returned.
+fn find_fallbacks<F>(coords: &WorldTileCoords, has_tile: F) -> Vec<WorldTileCoords>
+where
+    F: Fn(&WorldTileCoords) -> bool,
+{
+    let mut current = coords.get_parent();
+    while let Some(parent) = current {
+        if has_tile(&parent) {
+            return vec![parent];
+        }
+        current = parent.get_parent();
+    }
+
+    coords
+        .children()
+        .into_iter()
+        .filter(|child| has_tile(child))
+        .collect()
+}
+
+/// The opacity of a tile which started fading in at `start`.
+fn fade_opacity(start: Instant, now: Instant, fade_duration: Duration) -> f32 {
+    if fade_duration == Duration::ZERO {
+        return 1.0;
+    }
+
+    let elapsed = now.saturating_duration_since(start);
+    (elapsed.as_secs_f32() / fade_duration.as_secs_f32()).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coords::WorldTileCoords;
+    use crate::coords::Zoom;
+    use crate::render::tile_view_pattern::{
+        fade_opacity, find_fallbacks, source_coords, TileInView, TileShape,
+    };
+    use instant::Instant;
+    use std::time::Duration;
+
+    #[test]
+    fn test_source_coords() {
+        let coords = WorldTileCoords::from((13, 6, 4));
+        assert_eq!(
+            source_coords(&coords, &[None, Some(2), Some(3), Some(5)]),
+            vec![
+                WorldTileCoords::from((3, 1, 2)),
+                (6, 3, 3).into(),
+                (13, 6, 4).into()
+            ]
+        );
+        assert_eq!(source_coords(&coords, &[Some(3), Some(2)]).len(), 2);
+
+        let zoom = Zoom::new(4.0);
+        let tile = TileInView {
+            shape: TileShape::new(coords, zoom, 0),
+            sources: vec![TileShape::new((3, 1, 2).into(), zoom, 1)],
+            fallbacks: Vec::new(),
+            opacity: 1.0,
+        };
+        assert_eq!(tile.source_shape(Some(2)).coords, (3, 1, 2).into());
+        assert_eq!(tile.source_shape(Some(14)).coords, coords);
+        assert_eq!(tile.source_shape(None).coords, coords);
+        assert_eq!(tile.data_shapes().count(), 2);
+    }
+
+    #[test]
+    fn test_find_fallbacks() {
+        let coords = WorldTileCoords::from((2, 2, 2));
+
+        let loaded = [WorldTileCoords::from((0, 0, 0)), (1, 1, 1).into()];
+        assert_eq!(
+            find_fallbacks(&coords, |coords| loaded.contains(coords)),
+            vec![WorldTileCoords::from((1, 1, 1))]
+        );
+
+        let loaded = [
+            WorldTileCoords::from((4, 4, 3)),
+            (5, 5, 3).into(),
+            (2, 4, 3).into(),
+        ];
+        assert_eq!(
+            find_fallbacks(&coords, |coords| loaded.contains(coords)),
+            vec![WorldTileCoords::from((4, 4, 3)), (5, 5, 3).into()]
+        );
+
+        assert!(find_fallbacks(&coords, |_| false).is_empty());
+    }
+
+    #[test]
+    fn test_fade_opacity() {
+        let start = Instant::now();
+        let duration = Duration::from_millis(200);
+
+        assert_eq!(fade_opacity(start, start, duration), 0.0);
+        assert!((fade_opacity(start, start + duration / 2, duration) - 0.5).abs() < 1e-6);
+        assert_eq!(fade_opacity(start, start + duration * 2, duration), 1.0);
+        assert_eq!(fade_opacity(start, start, Duration::ZERO), 1.0);
+    }
+}
@@ -56,6 +56,7 @@ impl LayerPaint {
 
 /// Stores all the styles for a specific layer.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(from = "RawStyleLayer")]
 pub struct StyleLayer {
     #[serde(skip)]
     pub index: u32,
@@ -76,9 +77,48 @@ pub struct StyleLayer {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "source-layer")]
     pub source_layer: Option<String>,
 }
 
+/// The layer as it is written in a style. The `type` key is needed by both the layer and its
+/// paint, therefore the paint is parsed after the layer.
+#[derive(Deserialize)]
+struct RawStyleLayer {
+    id: String,
+    #[serde(rename = "type")]
+    typ: String,
+    maxzoom: Option<u8>,
+    minzoom: Option<u8>,
+    metadata: Option<HashMap<String, String>>,
+    paint: Option<serde_json::Value>,
+    source: Option<String>,
+    #[serde(alias = "source-layer")]
+    source_layer: Option<String>,
+}
+
+impl From<RawStyleLayer> for StyleLayer {
+    fn from(layer: RawStyleLayer) -> Self {
+        let paint = serde_json::json!({
+            "type": layer.typ,
+            "paint": layer.paint.unwrap_or_else(|| serde_json::json!({})),
+        });
+
+        Self {
+            index: 0,
+            id: layer.id,
+            typ: layer.typ,
+            maxzoom: layer.maxzoom,
+            minzoom: layer.minzoom,
+            metadata: layer.metadata,
+            // Layers of unsupported types have no paint
+            paint: serde_json::from_value(paint).ok(),
+            source: layer.source,
+            source_layer: layer.source_layer,
+        }
+    }
+}
+
 impl Default for StyleLayer {
     fn default() -> Self {
         Self {
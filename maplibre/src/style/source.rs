@@ -44,8 +44,11 @@ pub struct VectorSource {
     pub scheme: Option<TileAddressingScheme>,
     /// Array of URLs which can contain place holders like {x}, {y}, {z}.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tiles: Option<TileUrl>,
-    // url: Option<TileJSONUrl>,
+    pub tiles: Option<Vec<TileUrl>>,
+    /// URL to a TileJSON which describes the tiles. Used if `tiles` is not set.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<TileJSONUrl>,
     // TODO volatile
 }
 
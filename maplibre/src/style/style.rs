@@ -1,10 +1,10 @@
 //! Default vector tile styles configuration.
 
 use crate::style::layer::{LayerPaint, LinePaint, StyleLayer};
-use crate::style::source::Source;
+use crate::style::source::{Source, VectorSource};
 use csscolorparser::Color;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 
 /// Stores the style for a multi-layered map.
@@ -12,9 +12,67 @@ use std::str::FromStr;
 pub struct Style {
     pub version: u16,
     pub name: String,
+    #[serde(default)]
     pub metadata: HashMap<String, String>,
     pub sources: HashMap<String, Source>,
     pub layers: Vec<StyleLayer>,
+    /// URL template for glyphs with the place holders {fontstack} and {range}.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub glyphs: Option<String>,
+    /// Base URL of the sprite. The images and their index are stored at `{sprite}.png` and
+    /// `{sprite}.json`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sprite: Option<String>,
+}
+
+impl Style {
+    /// The max zoom of the source of `layer`. Beyond this zoom level the data of the layer is
+    /// overzoomed.
+    pub fn layer_max_zoom(&self, layer: &StyleLayer) -> Option<u8> {
+        match self.sources.get(layer.source.as_ref()?)? {
+            Source::Vector(source) | Source::Raster(source) => source.maxzoom,
+        }
+    }
+
+    /// The distinct max zooms of the sources of all layers. `None` stands for layers whose source
+    /// has no max zoom.
+    pub fn layer_max_zooms(&self) -> Vec<Option<u8>> {
+        let mut max_zooms = self
+            .layers
+            .iter()
+            .map(|layer| self.layer_max_zoom(layer))
+            .collect::<Vec<_>>();
+        max_zooms.sort_unstable();
+        max_zooms.dedup();
+        max_zooms
+    }
+
+    /// The vector source of the first layer which uses one. The tiles of the map are loaded from
+    /// this source.
+    pub fn vector_source(&self) -> Option<&VectorSource> {
+        self.layers
+            .iter()
+            .filter_map(|layer| self.sources.get(layer.source.as_ref()?))
+            .find_map(|source| match source {
+                Source::Vector(source) => Some(source),
+                Source::Raster(_) => None,
+            })
+    }
+
+    /// The source layers which are loaded from tiles at zoom level `z`. Layers whose source ends
+    /// below `z` are loaded from the ancestor at the max zoom of their source instead.
+    pub fn source_layers_at(&self, z: u8) -> HashSet<String> {
+        self.layers
+            .iter()
+            .filter(|layer| match self.layer_max_zoom(layer) {
+                Some(max_zoom) => z <= max_zoom,
+                None => true,
+            })
+            .filter_map(|layer| layer.source_layer.clone())
+            .collect()
+    }
 }
 
 impl Default for Style {
@@ -24,6 +82,8 @@ impl Default for Style {
             name: "Default Style".to_string(),
             metadata: Default::default(),
             sources: Default::default(),
+            glyphs: None,
+            sprite: None,
             layers: vec![
                 StyleLayer {
                     index: 0,
@@ -191,6 +251,55 @@ mod tests {
         }
         "##;
 
-        let _style: Style = serde_json::from_str(style_json_str).unwrap();
+        let style: Style = serde_json::from_str(style_json_str).unwrap();
+        let color = |index: usize| {
+            style.layers[index]
+                .paint
+                .as_ref()
+                .and_then(|paint| paint.get_color())
+        };
+        assert!(color(0).is_some());
+        assert!(color(1).is_some());
+        // A fill layer has no line-color
+        assert!(color(3).is_none());
+        assert_eq!(style.layers[3].typ, "fill");
+    }
+
+    #[test]
+    fn test_layer_max_zoom() {
+        assert_eq!(Style::default().layer_max_zooms(), vec![None]);
+
+        let style: Style = serde_json::from_str(
+            r##"
+        {
+          "version": 8,
+          "name": "Test Style",
+          "sources": {
+            "a": {"type": "vector", "maxzoom": 14},
+            "b": {"type": "vector", "maxzoom": 12},
+            "c": {"type": "vector"}
+          },
+          "layers": [
+            {"id": "water", "type": "fill", "source": "a", "source-layer": "water"},
+            {"id": "roads", "type": "line", "source": "b", "source-layer": "transportation"},
+            {"id": "bridges", "type": "line", "source": "b", "source-layer": "transportation"},
+            {"id": "places", "type": "fill", "source": "c", "source-layer": "place"}
+          ]
+        }
+        "##,
+        )
+        .unwrap();
+        assert_eq!(style.layer_max_zoom(&style.layers[0]), Some(14));
+        assert_eq!(style.layer_max_zoom(&style.layers[1]), Some(12));
+        assert_eq!(style.layer_max_zoom(&style.layers[3]), None);
+        assert_eq!(style.layer_max_zooms(), vec![None, Some(12), Some(14)]);
+
+        let layers = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        assert_eq!(
+            style.source_layers_at(12),
+            layers(&["water", "transportation", "place"])
+        );
+        assert_eq!(style.source_layers_at(13), layers(&["water", "place"]));
+        assert_eq!(style.source_layers_at(15), layers(&["place"]));
     }
 }
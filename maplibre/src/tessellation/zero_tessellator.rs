@@ -1,5 +1,6 @@
 //! Tessellator implementation.
 
+use geozero::error::GeozeroError;
 use geozero::{FeatureProcessor, GeomProcessor, PropertyProcessor};
 use lyon::geom;
 
@@ -9,9 +10,11 @@ use lyon::path::Path;
 use lyon::tessellation::geometry_builder::MaxIndex;
 use lyon::tessellation::{
     BuffersBuilder, FillOptions, FillRule, FillTessellator, StrokeOptions, StrokeTessellator,
+    TessellationError,
 };
 use std::cell::RefCell;
 
+use crate::error::Error;
 use crate::render::ShaderVertex;
 use crate::tessellation::{VertexConstructor, DEFAULT_TOLERANCE};
 
@@ -27,6 +30,8 @@ pub struct ZeroTessellator<I: std::ops::Add + From<lyon::tessellation::VertexId>
 
     pub feature_indices: Vec<u32>,
     current_index: usize,
+    /// The error of lyon which aborted the processing of the layer.
+    error: Option<TessellationError>,
 }
 
 impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> Default
@@ -40,6 +45,7 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> Default
             current_index: 0,
             path_open: false,
             is_point: false,
+            error: None,
         }
     }
 }
@@ -52,16 +58,29 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> ZeroTesse
         self.current_index = next_index;
     }
 
-    fn tessellate_strokes(&mut self) {
+    /// Returns the tessellation error which aborted the processing, if any.
+    pub fn take_error(&mut self) -> Option<Error> {
+        self.error.take().map(Error::Tesselation)
+    }
+
+    /// Keeps the typed error, because geozero only passes on its own error type.
+    fn check<T>(&mut self, result: Result<T, TessellationError>) -> GeoResult<()> {
+        result.map(|_| ()).map_err(|e| {
+            let geozero_error = GeozeroError::Geometry(format!("{:?}", e));
+            self.error = Some(e);
+            geozero_error
+        })
+    }
+
+    fn tessellate_strokes(&mut self) -> GeoResult<()> {
         let path_builder = self.path_builder.replace(Path::builder());
 
-        StrokeTessellator::new()
-            .tessellate_path(
-                &path_builder.build(),
-                &StrokeOptions::tolerance(DEFAULT_TOLERANCE),
-                &mut BuffersBuilder::new(&mut self.buffer, VertexConstructor {}),
-            )
-            .unwrap();
+        let result = StrokeTessellator::new().tessellate_path(
+            &path_builder.build(),
+            &StrokeOptions::tolerance(DEFAULT_TOLERANCE),
+            &mut BuffersBuilder::new(&mut self.buffer, VertexConstructor {}),
+        );
+        self.check(result)
     }
 
     fn end(&mut self, close: bool) {
@@ -71,16 +90,15 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> ZeroTesse
         }
     }
 
-    fn tessellate_fill(&mut self) {
+    fn tessellate_fill(&mut self) -> GeoResult<()> {
         let path_builder = self.path_builder.replace(Path::builder());
 
-        FillTessellator::new()
-            .tessellate_path(
-                &path_builder.build(),
-                &FillOptions::tolerance(DEFAULT_TOLERANCE).with_fill_rule(FillRule::NonZero),
-                &mut BuffersBuilder::new(&mut self.buffer, VertexConstructor {}),
-            )
-            .unwrap();
+        let result = FillTessellator::new().tessellate_path(
+            &path_builder.build(),
+            &FillOptions::tolerance(DEFAULT_TOLERANCE).with_fill_rule(FillRule::NonZero),
+            &mut BuffersBuilder::new(&mut self.buffer, VertexConstructor {}),
+        );
+        self.check(result)
     }
 }
 
@@ -138,7 +156,7 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> GeomProce
         self.end(false);
 
         if tagged {
-            self.tessellate_strokes();
+            self.tessellate_strokes()?;
         }
         Ok(())
     }
@@ -150,8 +168,7 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> GeomProce
 
     fn multilinestring_end(&mut self, _idx: usize) -> GeoResult<()> {
         // log::info!("multilinestring_end");
-        self.tessellate_strokes();
-        Ok(())
+        self.tessellate_strokes()
     }
 
     fn polygon_begin(&mut self, _tagged: bool, _size: usize, _idx: usize) -> GeoResult<()> {
@@ -164,7 +181,7 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> GeomProce
 
         self.end(true);
         if tagged {
-            self.tessellate_fill();
+            self.tessellate_fill()?;
         }
         Ok(())
     }
@@ -177,8 +194,7 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> GeomProce
     fn multipolygon_end(&mut self, _idx: usize) -> GeoResult<()> {
         // log::info!("multipolygon_end");
 
-        self.tessellate_fill();
-        Ok(())
+        self.tessellate_fill()
     }
 }
 
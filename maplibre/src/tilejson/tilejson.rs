@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// TileJSON struct that represents map metadata
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -110,7 +111,33 @@ pub struct TileJSON {
     /// value is null, implementations may use their own algorithm for
     /// determining a default location.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub center: Option<Vec<i32>>,
+    pub center: Option<Vec<f64>>,
+
+    /// The layers of vector tiles. Required by TileJSON 3.0 for vector tiles.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vector_layers: Option<Vec<VectorLayer>>,
+}
+
+/// Describes a layer of vector tiles and its attributes.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct VectorLayer {
+    /// The name of the layer in the tiles.
+    pub id: String,
+
+    /// The names of the attributes of the features in the layer, mapped to a description
+    /// of them.
+    #[serde(default)]
+    pub fields: HashMap<String, String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minzoom: Option<u8>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maxzoom: Option<u8>,
 }
 
 #[cfg(test)]
@@ -155,6 +182,7 @@ mod tests {
                 maxzoom: None,
                 bounds: None,
                 center: None,
+                vector_layers: None,
             }
         )
     }
@@ -1,5 +1,5 @@
 use cgmath::{
-    ulps_eq, BaseFloat, BaseNum, EuclideanSpace, InnerSpace, Point2, Point3, Vector3, Zero,
+    ulps_eq, BaseFloat, BaseNum, EuclideanSpace, InnerSpace, Point2, Point3, Vector2, Vector3, Zero,
 };
 use std::cmp::Ordering;
 use std::fmt;
@@ -228,6 +228,63 @@ pub(crate) fn max<S: PartialOrd + Copy>(lhs: S, rhs: S) -> S {
     }
 }
 
+/// Checks whether the convex `polygon` intersects the `aabb` by using the separating axis
+/// theorem. The vertices of the polygon have to be in order, either clockwise or
+/// counter-clockwise.
+pub fn intersects_convex_polygon_aabb2<S: BaseFloat>(
+    polygon: &[Point2<S>],
+    aabb: &Aabb2<S>,
+) -> bool {
+    if polygon.is_empty() {
+        return false;
+    }
+
+    // The axes of the AABB
+    let (min_x, max_x) = polygon
+        .iter()
+        .fold((polygon[0].x, polygon[0].x), |(min_x, max_x), point| {
+            (min(min_x, point.x), max(max_x, point.x))
+        });
+    let (min_y, max_y) = polygon
+        .iter()
+        .fold((polygon[0].y, polygon[0].y), |(min_y, max_y), point| {
+            (min(min_y, point.y), max(max_y, point.y))
+        });
+    if max_x < aabb.min.x || min_x > aabb.max.x || max_y < aabb.min.y || min_y > aabb.max.y {
+        return false;
+    }
+
+    // The normals of the edges of the polygon
+    let corners = aabb.to_corners();
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        let axis = Vector2::new(a.y - b.y, b.x - a.x);
+        if axis.is_zero() {
+            continue;
+        }
+
+        let project = |point: &Point2<S>| axis.dot(point.to_vec());
+        let (polygon_min, polygon_max) = polygon
+            .iter()
+            .map(project)
+            .fold((S::infinity(), S::neg_infinity()), |(lo, hi), v| {
+                (min(lo, v), max(hi, v))
+            });
+        let (aabb_min, aabb_max) = corners
+            .iter()
+            .map(project)
+            .fold((S::infinity(), S::neg_infinity()), |(lo, hi), v| {
+                (min(lo, v), max(hi, v))
+            });
+
+        if polygon_max < aabb_min || polygon_min > aabb_max {
+            return false;
+        }
+    }
+
+    true
+}
+
 /// A two-dimensional AABB, aka a rectangle.
 pub struct Aabb2<S> {
     /// Minimum point of the AABB
@@ -380,11 +437,49 @@ pub const fn div_floor(lhs: i32, rhs: i32) -> i32 {
 #[cfg(test)]
 mod tests {
     use crate::coords::EXTENT_SINT;
-    use crate::util::math::div_ceil;
+    use crate::util::math::{div_ceil, intersects_convex_polygon_aabb2, Aabb2};
+    use cgmath::Point2;
 
     #[test]
     pub fn test_div_floor() {
         assert_eq!(div_ceil(7000, EXTENT_SINT), 2);
         assert_eq!(div_ceil(-7000, EXTENT_SINT), -1);
     }
+
+    #[test]
+    pub fn test_intersects_convex_polygon_aabb2() {
+        // A square which is rotated by 45 degrees around (0, 0)
+        let diamond = [
+            Point2::new(0.0, -1.0),
+            Point2::new(1.0, 0.0),
+            Point2::new(0.0, 1.0),
+            Point2::new(-1.0, 0.0),
+        ];
+
+        let aabb = |min_x, min_y, max_x, max_y| {
+            Aabb2::new(Point2::new(min_x, min_y), Point2::new(max_x, max_y))
+        };
+
+        assert!(intersects_convex_polygon_aabb2(
+            &diamond,
+            &aabb(-0.1, -0.1, 0.1, 0.1)
+        ));
+        assert!(intersects_convex_polygon_aabb2(
+            &diamond,
+            &aabb(0.4, 0.4, 2.0, 2.0)
+        ));
+        // Within the bounding box of the diamond, but outside of the diamond
+        assert!(!intersects_convex_polygon_aabb2(
+            &diamond,
+            &aabb(0.6, 0.6, 2.0, 2.0)
+        ));
+        assert!(!intersects_convex_polygon_aabb2(
+            &diamond,
+            &aabb(-2.0, -2.0, -0.6, -0.6)
+        ));
+        assert!(!intersects_convex_polygon_aabb2(
+            &diamond,
+            &aabb(2.0, 2.0, 3.0, 3.0)
+        ));
+    }
 }
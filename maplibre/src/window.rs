@@ -2,15 +2,20 @@
 
 use crate::{HTTPClient, MapState, ScheduleMethod};
 
-/// Window with an optional [carte::window::WindowSize].
+/// Window with an optional [carte::window::WindowSize]. The window is not necessarily visible,
+/// for example when rendering headless.
 pub trait MapWindow {
     type EventLoop;
-    type Window: raw_window_handle::HasRawWindowHandle;
     type MapWindowConfig: MapWindowConfig<MapWindow = Self>;
 
     fn create(map_window_config: &Self::MapWindowConfig) -> Self;
 
     fn size(&self) -> WindowSize;
+}
+
+/// Window which is backed by a window of the platform. A surface can be created for it.
+pub trait HeadedMapWindow: MapWindow {
+    type Window: raw_window_handle::HasRawWindowHandle;
 
     fn inner(&self) -> &Self::Window;
 }
@@ -1,3 +1,4 @@
+use crate::platform::http_cache::CacheApiHttpCache;
 use crate::platform::http_client::WHATWGFetchHttpClient;
 use crate::platform::schedule_method::WebWorkerPoolScheduleMethod;
 
@@ -11,6 +12,9 @@ use wasm_bindgen::prelude::*;
 mod error;
 mod platform;
 
+/// The size in bytes up to which HTTP responses are cached.
+const HTTP_CACHE_SIZE: u64 = 1024 * 1024 * 256;
+
 #[cfg(not(target_arch = "wasm32"))]
 compile_error!("web works only on wasm32.");
 
@@ -56,7 +60,12 @@ pub async fn run(scheduler_ptr: *mut Scheduler<WebWorkerPoolScheduleMethod>) {
     // Either call forget or the main loop to keep worker loop alive
     MapBuilder::new()
         .with_map_window_config(WinitMapWindowConfig::new("maplibre".to_string()))
-        .with_http_client(WHATWGFetchHttpClient::new())
+        .with_http_client(
+            WHATWGFetchHttpClient::new().with_cache(CacheApiHttpCache::new(
+                "maplibre".to_string(),
+                HTTP_CACHE_SIZE,
+            )),
+        )
         .with_existing_scheduler(*scheduler)
         .build()
         .initialize()
@@ -0,0 +1,275 @@
+use async_trait::async_trait;
+use js_sys::{Array, Object, Reflect, Uint8Array};
+use maplibre::io::http_cache::{CachedResponse, HttpCache};
+use std::cmp::Ordering;
+use std::sync::Mutex;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    Cache, Headers, IdbDatabase, IdbObjectStore, IdbObjectStoreParameters, IdbRequest,
+    IdbTransactionMode, Response, ResponseInit, WorkerGlobalScope,
+};
+
+/// Header in which the expiry time of a cached response is stored.
+const EXPIRES_HEADER: &str = "x-maplibre-expires";
+/// The object store of the IndexedDB database which records the size and the last access of
+/// each cached response.
+const ENTRIES_STORE: &str = "entries";
+
+/// Resolves once `request` succeeded, with its result.
+async fn complete(request: &IdbRequest) -> Result<JsValue, JsValue> {
+    let promise = js_sys::Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+    JsFuture::from(promise).await?;
+    request.result()
+}
+
+/// The size and the last access of a cached response, as recorded in IndexedDB.
+struct Entry {
+    url: String,
+    size: u64,
+    /// Milliseconds since the Unix epoch.
+    accessed: f64,
+}
+
+impl Entry {
+    fn to_js(&self) -> Result<JsValue, JsValue> {
+        let object = Object::new();
+        Reflect::set(&object, &"url".into(), &self.url.as_str().into())?;
+        Reflect::set(&object, &"size".into(), &(self.size as f64).into())?;
+        Reflect::set(&object, &"accessed".into(), &self.accessed.into())?;
+        Ok(object.into())
+    }
+
+    fn from_js(value: &JsValue) -> Option<Self> {
+        Some(Self {
+            url: Reflect::get(value, &"url".into()).ok()?.as_string()?,
+            size: Reflect::get(value, &"size".into()).ok()?.as_f64()? as u64,
+            accessed: Reflect::get(value, &"accessed".into()).ok()?.as_f64()?,
+        })
+    }
+}
+
+/// Caches HTTP responses in the [Cache API](https://developer.mozilla.org/en-US/docs/Web/API/Cache)
+/// of the browser. If the size of the cached responses exceeds `max_size`, then the least
+/// recently used responses are evicted. The Cache API does not keep track of sizes and accesses,
+/// therefore they are recorded in an IndexedDB database of the same name.
+pub struct CacheApiHttpCache {
+    name: String,
+    max_size: u64,
+    /// The size of all cached responses in bytes, or `None` if it has not been read yet. It is
+    /// tracked such that the entries do not need to be summed up on every write.
+    size: Mutex<Option<u64>>,
+}
+
+impl CacheApiHttpCache {
+    /// name: The name of the cache within the storage of the origin.
+    /// max_size: The size in bytes up to which responses are cached.
+    pub fn new(name: String, max_size: u64) -> Self {
+        Self {
+            name,
+            max_size,
+            size: Mutex::new(None),
+        }
+    }
+
+    fn scope() -> Result<WorkerGlobalScope, JsValue> {
+        Ok(js_sys::global().dyn_into::<WorkerGlobalScope>()?)
+    }
+
+    async fn open(&self) -> Result<Cache, JsValue> {
+        JsFuture::from(Self::scope()?.caches()?.open(&self.name))
+            .await?
+            .dyn_into::<Cache>()
+    }
+
+    async fn open_database(&self) -> Result<IdbDatabase, JsValue> {
+        let factory = Self::scope()?
+            .indexed_db()?
+            .ok_or_else(|| JsValue::from_str("IndexedDB is not available"))?;
+        let request = factory.open_with_u32(&self.name, 1)?;
+
+        let upgraded = request.clone();
+        let on_upgrade_needed = Closure::once(move || -> Result<(), JsValue> {
+            let database = upgraded.result()?.dyn_into::<IdbDatabase>()?;
+            let mut parameters = IdbObjectStoreParameters::new();
+            parameters.key_path(Some(&"url".into()));
+            database.create_object_store_with_optional_parameters(ENTRIES_STORE, &parameters)?;
+            Ok(())
+        });
+        request.set_onupgradeneeded(Some(on_upgrade_needed.as_ref().unchecked_ref()));
+
+        let database = complete(&request).await?.dyn_into::<IdbDatabase>();
+        request.set_onupgradeneeded(None);
+        database
+    }
+
+    /// Every request runs in its own transaction, because transactions are committed as soon as
+    /// no requests are pending.
+    fn entries(
+        database: &IdbDatabase,
+        mode: IdbTransactionMode,
+    ) -> Result<IdbObjectStore, JsValue> {
+        database
+            .transaction_with_str_and_mode(ENTRIES_STORE, mode)?
+            .object_store(ENTRIES_STORE)
+    }
+
+    async fn record_access(&self, url: &str, size: u64) -> Result<Option<Entry>, JsValue> {
+        let database = self.open_database().await?;
+        let previous =
+            complete(&Self::entries(&database, IdbTransactionMode::Readonly)?.get(&url.into())?)
+                .await?;
+        let entry = Entry {
+            url: url.to_string(),
+            size,
+            accessed: js_sys::Date::now(),
+        };
+        complete(&Self::entries(&database, IdbTransactionMode::Readwrite)?.put(&entry.to_js()?)?)
+            .await?;
+        Ok(Entry::from_js(&previous))
+    }
+
+    /// Returns the size of all cached responses and reads it from IndexedDB if it is unknown.
+    async fn stored_size(&self, database: &IdbDatabase) -> Result<u64, JsValue> {
+        if let Some(size) = *self.size.lock().unwrap() {
+            return Ok(size);
+        }
+        let size = Self::read_entries(database)
+            .await?
+            .iter()
+            .map(|entry| entry.size)
+            .sum();
+        *self.size.lock().unwrap() = Some(size);
+        Ok(size)
+    }
+
+    async fn read_entries(database: &IdbDatabase) -> Result<Vec<Entry>, JsValue> {
+        let entries = complete(&Self::entries(database, IdbTransactionMode::Readonly)?.get_all()?)
+            .await?
+            .dyn_into::<Array>()?;
+        Ok(entries
+            .iter()
+            .filter_map(|entry| Entry::from_js(&entry))
+            .collect())
+    }
+
+    /// Evicts the least recently used responses until their size is below `max_size`. Other
+    /// workers might share the cache, therefore the size is summed up again before evicting.
+    async fn evict(&self, database: &IdbDatabase) -> Result<(), JsValue> {
+        let mut entries = Self::read_entries(database).await?;
+        entries.sort_by(|a, b| {
+            a.accessed
+                .partial_cmp(&b.accessed)
+                .unwrap_or(Ordering::Equal)
+        });
+        let mut size: u64 = entries.iter().map(|entry| entry.size).sum();
+
+        let cache = self.open().await?;
+        for entry in entries {
+            if size <= self.max_size {
+                break;
+            }
+            JsFuture::from(cache.delete_with_str(&entry.url)).await?;
+            complete(
+                &Self::entries(database, IdbTransactionMode::Readwrite)?
+                    .delete(&entry.url.as_str().into())?,
+            )
+            .await?;
+            size -= entry.size;
+        }
+
+        *self.size.lock().unwrap() = Some(size);
+        Ok(())
+    }
+
+    async fn try_get(&self, url: &str) -> Result<Option<CachedResponse>, JsValue> {
+        let cache = self.open().await?;
+        let maybe_response = JsFuture::from(cache.match_with_str(url)).await?;
+        if maybe_response.is_undefined() {
+            return Ok(None);
+        }
+
+        let response = maybe_response.dyn_into::<Response>()?;
+        let headers = response.headers();
+        let expires = headers
+            .get(EXPIRES_HEADER)?
+            .and_then(|expires| expires.parse().ok())
+            .unwrap_or(0);
+        let etag = headers.get("etag")?;
+        let last_modified = headers.get("last-modified")?;
+        let array_buffer = JsFuture::from(response.array_buffer()?).await?;
+        let data = Uint8Array::new(&array_buffer).to_vec();
+
+        // A failure to record the access only affects the order of eviction
+        if let Err(e) = self.record_access(url, data.len() as u64).await {
+            log::warn!("recording the access of {} failed: {:?}", url, e);
+        }
+
+        Ok(Some(CachedResponse {
+            data,
+            etag,
+            last_modified,
+            expires,
+        }))
+    }
+
+    async fn try_put(&self, url: &str, response: CachedResponse) -> Result<(), JsValue> {
+        let size = response.data.len() as u64;
+        if size > self.max_size {
+            return Ok(());
+        }
+
+        let headers = Headers::new()?;
+        headers.set(EXPIRES_HEADER, &response.expires.to_string())?;
+        if let Some(etag) = &response.etag {
+            headers.set("etag", etag)?;
+        }
+        if let Some(last_modified) = &response.last_modified {
+            headers.set("last-modified", last_modified)?;
+        }
+
+        let mut init = ResponseInit::new();
+        init.headers(&headers);
+        let mut data = response.data;
+        let response = Response::new_with_opt_u8_array_and_init(Some(data.as_mut_slice()), &init)?;
+
+        let database = self.open_database().await?;
+        let stored_size = self.stored_size(&database).await?;
+
+        let cache = self.open().await?;
+        JsFuture::from(cache.put_with_str(url, &response)).await?;
+        let previous = self.record_access(url, size).await?;
+
+        let size = (stored_size + size)
+            .saturating_sub(previous.map(|previous| previous.size).unwrap_or(0));
+        *self.size.lock().unwrap() = Some(size);
+        if size > self.max_size {
+            self.evict(&database).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl HttpCache for CacheApiHttpCache {
+    async fn get(&self, url: &str) -> Option<CachedResponse> {
+        match self.try_get(url).await {
+            Ok(response) => response,
+            Err(e) => {
+                log::warn!("reading {} from the HTTP cache failed: {:?}", url, e);
+                None
+            }
+        }
+    }
+
+    async fn put(&self, url: &str, response: CachedResponse) {
+        // Fails for example if the storage quota is exceeded
+        if let Err(e) = self.try_put(url, response).await {
+            log::warn!("writing {} to the HTTP cache failed: {:?}", url, e);
+        }
+    }
+}
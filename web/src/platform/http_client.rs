@@ -1,28 +1,82 @@
 use js_sys::{ArrayBuffer, Uint8Array};
-use maplibre::io::source_client::HTTPClient;
+use maplibre::io::http_cache::{CachePolicy, CachedResponse, HttpCache};
+use maplibre::io::source_client::{
+    Credentials, HTTPClient, Request as ResourceRequest, ResourceKind, TransformRequest,
+};
+use std::sync::Arc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::JsFuture;
 
-use web_sys::{Request, RequestInit, Response, WorkerGlobalScope};
+use web_sys::{Headers, Request, RequestCredentials, RequestInit, Response, WorkerGlobalScope};
 
 use crate::error::WebError;
 use async_trait::async_trait;
 
 use maplibre::error::Error;
 
-pub struct WHATWGFetchHttpClient {}
+/// The HTTP status code of a successful conditional request.
+const NOT_MODIFIED: u16 = 304;
+/// HTTP status codes which mean that the resource does not exist, or that it is empty in case of
+/// `204 No Content`.
+const NOT_FOUND: [u16; 3] = [204, 404, 410];
+/// HTTP status codes of server errors, on which a stale cached response is used instead.
+const SERVER_ERRORS: std::ops::Range<u16> = 500..600;
+
+pub struct WHATWGFetchHttpClient {
+    cache: Option<Arc<dyn HttpCache>>,
+    transform_request: Option<Arc<TransformRequest>>,
+}
 
 impl WHATWGFetchHttpClient {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            cache: None,
+            transform_request: None,
+        }
+    }
+
+    /// Caches responses in `cache`, for example a
+    /// [`crate::platform::http_cache::CacheApiHttpCache`].
+    pub fn with_cache<C: HttpCache>(mut self, cache: C) -> Self {
+        self.cache = Some(Arc::new(cache));
+        self
     }
 
-    async fn fetch_array_buffer(url: &str) -> Result<JsValue, JsValue> {
+    async fn fetch_response(
+        &self,
+        url: &str,
+        kind: ResourceKind,
+        cached: Option<&CachedResponse>,
+    ) -> Result<Response, JsValue> {
+        let resource_request = match &self.transform_request {
+            Some(transform_request) => transform_request(url, kind),
+            None => ResourceRequest::new(url),
+        };
+
         let mut opts = RequestInit::new();
         opts.method("GET");
-
-        let request = Request::new_with_str_and_init(url, &opts)?;
+        opts.credentials(match resource_request.credentials {
+            Credentials::Omit => RequestCredentials::Omit,
+            Credentials::SameOrigin => RequestCredentials::SameOrigin,
+            Credentials::Include => RequestCredentials::Include,
+        });
+
+        let headers = Headers::new()?;
+        for (name, value) in &resource_request.headers {
+            headers.set(name, value)?;
+        }
+        if let Some(cached) = cached {
+            if let Some(etag) = &cached.etag {
+                headers.set("If-None-Match", etag)?;
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                headers.set("If-Modified-Since", last_modified)?;
+            }
+        }
+        opts.headers(&headers);
+
+        let request = Request::new_with_str_and_init(&resource_request.url, &opts)?;
 
         // Get the global scope
         let global = js_sys::global();
@@ -33,14 +87,12 @@ impl WHATWGFetchHttpClient {
         let maybe_response = JsFuture::from(scope.fetch_with_request(&request)).await?;
         assert!(maybe_response.is_instance_of::<Response>());
         let response: Response = maybe_response.dyn_into().unwrap();
+        Ok(response)
+    }
 
+    async fn read_bytes(response: &Response) -> Result<Vec<u8>, JsValue> {
         // Get ArrayBuffer
         let maybe_array_buffer = JsFuture::from(response.array_buffer()?).await?;
-        Ok(maybe_array_buffer)
-    }
-
-    async fn fetch_bytes(&self, url: &str) -> Result<Vec<u8>, WebError> {
-        let maybe_array_buffer = Self::fetch_array_buffer(url).await?;
 
         assert!(maybe_array_buffer.is_instance_of::<ArrayBuffer>());
         let array_buffer: ArrayBuffer = maybe_array_buffer.dyn_into().unwrap();
@@ -52,19 +104,147 @@ impl WHATWGFetchHttpClient {
 
         Ok(output)
     }
+
+    fn cache_policy(response: &Response, now: u64) -> Result<CachePolicy, JsValue> {
+        let headers = response.headers();
+        let expires = headers
+            .get("expires")?
+            .map(|expires| js_sys::Date::parse(&expires))
+            .filter(|expires| expires.is_finite())
+            .map(|expires| (expires / 1000.0).max(0.0) as u64);
+        Ok(CachePolicy::from_headers(
+            headers.get("cache-control")?.as_deref(),
+            expires,
+            now,
+        ))
+    }
+
+    async fn fetch_bytes(&self, url: &str, kind: ResourceKind) -> Result<Vec<u8>, Error> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => {
+                let response = self
+                    .fetch_response(url, kind, None)
+                    .await
+                    .map_err(network_error)?;
+                return Self::read_response(&response).await;
+            }
+        };
+
+        let now = (js_sys::Date::now() / 1000.0) as u64;
+        let cached = cache.get(url).await;
+        if let Some(cached) = &cached {
+            if cached.is_fresh(now) {
+                return Ok(cached.data.clone());
+            }
+        }
+
+        let response = match self.fetch_response(url, kind, cached.as_ref()).await {
+            Ok(response) => response,
+            Err(e) => {
+                // Stale data is better than no data, for example if the device is offline
+                return match cached {
+                    Some(cached) => {
+                        log::warn!("using stale cached response for {}: {:?}", url, e);
+                        Ok(cached.data)
+                    }
+                    None => Err(network_error(e)),
+                };
+            }
+        };
+
+        if SERVER_ERRORS.contains(&response.status()) {
+            if let Some(cached) = cached {
+                log::warn!(
+                    "using stale cached response for {}: {}",
+                    url,
+                    response.status()
+                );
+                return Ok(cached.data);
+            }
+        }
+
+        let policy = Self::cache_policy(&response, now).map_err(network_error)?;
+
+        if response.status() == NOT_MODIFIED {
+            if let Some(cached) = cached {
+                let data = cached.data.clone();
+                if policy.storable {
+                    cache
+                        .put(
+                            url,
+                            CachedResponse {
+                                expires: policy.expires,
+                                ..cached
+                            },
+                        )
+                        .await;
+                }
+                return Ok(data);
+            }
+        }
+
+        let data = Self::read_response(&response).await?;
+        if policy.storable {
+            let headers = response.headers();
+            cache
+                .put(
+                    url,
+                    CachedResponse {
+                        data: data.clone(),
+                        etag: headers.get("etag").map_err(network_error)?,
+                        last_modified: headers.get("last-modified").map_err(network_error)?,
+                        expires: policy.expires,
+                    },
+                )
+                .await;
+        }
+        Ok(data)
+    }
+
+    /// Reads the body of a successful response. Failed responses are mapped to errors by their
+    /// status, see [`Error::from_status`].
+    async fn read_response(response: &Response) -> Result<Vec<u8>, Error> {
+        if NOT_FOUND.contains(&response.status()) {
+            return Err(Error::NotFound(response.url()));
+        }
+        if !response.ok() {
+            return Err(Error::from_status(
+                response.status(),
+                format!(
+                    "request to {} failed with status {}",
+                    response.url(),
+                    response.status()
+                ),
+            ));
+        }
+        Self::read_bytes(response).await.map_err(network_error)
+    }
+}
+
+/// Maps an exception of the Fetch API, for example if the device is offline, to
+/// [`Error::Network`].
+fn network_error(e: JsValue) -> Error {
+    Error::Network(WebError::from(e).0)
 }
 
 impl Clone for WHATWGFetchHttpClient {
     fn clone(&self) -> Self {
-        WHATWGFetchHttpClient {}
+        WHATWGFetchHttpClient {
+            cache: self.cache.clone(),
+            transform_request: self.transform_request.clone(),
+        }
     }
 }
 
 #[async_trait(?Send)]
 impl HTTPClient for WHATWGFetchHttpClient {
-    async fn fetch(&self, url: &str) -> Result<Vec<u8>, Error> {
-        self.fetch_bytes(url)
-            .await
-            .map_err(|WebError(msg)| Error::Network(msg))
+    async fn fetch(&self, url: &str, kind: ResourceKind) -> Result<Vec<u8>, Error> {
+        self.fetch_bytes(url, kind).await
+    }
+
+    fn with_transform_request(mut self, transform_request: Arc<TransformRequest>) -> Self {
+        self.transform_request = Some(transform_request);
+        self
     }
 }
@@ -1,3 +1,4 @@
+pub mod http_cache;
 pub mod http_client;
 pub mod legacy_webworker_fetcher;
 pub mod pool;